@@ -3,9 +3,30 @@ use std::{env, path::PathBuf};
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
 
+    // The descriptors let grpcurl discover the control service through reflection
     tonic_prost_build::configure()
-        .file_descriptor_set_path(out_dir.join("indicator_descriptor.bin"))
-        .compile_protos(&["proto/indicators.proto", "proto/depot.proto"], &["proto"])?;
+        .file_descriptor_set_path(out_dir.join("trader_descriptor.bin"))
+        .compile_protos(
+            &[
+                "proto/indicators.proto",
+                "proto/depot.proto",
+                "proto/control.proto",
+            ],
+            &["proto"],
+        )?;
+
+    // Stored with every run result, left out when not built from a checkout
+    let revision = std::process::Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+    if let Some(revision) = revision {
+        println!("cargo:rustc-env=GIT_REVISION={}", revision.trim());
+    }
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
 
     Ok(())
 }
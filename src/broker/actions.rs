@@ -4,7 +4,8 @@ use apca::{
     ApiInfo, Client,
 };
 use depot::{
-    depot_client::DepotClient, BuyRequest, DepositRequest, Empty, SellRequest, StockRequest,
+    depot_client::DepotClient, BuyRequest, DepositRequest, Empty, SellRequest, StateResponse,
+    StockRequest, Transaction, TransactionResponse, WithdrawRequest,
 };
 use tonic::transport::Channel;
 use tracing::{error, info};
@@ -22,34 +23,48 @@ pub struct Alpaca {
 }
 
 impl Alpaca {
-    pub async fn new() -> Self {
-        let conf = settings::Settings::new().unwrap();
+    /// Connect to the depot of the settings
+    pub async fn new() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let conf = settings::Settings::new()?;
         let depot_url = conf.depot_url.clone();
         let api_base = conf.api_base_url.clone();
-        let api_key = conf.api_key_id.clone();
-        let api_secret = conf.api_secret_key.clone();
-        let client = DepotClient::connect(depot_url).await.unwrap();
+        // Only live trading uses the account, everything else works without keys
+        let api_key = conf
+            .api_key_id
+            .as_ref()
+            .map_or("", settings::Secret::expose);
+        let api_secret = conf
+            .api_secret_key
+            .as_ref()
+            .map_or("", settings::Secret::expose);
+        let client = DepotClient::connect(depot_url).await?;
 
-        let api_info = ApiInfo::from_parts(api_base, api_key, api_secret).unwrap();
+        let api_info = ApiInfo::from_parts(api_base, api_key, api_secret)?;
         let account = Client::new(api_info);
-        Self {
+        Ok(Self {
             client,
             account: std::sync::Arc::new(account),
-        }
+        })
     }
-    pub async fn buy(&mut self, req: BuyRequest) {
+    pub async fn buy(&mut self, req: BuyRequest) -> Option<TransactionResponse> {
         let c = &mut self.client;
 
         match c.buy_shares(req).await {
-            Ok(res) => _ = res.into_inner(), //info!("Buy Response: {:?}", res.into_inner()),
-            Err(e) => error!("Buy RPC error: {:?}", e),
+            Ok(res) => Some(res.into_inner()), //info!("Buy Response: {:?}", res.into_inner()),
+            Err(e) => {
+                error!("Buy RPC error: {:?}", e);
+                None
+            }
         }
     }
-    pub async fn sell(&mut self, req: SellRequest) {
+    pub async fn sell(&mut self, req: SellRequest) -> Option<TransactionResponse> {
         let c = &mut self.client;
         match c.sell_shares(req).await {
-            Ok(res) => _ = res.into_inner(), //info!("Sell Response: {:?}", res.into_inner()),
-            Err(e) => error!("Sell RPC error: {:?}", e),
+            Ok(res) => Some(res.into_inner()), //info!("Sell Response: {:?}", res.into_inner()),
+            Err(e) => {
+                error!("Sell RPC error: {:?}", e);
+                None
+            }
         }
     }
     pub async fn get_position(&mut self, symbol: &str) -> Option<i32> {
@@ -98,6 +113,15 @@ impl Alpaca {
         }
     }
 
+    pub async fn withdraw(&mut self, amount: f64) {
+        let c = &mut self.client;
+        let req = WithdrawRequest { amount };
+        match c.withdraw(req).await {
+            Ok(_) => info!("Withdrawal of {} successful", amount),
+            Err(e) => error!("Withdraw RPC error: {:?}", e),
+        }
+    }
+
     pub async fn get_gain(&mut self) -> Option<f64> {
         let c = &mut self.client;
         let req = Empty {};
@@ -146,6 +170,32 @@ impl Alpaca {
         }
     }
 
+    pub async fn get_state(&mut self) -> Option<StateResponse> {
+        let c = &mut self.client;
+        let req = Empty {};
+        match c.get_state(req).await {
+            Ok(res) => Some(res.into_inner()),
+            Err(e) => {
+                error!("Get state RPC error: {:?}", e);
+                None
+            }
+        }
+    }
+
+    pub async fn get_transactions(&mut self, symbol: &str) -> Option<Vec<Transaction>> {
+        let c = &mut self.client;
+        let req = StockRequest {
+            symbol: symbol.to_string(),
+        };
+        match c.get_transactions(req).await {
+            Ok(res) => Some(res.into_inner().transactions),
+            Err(e) => {
+                error!("Get transactions RPC error: {:?}", e);
+                None
+            }
+        }
+    }
+
     pub async fn eval_bar(&mut self, _b: Bar) {}
     pub async fn eval_trade(&mut self, _t: Vec<Trade>) {}
 }
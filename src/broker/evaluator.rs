@@ -1,61 +1,99 @@
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use apca::data::v2::stream::Trade;
+use apca::data::v2::stream::{Bar, Trade};
+use chrono::{DateTime, Duration, Utc};
 use rand::Rng;
 use tonic::transport::{Channel, Error};
-use tracing::info;
+use tonic::Status;
+use tracing::{error, info};
 use trader_bot::indicator_client::init::calculate::{
-    indicator_client::IndicatorClient, IndicatorType, ListNumbersRequest2, Opt,
+    indicator_client::IndicatorClient, IndicatorType,
 };
 
 use crate::{
     broker::actions::Alpaca,
+    broker::events::{EventKind, Events},
+    broker::execution::{
+        self, ExecutionSettings, Nbbo, OrderPrice, QuoteBook, Refusal, Side, TradeRecord,
+    },
+    broker::indicator_config::{IndicatorConfig, SMAConfig},
+    broker::indicators,
     broker::position_sizing::PositionSizer,
-    depot::{BuyRequest, SellRequest},
-    pattern::cross_gc_dc,
-    settings::Settings,
-    wrangling::buffers::Buffer,
+    broker::reload::{Change, LiveParams},
+    broker::snapshot::{LiveState, SymbolState},
+    broker::strategy::{
+        BreakoutConfig, EnsembleConfig, MeanReversionConfig, PairsConfig, StrategyConfig,
+        StrategyKind,
+    },
+    depot::{BuyRequest, SellRequest, StateResponse, TransactionResponse},
+    error::CLIError,
+    mocking::layout::CsvRow,
+    mocking::source::{parse_bar_size, Resampler},
+    pattern::breakout,
+    pattern::mean_reversion::{self, Bands, Thresholds},
+    pattern::oscillators,
+    pattern::pairs::{self, PairSignal},
+    pattern::signal::{combine, Signal},
+    pattern::trend,
+    settings::{Settings, TrendFilterSettings},
+    wrangling::buffers::{aligned_closes, closes, Buffer, Timeframes, BUFFER_SIZE},
+    wrangling::calendar::{Session, TradingCalendar},
 };
 
+/// Bar time after which the depot is asked for cash and shares again, orders
+/// placed by others are only seen then
+pub const HOLDINGS_REFRESH_MINUTES: i64 = 15;
+
 pub struct Evaluator {
     pub ap: Alpaca,
     pub indicator_client: IndicatorClient<Channel>,
     pub buffer: HashMap<String, Buffer>,
     pub eval_config: Option<EvalConfig>,
-    pub best_eval_config: HashMap<i32, IndicatorConfig>,
+    pub best_eval_config: HashMap<i32, StrategyConfig>,
     pub position_sizer: PositionSizer,
+    /// Symbols `(a, b)` traded by the pairs strategy
+    pub pair: Option<(String, String)>,
+    pub calendar: TradingCalendar,
+    /// Trade pre-market and after-hours bars
+    pub extended_hours: bool,
+    /// Minutes before the regular close from which positions are closed
+    pub flatten_before_close: Option<i64>,
+    /// Closed higher-timeframe bars per symbol
+    pub timeframes: HashMap<String, Timeframes>,
+    /// Names and bar sizes of the timeframes in `timeframes`
+    pub higher_timeframes: Vec<(String, Duration)>,
+    /// Bar size strategies act on, the base bars are buffered if not set
+    pub entry_timeframe: Option<Duration>,
+    /// Entry bars in progress per symbol
+    pub entry_bars: HashMap<String, Resampler>,
+    /// Timestamp of the last base bar per symbol
+    pub last_bars: HashMap<String, DateTime<Utc>>,
+    pub trend_filter: Option<TrendFilterSettings>,
+    /// Latest bid and ask per symbol, orders are priced against it
+    pub quotes: QuoteBook,
+    pub execution: ExecutionSettings,
+    /// Orders placed in the current run
+    pub trades: Vec<TradeRecord>,
+    /// Latest close per symbol, positions are valued at it
+    pub last_prices: HashMap<String, f64>,
+    /// Portfolio value at the close of every bar of the current run
+    pub equity: Vec<(DateTime<Utc>, f64)>,
+    /// Most recent trades and equity points kept, all of them if not set
+    pub max_history: Option<usize>,
+    /// Depot cash and shares the equity is valued with, and the bar time they
+    /// were fetched at. Cleared whenever they change.
+    pub holdings: Option<(DateTime<Utc>, StateResponse)>,
+    /// Symbols whose signals are not traded, their bars are still buffered
+    pub paused: HashSet<String>,
+    /// Latest signal and bar time per symbol, holds included
+    pub signals: HashMap<String, (DateTime<Utc>, Signal)>,
+    /// Signals, orders, fills and risk breaches for the control API
+    pub events: Events,
 }
 
 pub struct EvalConfig {
     pub cash: f64,
-    pub indicator_config: IndicatorConfig,
-}
-
-#[derive(Debug, Clone)]
-pub struct IndicatorConfig {
-    pub sma: Option<SMAConfig>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SMAConfig {
-    pub long_range: i32,
-    pub short_range: i32,
-}
-
-impl IndicatorConfig {
-    pub fn random() -> Self {
-        let mut rng = rand::rng();
-        let long_range = rng.random_range(10..=50);
-        let short_range = rng.random_range(2..=long_range - 5); // Ensure short < long
-
-        IndicatorConfig {
-            sma: Some(SMAConfig {
-                long_range,
-                short_range,
-            }),
-        }
-    }
+    pub strategy: StrategyConfig,
 }
 
 fn gen_sell() -> (f64, i32) {
@@ -73,30 +111,411 @@ fn gen_buy() -> (f64, i32) {
 }
 
 impl Evaluator {
-    pub async fn new() -> Self {
-        let settings = Settings::new().unwrap();
-        let _depot_url = settings.depot_url.clone();
+    pub async fn new() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let settings = Settings::new()?;
         let indicator_url = settings.indicator_url.clone();
-        let ap = Alpaca::new().await;
+        let ap = Alpaca::new().await?;
 
-        let indicator_client = IndicatorClient::connect(indicator_url.to_string())
-            .await
-            .unwrap();
+        let indicator_client = IndicatorClient::connect(indicator_url.to_string()).await?;
+        Ok(Self::with_clients(&settings, ap, indicator_client))
+    }
+
+    /// Evaluator of `settings` on the depot `ap`, connected to the Indicator
+    /// service only if `strategy` uses it
+    pub async fn with_depot(
+        settings: &Settings,
+        ap: Alpaca,
+        strategy: StrategyKind,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let indicator_url = settings.indicator_url.clone();
+        let indicator_client = if strategy.uses_indicators() {
+            IndicatorClient::connect(indicator_url).await?
+        } else {
+            // Never called, so the channel never connects
+            IndicatorClient::new(Channel::from_shared(indicator_url)?.connect_lazy())
+        };
+        Ok(Self::with_clients(settings, ap, indicator_client))
+    }
 
+    /// Evaluator of `settings` on already connected services
+    pub fn with_clients(
+        settings: &Settings,
+        ap: Alpaca,
+        indicator_client: IndicatorClient<Channel>,
+    ) -> Self {
         let position_sizer =
             PositionSizer::new(settings.max_trade_percent, settings.max_position_percent);
+        let calendar = settings.trading_calendar().unwrap_or_else(|e| {
+            error!(
+                "Failed to load trading calendar, using NYSE weekdays: {:?}",
+                e
+            );
+            TradingCalendar::nyse()
+        });
+
+        let mut names = settings.timeframes.clone();
+        if let Some(filter) = &settings.trend_filter {
+            if !names.contains(&filter.timeframe) {
+                names.push(filter.timeframe.clone());
+            }
+        }
+        let higher_timeframes = names
+            .into_iter()
+            .filter_map(|name| match parse_bar_size(&name) {
+                Some(size) => Some((name, size)),
+                None => {
+                    error!("Ignoring invalid timeframe {}", name);
+                    None
+                }
+            })
+            .collect();
+
+        let entry_timeframe =
+            settings
+                .entry_timeframe
+                .as_ref()
+                .and_then(|name| match parse_bar_size(name) {
+                    Some(size) => Some(size),
+                    None => {
+                        error!("Ignoring invalid entry timeframe {}", name);
+                        None
+                    }
+                });
 
         Self {
             ap,
             indicator_client,
             buffer: HashMap::new(),
-            eval_config: None,
+            // Live trading takes its cash from the depot, it only matters to runs
+            eval_config: settings.live_strategy.clone().map(|strategy| EvalConfig {
+                cash: 0.0,
+                strategy,
+            }),
             best_eval_config: HashMap::new(),
             position_sizer,
+            pair: settings
+                .pair
+                .as_ref()
+                .map(|p| (p.symbol_a.clone(), p.symbol_b.clone())),
+            calendar,
+            extended_hours: settings.calendar.extended_hours,
+            flatten_before_close: settings.calendar.flatten_before_close,
+            timeframes: HashMap::new(),
+            higher_timeframes,
+            entry_timeframe,
+            entry_bars: HashMap::new(),
+            last_bars: HashMap::new(),
+            trend_filter: settings.trend_filter.clone(),
+            quotes: QuoteBook::new(),
+            execution: settings.execution.clone(),
+            trades: vec![],
+            last_prices: HashMap::new(),
+            equity: vec![],
+            max_history: None,
+            holdings: None,
+            paused: HashSet::new(),
+            signals: HashMap::new(),
+            events: Events::new(),
+        }
+    }
+
+    /// Aggregate a base bar into the higher timeframes of its symbol
+    pub fn add_to_timeframes(&mut self, bar: &Bar) {
+        if self.higher_timeframes.is_empty() {
+            return;
+        }
+        let timeframes = self
+            .timeframes
+            .entry(bar.symbol.clone())
+            .or_insert_with(|| {
+                Timeframes::new(
+                    &bar.symbol,
+                    &self.higher_timeframes,
+                    BUFFER_SIZE,
+                    &self.calendar,
+                )
+            });
+        if let Err(e) = timeframes.add_bar(bar) {
+            error!("Failed to aggregate {} bar: {:?}", bar.symbol, e);
+        }
+    }
+
+    /// Bar to buffer and evaluate for a base bar, if any
+    ///
+    /// Without an entry timeframe that is the base bar itself. Otherwise base
+    /// bars are aggregated like higher timeframes and a bar is returned once
+    /// its period has closed.
+    pub fn entry_bar(&mut self, bar: &Bar) -> Option<Bar> {
+        self.last_bars.insert(bar.symbol.clone(), bar.timestamp);
+        let Some(size) = self.entry_timeframe else {
+            return Some(bar.clone());
+        };
+        let calendar = &self.calendar;
+        let resampler = self
+            .entry_bars
+            .entry(bar.symbol.clone())
+            .or_insert_with(|| Resampler::new(size).with_calendar(calendar.clone()));
+        let closed = CsvRow::from_bar(bar)
+            .and_then(|row| resampler.push(row).map(|row| row.to_bar()).transpose());
+        match closed {
+            Ok(closed) => closed,
+            Err(e) => {
+                error!("Failed to aggregate {} entry bar: {:?}", bar.symbol, e);
+                None
+            }
+        }
+    }
+
+    /// Strategy and position limits currently traded with
+    pub fn live_params(&self) -> LiveParams {
+        LiveParams {
+            live_strategy: self
+                .eval_config
+                .as_ref()
+                .map(|config| config.strategy.clone()),
+            max_trade_percent: self.position_sizer.max_trade_percent,
+            max_position_percent: self.position_sizer.max_position_percent,
+        }
+    }
+
+    /// Swap in a new strategy and position limits, logging every changed value
+    ///
+    /// Invalid parameters are rejected as a whole and the current ones kept.
+    /// Buffers and positions stay as they are, the next bar is evaluated with
+    /// the new parameters. Without a strategy the current one is kept, the
+    /// default strategy included.
+    pub fn apply(&mut self, mut params: LiveParams) -> Result<Vec<Change>, CLIError> {
+        let current = self.live_params();
+        if params.live_strategy.is_none() {
+            params.live_strategy = current.live_strategy.clone();
+        }
+        params.validate()?;
+        let changes = current.changes(&params);
+        for change in &changes {
+            info!("Changed {}", change);
+        }
+
+        self.position_sizer = params.position_sizer();
+        if let Some(strategy) = params.live_strategy {
+            match &mut self.eval_config {
+                Some(config) => config.strategy = strategy,
+                None => {
+                    self.eval_config = Some(EvalConfig {
+                        cash: 0.0,
+                        strategy,
+                    })
+                }
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Buffers, strategy and the last `history` trades and equity points to
+    /// continue from after a restart
+    pub fn snapshot(&self, now: DateTime<Utc>, history: usize) -> Result<LiveState, CLIError> {
+        let mut symbols = vec![];
+        for (symbol, buffer) in &self.buffer {
+            let timeframes = match self.timeframes.get(symbol) {
+                Some(timeframes) => timeframes.state()?,
+                None => vec![],
+            };
+            let entry = self
+                .entry_bars
+                .get(symbol)
+                .and_then(|resampler| resampler.open_bucket(symbol))
+                .map(CsvRow::to_bar)
+                .transpose()?;
+            symbols.push(SymbolState {
+                symbol: symbol.clone(),
+                bars: buffer.get_bars().cloned().unwrap_or_default(),
+                entry,
+                last_bar_at: self.last_bars.get(symbol).copied(),
+                timeframes,
+                last_price: self.last_prices.get(symbol).copied(),
+            });
+        }
+        Ok(LiveState {
+            taken_at: now,
+            strategy: self.eval_config.as_ref().map(|c| c.strategy.clone()),
+            cash: self.eval_config.as_ref().map(|c| c.cash),
+            symbols,
+            trades: self.trades[self.trades.len().saturating_sub(history)..].to_vec(),
+            equity: self.equity[self.equity.len().saturating_sub(history)..].to_vec(),
+            paused: {
+                let mut paused: Vec<String> = self.paused.iter().cloned().collect();
+                paused.sort();
+                paused
+            },
+        })
+    }
+
+    /// Continue from a snapshot, a configured strategy is kept
+    pub fn restore(&mut self, state: LiveState) -> Result<(), CLIError> {
+        if self.eval_config.is_none() {
+            if let (Some(strategy), Some(cash)) = (state.strategy, state.cash) {
+                self.eval_config = Some(EvalConfig { cash, strategy });
+            }
+        }
+        for symbol_state in state.symbols {
+            let symbol = symbol_state.symbol;
+            let mut buffer = Buffer::new(symbol.clone(), BUFFER_SIZE);
+            for bar in symbol_state.bars {
+                buffer.add_bar(bar);
+            }
+            self.buffer.insert(symbol.clone(), buffer);
+
+            if let (Some(size), Some(entry)) = (self.entry_timeframe, symbol_state.entry) {
+                let mut resampler = Resampler::new(size).with_calendar(self.calendar.clone());
+                resampler.resume(CsvRow::from_bar(&entry)?);
+                self.entry_bars.insert(symbol.clone(), resampler);
+            }
+            if !self.higher_timeframes.is_empty() {
+                let mut timeframes = Timeframes::new(
+                    &symbol,
+                    &self.higher_timeframes,
+                    BUFFER_SIZE,
+                    &self.calendar,
+                );
+                timeframes.restore(symbol_state.timeframes)?;
+                self.timeframes.insert(symbol.clone(), timeframes);
+            }
+            if let Some(last) = symbol_state.last_bar_at {
+                self.last_bars.insert(symbol.clone(), last);
+            }
+            if let Some(price) = symbol_state.last_price {
+                self.last_prices.insert(symbol, price);
+            }
+        }
+        self.trades = state.trades;
+        self.equity = state.equity;
+        self.paused = state.paused.into_iter().collect();
+        Ok(())
+    }
+
+    /// Buffer historical bars without trading on them
+    ///
+    /// Fills the gap between a stale snapshot and the live stream.
+    pub fn backfill(&mut self, rows: Vec<CsvRow>) -> Result<(), CLIError> {
+        for row in rows {
+            if !self.in_session(row.timestamp) {
+                continue;
+            }
+            let bar = row.to_bar()?;
+            let newer = self
+                .last_bars
+                .get(&row.symbol)
+                .copied()
+                .or_else(|| Some(self.buffer.get(&row.symbol)?.get_bars()?.last()?.timestamp))
+                .is_none_or(|last| last < bar.timestamp);
+            if !newer {
+                continue;
+            }
+            if let Some(entry) = self.entry_bar(&bar) {
+                self.buffer
+                    .entry(row.symbol.clone())
+                    .or_insert_with(|| Buffer::new(row.symbol.clone(), BUFFER_SIZE))
+                    .add_bar(entry);
+            }
+            self.add_to_timeframes(&bar);
+            self.last_prices.insert(row.symbol, row.close);
+        }
+        Ok(())
+    }
+
+    /// Value the portfolio at the closes of `bar` and earlier bars
+    ///
+    /// Bars of several symbols with the same timestamp add a single point.
+    /// The depot is only asked for cash and shares after they changed, or
+    /// once `HOLDINGS_REFRESH_MINUTES` of bars have passed.
+    pub async fn record_equity(&mut self, bar: &Bar) {
+        let close = bar.close_price.to_f64().unwrap_or(0.0);
+        self.last_prices.insert(bar.symbol.clone(), close);
+        let refresh = self.holdings.as_ref().is_none_or(|(fetched, _)| {
+            let age = bar.timestamp - *fetched;
+            age < Duration::zero() || age >= Duration::minutes(HOLDINGS_REFRESH_MINUTES)
+        });
+        if refresh {
+            let Some(state) = self.ap.get_state().await else {
+                return;
+            };
+            self.holdings = Some((bar.timestamp, state));
+        }
+        let Some((_, state)) = &self.holdings else {
+            return;
+        };
+
+        let positions: f64 = state
+            .shares
+            .iter()
+            .map(|share| {
+                let price = self
+                    .last_prices
+                    .get(&share.symbol)
+                    .copied()
+                    .unwrap_or(share.price_per_share);
+                share.count as f64 * price
+            })
+            .sum();
+        let point = (bar.timestamp, state.cash + positions);
+        match self.equity.last_mut() {
+            Some(last) if last.0 == bar.timestamp => *last = point,
+            _ => self.equity.push(point),
+        }
+        self.trim_history();
+    }
+
+    /// Drop the oldest trades and equity points beyond `max_history`
+    fn trim_history(&mut self) {
+        let Some(max) = self.max_history else {
+            return;
+        };
+        if self.trades.len() > max {
+            self.trades.drain(..self.trades.len() - max);
+        }
+        if self.equity.len() > max {
+            self.equity.drain(..self.equity.len() - max);
+        }
+    }
+
+    /// Whether a bar at `timestamp` is traded at all
+    ///
+    /// Daily bars always are, intraday bars only in the regular session
+    /// unless extended hours are enabled.
+    pub fn in_session(&self, timestamp: DateTime<Utc>) -> bool {
+        if self.calendar.is_daily(timestamp) {
+            return true;
         }
+        match self.calendar.session(timestamp) {
+            Session::Regular => true,
+            Session::PreMarket | Session::AfterHours => self.extended_hours,
+            Session::Closed => false,
+        }
+    }
+
+    /// Close the position in `symbol` if the regular close is near
+    ///
+    /// Returns true while in the flattening window, no new positions should
+    /// be opened then.
+    pub async fn flatten_before_close(
+        &mut self,
+        symbol: &str,
+        timestamp: DateTime<Utc>,
+        current_price: f64,
+    ) -> bool {
+        let Some(minutes) = self.flatten_before_close else {
+            return false;
+        };
+        let closing = self
+            .calendar
+            .minutes_to_close(timestamp)
+            .is_some_and(|left| left <= minutes);
+        if closing {
+            self.execute(symbol, Signal::Exit, current_price).await;
+        }
+        closing
     }
 
-    pub fn update_best_configs(&mut self, gain: i32, config: IndicatorConfig, top_n: usize) {
+    pub fn update_best_configs(&mut self, gain: i32, config: StrategyConfig, top_n: usize) {
         self.best_eval_config.insert(gain, config);
 
         // Keep top N
@@ -117,73 +536,508 @@ impl Evaluator {
             .iter()
             .map(|bar| bar.close_price.to_f64().unwrap_or(0.0))
             .collect::<Vec<f64>>();
-        let i = &mut self.indicator_client;
 
-        // Determine periods from config or defaults
-        let (long_period, short_period) = if let Some(config) = &self.eval_config {
-            if let Some(sma) = &config.indicator_config.sma {
-                (sma.long_range as i64, sma.short_range as i64)
-            } else {
-                (bars_to_f64.len() as i64, 5)
+        // Without a configured strategy a 5 bar SMA is crossed with the SMA of
+        // every buffered bar, as before strategies could be configured
+        let strategy = match &self.eval_config {
+            Some(config) => config.strategy.clone(),
+            None => StrategyConfig::Trend(IndicatorConfig::sma_only(SMAConfig {
+                long_range: bars_to_f64.len() as i32,
+                short_range: 5,
+            })),
+        };
+
+        if let StrategyConfig::Pairs(config) = &strategy {
+            return self.eval_pair(config).await;
+        }
+
+        if self.eval_config.is_some() && bars_to_f64.len() < strategy.lookback() {
+            return;
+        }
+
+        let signal = match &strategy {
+            StrategyConfig::Ensemble(config) => {
+                self.ensemble_signal(symbol, config, &bars_to_f64).await
             }
-        } else {
-            (bars_to_f64.len() as i64, 5)
+            strategy => self.strategy_signal(symbol, strategy, &bars_to_f64).await,
+        };
+
+        let signal = match signal {
+            Ok(signal) => self.filter_trend(symbol, signal).await,
+            Err(e) => Err(e),
         };
+        match signal {
+            Ok(signal) => {
+                self.record_signal(symbol, signal);
+                if !self.paused.contains(symbol) {
+                    self.execute(symbol, signal, current_price).await;
+                }
+            }
+            Err(e) => error!("Indicator RPC error: {:?}", e),
+        }
+    }
+
+    /// Keep the signal of the latest bar of `symbol`, emitting it unless it holds
+    fn record_signal(&mut self, symbol: &str, signal: Signal) {
+        let timestamp = self
+            .buffer
+            .get(symbol)
+            .and_then(|buffer| buffer.get_bars()?.last())
+            .map_or_else(Utc::now, |bar| bar.timestamp);
+        self.signals.insert(symbol.to_string(), (timestamp, signal));
+        if signal != Signal::Hold {
+            self.events.emit(symbol, EventKind::Signal(signal));
+        }
+    }
 
-        if (bars_to_f64.len() as i64) < long_period {
+    /// Hold entries against the higher-timeframe trend
+    ///
+    /// Until the trend timeframe has enough closed bars no entries are made.
+    async fn filter_trend(&mut self, symbol: &str, signal: Signal) -> Result<Signal, Status> {
+        let Some(filter) = self.trend_filter.clone() else {
+            return Ok(signal);
+        };
+        if matches!(signal, Signal::Exit | Signal::Hold) {
+            return Ok(signal);
+        }
+        let higher = self
+            .timeframes
+            .get(symbol)
+            .and_then(|t| t.get(&filter.timeframe))
+            .map(closes)
+            .unwrap_or_default();
+        let period = filter.sma_period;
+        if higher.len() < period as usize {
+            return Ok(Signal::Hold);
+        }
+
+        let sma = indicators::series(
+            &mut self.indicator_client,
+            IndicatorType::SimpleMovingAverage,
+            period as i64,
+            2.0,
+            &higher,
+        )
+        .await?;
+        let (Some(close), Some(sma)) = (higher.last(), sma.last()) else {
+            return Ok(Signal::Hold);
+        };
+
+        let position = self.ap.get_position(symbol).await.unwrap_or(0);
+        let filtered = trend::filter(signal, trend::direction(*close, *sma), position);
+        if filtered != signal {
+            info!(
+                "{:?} on {} held against the {} trend",
+                signal, symbol, filter.timeframe
+            );
+        }
+        Ok(filtered)
+    }
+
+    /// Signal of a single-symbol strategy
+    async fn strategy_signal(
+        &mut self,
+        symbol: &str,
+        strategy: &StrategyConfig,
+        closes: &[f64],
+    ) -> Result<Signal, Status> {
+        match strategy {
+            StrategyConfig::Trend(config) => self
+                .indicator_signals(config, closes)
+                .await
+                .map(|signals| combine(&signals, &config.rule)),
+            StrategyConfig::MeanReversion(config) => {
+                self.mean_reversion_signal(symbol, config, closes).await
+            }
+            StrategyConfig::Breakout(config) => self.breakout_signal(symbol, config, closes).await,
+            StrategyConfig::Pairs(_) | StrategyConfig::Ensemble(_) => {
+                error!("{:?} can't vote in an ensemble", strategy.kind());
+                Ok(Signal::Hold)
+            }
+        }
+    }
+
+    /// Vote of all ensemble members, each sub-signal is logged
+    async fn ensemble_signal(
+        &mut self,
+        symbol: &str,
+        config: &EnsembleConfig,
+        closes: &[f64],
+    ) -> Result<Signal, Status> {
+        let mut signals = vec![];
+        for (n, member) in config.members.iter().enumerate() {
+            let signal = self
+                .strategy_signal(symbol, &member.strategy, closes)
+                .await?;
+            info!(
+                "Ensemble member {} ({:?}, weight {:.2}): {:?}",
+                n,
+                member.strategy.kind(),
+                member.weight,
+                signal
+            );
+            signals.push((signal, member.weight));
+        }
+
+        let signal = combine(&signals, &config.rule);
+        if signal != Signal::Hold {
+            info!(
+                "Ensemble {:?} vote for {}: {:?}",
+                config.rule, symbol, signal
+            );
+        }
+        Ok(signal)
+    }
+
+    /// Trade the spread of the configured pair once both legs have a bar for the same time
+    pub async fn eval_pair(&mut self, config: &PairsConfig) {
+        let Some((symbol_a, symbol_b)) = self.pair.clone() else {
+            error!("Pairs strategy selected but no pair configured");
+            return;
+        };
+        let (Some(buffer_a), Some(buffer_b)) =
+            (self.buffer.get(&symbol_a), self.buffer.get(&symbol_b))
+        else {
+            return;
+        };
+
+        // Wait until the second leg of the latest bar has arrived
+        let last_a = buffer_a
+            .get_bars()
+            .and_then(|b| b.last())
+            .map(|b| b.timestamp);
+        let last_b = buffer_b
+            .get_bars()
+            .and_then(|b| b.last())
+            .map(|b| b.timestamp);
+        if last_a.is_none() || last_a != last_b {
+            return;
+        }
+
+        let (closes_a, closes_b) = aligned_closes(buffer_a, buffer_b);
+        let lookback = config.lookback();
+        if closes_a.len() < lookback {
             return;
         }
+        let window_a = &closes_a[closes_a.len() - lookback..];
+        let window_b = &closes_b[closes_b.len() - lookback..];
+        let (price_a, price_b) = (window_a[lookback - 1], window_b[lookback - 1]);
 
-        let opt = Opt {
-            multiplier: 2.0,
-            period: long_period,
+        let Some(spread) = pairs::spread(window_a, window_b) else {
+            return;
         };
-        let request = ListNumbersRequest2 {
-            id: IndicatorType::SimpleMovingAverage as i32,
-            opt: Some(opt),
-            list: bars_to_f64.clone(),
+
+        let position_a = self.ap.get_position(&symbol_a).await.unwrap_or(0);
+        let signal = pairs::signal(spread.zscore, config.entry_z, config.exit_z, position_a);
+
+        // Each leg is shown with the signal it is traded on
+        let (signal_a, signal_b) = match signal {
+            PairSignal::LongSpread => (Signal::Buy, Signal::Sell),
+            PairSignal::ShortSpread => (Signal::Sell, Signal::Buy),
+            PairSignal::Exit => (Signal::Exit, Signal::Exit),
+            PairSignal::Hold => (Signal::Hold, Signal::Hold),
         };
-        //grpc call
-        let long_range = i.gen_liste(request).await.unwrap().get_ref().result.clone();
+        self.record_signal(&symbol_a, signal_a);
+        self.record_signal(&symbol_b, signal_b);
+        if self.paused.contains(&symbol_a) || self.paused.contains(&symbol_b) {
+            return;
+        }
 
-        /* info!("long_range: {:?}", long_range);
-        info!("bars_to_f64: {:?}", bars_to_f64.len()); */
+        match signal {
+            PairSignal::LongSpread | PairSignal::ShortSpread => {
+                let portfolio_value = self.ap.get_portfolio_value().await.unwrap_or(0.0);
+                let (count_a, count_b) = self.position_sizer.calculate_pair_size(
+                    price_a,
+                    price_b,
+                    spread.hedge_ratio,
+                    portfolio_value,
+                );
+                if count_a == 0 {
+                    self.events.emit(
+                        &symbol_a,
+                        EventKind::RiskBreach(
+                            "trade and position limits leave no pair to trade".to_string(),
+                        ),
+                    );
+                    return;
+                }
 
-        let opt = Opt {
-            multiplier: 2.0,
-            period: short_period,
+                // Long spread buys a, short spread sells it. b is traded against a
+                // for a positive hedge ratio and alongside it for a negative one.
+                let side_a = if signal == PairSignal::LongSpread {
+                    Side::Buy
+                } else {
+                    Side::Sell
+                };
+                let side_b = match (side_a, count_b > 0) {
+                    (Side::Buy, true) | (Side::Sell, false) => Side::Sell,
+                    (Side::Sell, true) | (Side::Buy, false) => Side::Buy,
+                };
+                let leg_a = (symbol_a.as_str(), side_a, count_a, price_a);
+                let leg_b = (symbol_b.as_str(), side_b, count_b.abs(), price_b);
+                // Both legs or neither, a single leg would be an unhedged position
+                let spread_ok = |symbol: &str, side| {
+                    let quote = self.quote(symbol);
+                    execution::order_price(side, quote, 0.0, &self.execution).is_ok()
+                };
+                if !spread_ok(leg_a.0, leg_a.1) || !spread_ok(leg_b.0, leg_b.1) {
+                    info!("Not trading pair {:?}: spread too wide", signal);
+                    let reason = format!("pair {:?} not traded, spread too wide", signal);
+                    self.events
+                        .emit(&symbol_a, EventKind::RiskBreach(reason.clone()));
+                    self.events.emit(&symbol_b, EventKind::RiskBreach(reason));
+                    return;
+                }
+                if !self.place_pair(leg_a, leg_b).await {
+                    return;
+                }
+                info!(
+                    "Pair {:?} (z = {:.2}, hedge ratio = {:.3}): {:?} {} {}, {:?} {} {}",
+                    signal,
+                    spread.zscore,
+                    spread.hedge_ratio,
+                    leg_a.1,
+                    leg_a.2,
+                    leg_a.0,
+                    leg_b.1,
+                    leg_b.2,
+                    leg_b.0
+                );
+            }
+            PairSignal::Exit => {
+                info!("Pair exit (z = {:.2})", spread.zscore);
+                self.execute(&symbol_a, Signal::Exit, price_a).await;
+                self.execute(&symbol_b, Signal::Exit, price_b).await;
+            }
+            PairSignal::Hold => {}
+        }
+    }
+
+    /// Place leg `a` of a pair, then its hedge `b`, each as symbol, side, count
+    /// and reference price
+    ///
+    /// If `b` isn't filled, `a` is closed again rather than left unhedged and
+    /// a risk breach is reported for both symbols. Whether both legs were filled.
+    pub async fn place_pair(
+        &mut self,
+        (symbol_a, side_a, count_a, price_a): (&str, Side, i32, f64),
+        (symbol_b, side_b, count_b, price_b): (&str, Side, i32, f64),
+    ) -> bool {
+        if self
+            .place(side_a, symbol_a, count_a, price_a)
+            .await
+            .is_none()
+        {
+            error!("Not trading pair: {} was not filled", symbol_a);
+            return false;
+        }
+        if self
+            .place(side_b, symbol_b, count_b, price_b)
+            .await
+            .is_some()
+        {
+            return true;
+        }
+
+        let unwound = self
+            .place(side_a.opposite(), symbol_a, count_a, price_a)
+            .await
+            .is_some();
+        let reason = if unwound {
+            format!("hedge {} not filled, {} closed again", symbol_b, symbol_a)
+        } else {
+            format!("hedge {} not filled, {} left unhedged", symbol_b, symbol_a)
         };
-        let request = ListNumbersRequest2 {
-            id: IndicatorType::SimpleMovingAverage as i32,
-            opt: Some(opt),
-            list: bars_to_f64.clone(),
+        error!("Pair not traded: {}", reason);
+        self.events
+            .emit(symbol_a, EventKind::RiskBreach(reason.clone()));
+        self.events.emit(symbol_b, EventKind::RiskBreach(reason));
+        false
+    }
+
+    /// Bollinger band and RSI signal, depending on the open position
+    async fn mean_reversion_signal(
+        &mut self,
+        symbol: &str,
+        config: &MeanReversionConfig,
+        closes: &[f64],
+    ) -> Result<Signal, Status> {
+        let i = &mut self.indicator_client;
+        let close = *closes.last().unwrap_or(&0.0);
+
+        let bands = indicators::bollinger_bands(i, &config.bollinger, closes).await?;
+        let rsi = indicators::series(
+            i,
+            IndicatorType::RelativeStrengthIndex,
+            config.rsi.period as i64,
+            2.0,
+            closes,
+        )
+        .await?;
+
+        let (Some((lower, middle, upper)), Some(rsi)) = (bands, rsi.last()) else {
+            return Ok(Signal::Hold);
         };
 
-        let short_range = i.gen_liste(request).await.unwrap().get_ref().result.clone();
-        //info!("short_range: {:?}", short_range);
-        let gc = cross_gc_dc::gc(
-            (
-                *long_range.get(long_range.len() - 2).unwrap(),
-                *long_range.get(long_range.len() - 1).unwrap(),
-            ),
-            (
-                *short_range.get(short_range.len() - 2).unwrap(),
-                *short_range.get(short_range.len() - 1).unwrap(),
-            ),
-        );
+        let position = self.ap.get_position(symbol).await.unwrap_or(0);
+        Ok(mean_reversion::signal(
+            close,
+            Bands {
+                lower,
+                middle,
+                upper,
+                rsi: *rsi,
+            },
+            Thresholds {
+                oversold: config.rsi.oversold,
+                overbought: config.rsi.overbought,
+                exit_rsi: config.exit_rsi,
+            },
+            position,
+        ))
+    }
 
-        let dc = cross_gc_dc::dc(
-            (
-                *long_range.get(long_range.len() - 2).unwrap(),
-                *long_range.get(long_range.len() - 1).unwrap(),
-            ),
-            (
-                *short_range.get(short_range.len() - 2).unwrap(),
-                *short_range.get(short_range.len() - 1).unwrap(),
-            ),
-        );
-        if let Some(gc) = gc {
-            if gc {
+    /// Donchian channel breakout signal with optional momentum and volatility filters
+    async fn breakout_signal(
+        &mut self,
+        symbol: &str,
+        config: &BreakoutConfig,
+        closes: &[f64],
+    ) -> Result<Signal, Status> {
+        let bars = self.buffer.get(symbol).unwrap().get_bars().unwrap();
+        let highs = bars
+            .iter()
+            .map(|bar| bar.high_price.to_f64().unwrap_or(0.0))
+            .collect::<Vec<f64>>();
+        let lows = bars
+            .iter()
+            .map(|bar| bar.low_price.to_f64().unwrap_or(0.0))
+            .collect::<Vec<f64>>();
+        let close = *closes.last().unwrap_or(&0.0);
+        let i = &mut self.indicator_client;
+
+        let entry = config.entry_period as i64;
+        let exit = config.exit_period as i64;
+        let maximum = indicators::series(i, IndicatorType::Maximum, entry, 0.0, &highs).await?;
+        let minimum = indicators::series(i, IndicatorType::Minimum, exit, 0.0, &lows).await?;
+
+        // The channel is taken from the previous bar so the current bar can break it
+        let (Some((high, _)), Some((low, _))) = (
+            indicators::last_two(&maximum),
+            indicators::last_two(&minimum),
+        ) else {
+            return Ok(Signal::Hold);
+        };
+
+        let mut filters_pass = true;
+        if let Some(momentum) = &config.momentum {
+            let roc = indicators::series(
+                i,
+                IndicatorType::RateOfChange,
+                momentum.period as i64,
+                0.0,
+                closes,
+            )
+            .await?;
+            filters_pass &= roc
+                .last()
+                .is_some_and(|r| breakout::momentum(*r, momentum.min_rate_of_change));
+        }
+        if let Some(volatility) = &config.volatility {
+            let deviation = indicators::series(
+                i,
+                IndicatorType::StandardDeviation,
+                volatility.period as i64,
+                0.0,
+                closes,
+            )
+            .await?;
+            filters_pass &= deviation
+                .last()
+                .is_some_and(|d| breakout::regime(*d, close, volatility.min, volatility.max));
+        }
+
+        let position = self.ap.get_position(symbol).await.unwrap_or(0);
+        Ok(breakout::signal(
+            close,
+            breakout::Channel { high, low },
+            filters_pass,
+            position,
+        ))
+    }
+
+    /// Signal and vote weight of every indicator configured in `config`
+    async fn indicator_signals(
+        &mut self,
+        config: &IndicatorConfig,
+        closes: &[f64],
+    ) -> Result<Vec<(Signal, f64)>, Status> {
+        let i = &mut self.indicator_client;
+        let close = *closes.last().unwrap_or(&0.0);
+        let weights = &config.weights;
+        let mut signals = vec![];
+
+        if let Some(sma) = &config.sma {
+            let kind = IndicatorType::SimpleMovingAverage;
+            let long_range =
+                indicators::series(i, kind, sma.long_range as i64, 2.0, closes).await?;
+            let short_range =
+                indicators::series(i, kind, sma.short_range as i64, 2.0, closes).await?;
+            if let Some((long, short)) =
+                indicators::last_two(&long_range).zip(indicators::last_two(&short_range))
+            {
+                signals.push((oscillators::crossover(long, short), weights.sma));
+            }
+        }
+
+        if let Some(ema) = &config.ema {
+            let kind = IndicatorType::ExponentialMovingAverage;
+            let long_range =
+                indicators::series(i, kind, ema.long_range as i64, 2.0, closes).await?;
+            let short_range =
+                indicators::series(i, kind, ema.short_range as i64, 2.0, closes).await?;
+            if let Some((long, short)) =
+                indicators::last_two(&long_range).zip(indicators::last_two(&short_range))
+            {
+                signals.push((oscillators::crossover(long, short), weights.ema));
+            }
+        }
+
+        if let Some(rsi) = &config.rsi {
+            let kind = IndicatorType::RelativeStrengthIndex;
+            let values = indicators::series(i, kind, rsi.period as i64, 2.0, closes).await?;
+            if let Some(value) = values.last() {
+                signals.push((
+                    oscillators::rsi(*value, rsi.oversold, rsi.overbought),
+                    weights.rsi,
+                ));
+            }
+        }
+
+        if let Some(bollinger) = &config.bollinger {
+            if let Some((lower, _, upper)) =
+                indicators::bollinger_bands(i, bollinger, closes).await?
+            {
+                signals.push((
+                    oscillators::bollinger(close, lower, upper),
+                    weights.bollinger,
+                ));
+            }
+        }
+
+        if let Some(macd) = &config.macd {
+            if let Some((macd_line, signal_line)) = indicators::macd_lines(i, macd, closes).await? {
+                signals.push((oscillators::macd(signal_line, macd_line), weights.macd));
+            }
+        }
+
+        Ok(signals)
+    }
+
+    /// Place the orders for `signal`, sized by the position sizer
+    pub async fn execute(&mut self, symbol: &str, signal: Signal, current_price: f64) {
+        match signal {
+            Signal::Buy => {
                 let portfolio_value = self.ap.get_portfolio_value().await.unwrap_or(0.0);
                 let cash_available = self.ap.get_cash_balance().await.unwrap_or(0.0);
                 let current_position = self.ap.get_position(symbol).await.unwrap_or(0);
@@ -197,22 +1051,22 @@ impl Evaluator {
                 );
 
                 if shares_to_buy > 0 {
-                    let req = BuyRequest {
-                        symbol: symbol.to_string(),
-                        count: shares_to_buy,
-                        price_per_share: current_price,
-                    };
-                    self.ap.buy(req).await;
-                    info!(
-                        "Golden Cross: Buying {} shares at {}",
-                        shares_to_buy, current_price
+                    if let Some(price) = self
+                        .place(Side::Buy, symbol, shares_to_buy, current_price)
+                        .await
+                    {
+                        info!("Buy signal: Buying {} shares at {}", shares_to_buy, price);
+                    }
+                } else {
+                    self.events.emit(
+                        symbol,
+                        EventKind::RiskBreach(
+                            "trade and position limits leave no shares to buy".to_string(),
+                        ),
                     );
                 }
             }
-        }
-
-        if let Some(dc) = dc {
-            if dc {
+            Signal::Sell => {
                 let current_position = self.ap.get_position(symbol).await.unwrap_or(0);
 
                 if current_position > 0 {
@@ -224,16 +1078,15 @@ impl Evaluator {
                     );
 
                     if shares_to_sell > 0 {
-                        let req = SellRequest {
-                            symbol: symbol.to_string(),
-                            count: shares_to_sell,
-                            price_per_share: current_price,
-                        };
-                        self.ap.sell(req).await;
-                        info!(
-                            "Death Cross: Selling {} shares at {}",
-                            shares_to_sell, current_price
-                        );
+                        if let Some(price) = self
+                            .place(Side::Sell, symbol, shares_to_sell, current_price)
+                            .await
+                        {
+                            info!(
+                                "Sell signal: Selling {} shares at {}",
+                                shares_to_sell, price
+                            );
+                        }
                     }
                 } else {
                     let portfolio_value = self.ap.get_portfolio_value().await.unwrap_or(0.0);
@@ -244,20 +1097,178 @@ impl Evaluator {
                     );
 
                     if shares_to_short > 0 {
-                        let req = SellRequest {
-                            symbol: symbol.to_string(),
-                            count: shares_to_short,
-                            price_per_share: current_price,
-                        };
-                        self.ap.sell(req).await;
+                        if let Some(price) = self
+                            .place(Side::Sell, symbol, shares_to_short, current_price)
+                            .await
+                        {
+                            info!(
+                                "Sell signal: Shorting {} shares at {}",
+                                shares_to_short, price
+                            );
+                        }
+                    } else {
+                        self.events.emit(
+                            symbol,
+                            EventKind::RiskBreach(
+                                "trade and position limits leave no shares to short".to_string(),
+                            ),
+                        );
+                    }
+                }
+            }
+            Signal::Exit => {
+                let current_position = self.ap.get_position(symbol).await.unwrap_or(0);
+
+                if current_position > 0 {
+                    if let Some(price) = self
+                        .place(Side::Sell, symbol, current_position, current_price)
+                        .await
+                    {
                         info!(
-                            "Death Cross: Shorting {} shares at {}",
-                            shares_to_short, current_price
+                            "Exit signal: Selling {} shares at {}",
+                            current_position, price
+                        );
+                    }
+                } else if current_position < 0 {
+                    if let Some(price) = self
+                        .place(Side::Buy, symbol, -current_position, current_price)
+                        .await
+                    {
+                        info!(
+                            "Exit signal: Covering {} shares at {}",
+                            -current_position, price
                         );
                     }
                 }
             }
+            Signal::Hold => {}
+        }
+    }
+
+    /// Latest quote of `symbol`, unless it is older than allowed at its last bar
+    fn quote(&self, symbol: &str) -> Option<&Nbbo> {
+        let now = self.last_bars.get(symbol).copied().unwrap_or_else(Utc::now);
+        self.quotes
+            .fresh(symbol, now, self.execution.max_quote_age())
+    }
+
+    /// Send an order priced against the latest quote of `symbol`
+    ///
+    /// `reference_price` is used when no fresh quote is known. Returns the
+    /// order price once the depot confirmed the order, `None` if the spread is
+    /// too wide or the order failed. Only confirmed orders are recorded.
+    pub async fn place(
+        &mut self,
+        side: Side,
+        symbol: &str,
+        count: i32,
+        reference_price: f64,
+    ) -> Option<f64> {
+        let (order, mid) = self.price_order(side, symbol, reference_price)?;
+        let response = Self::send_order(&mut self.ap, side, symbol, count, order.price).await;
+        self.record_order(side, symbol, count, order, mid, response)
+            .map(|record| record.price)
+    }
+
+    /// Price of an order and the mid price it is measured against
+    ///
+    /// `None` if the spread is too wide to trade, a risk breach is reported then.
+    pub fn price_order(
+        &self,
+        side: Side,
+        symbol: &str,
+        reference_price: f64,
+    ) -> Option<(OrderPrice, f64)> {
+        let quote = self.quote(symbol);
+        let order = match execution::order_price(side, quote, reference_price, &self.execution) {
+            Ok(order) => order,
+            Err(Refusal::WideSpread(spread)) => {
+                info!(
+                    "Not trading {}: spread of {:.3}% is too wide",
+                    symbol, spread
+                );
+                self.events.emit(
+                    symbol,
+                    EventKind::RiskBreach(format!("spread of {:.3}% is too wide", spread)),
+                );
+                return None;
+            }
+        };
+        let mid = quote.map(|q| q.mid()).unwrap_or(order.price);
+        Some((order, mid))
+    }
+
+    /// Send an order to `depot`, `None` if it can't be reached
+    pub async fn send_order(
+        depot: &mut Alpaca,
+        side: Side,
+        symbol: &str,
+        count: i32,
+        price: f64,
+    ) -> Option<TransactionResponse> {
+        match side {
+            Side::Buy => {
+                depot
+                    .buy(BuyRequest {
+                        symbol: symbol.to_string(),
+                        count,
+                        price_per_share: price,
+                    })
+                    .await
+            }
+            Side::Sell => {
+                depot
+                    .sell(SellRequest {
+                        symbol: symbol.to_string(),
+                        count,
+                        price_per_share: price,
+                    })
+                    .await
+            }
+        }
+    }
+
+    /// Report the depot's answer to an order, the order if it was confirmed
+    pub fn record_order(
+        &mut self,
+        side: Side,
+        symbol: &str,
+        count: i32,
+        order: OrderPrice,
+        mid: f64,
+        response: Option<TransactionResponse>,
+    ) -> Option<TradeRecord> {
+        // Errors reaching the depot are logged by the client
+        let response = response?;
+        let success = response.success;
+        let record = TradeRecord {
+            symbol: symbol.to_string(),
+            side,
+            count,
+            price: order.price,
+            mid,
+            spread_cost: order.spread_cost * count as f64,
+        };
+        if success {
+            self.events.emit(symbol, EventKind::Order(record.clone()));
+        }
+        self.events.emit(
+            symbol,
+            EventKind::Fill {
+                side,
+                count,
+                price: order.price,
+                success,
+                message: response.message,
+                cash: response.current_cash,
+            },
+        );
+        if !success {
+            return None;
         }
+        self.holdings = None;
+        self.trades.push(record.clone());
+        Some(record)
     }
 
     pub async fn eval_trade(&mut self, t: Trade) -> Result<f64, Error> {
@@ -294,7 +1305,8 @@ impl Evaluator {
 
 #[cfg(test)]
 mod tests {
-    use crate::mocking::mock::data_stream_from_csv;
+    use crate::mocking::layout::CsvLayout;
+    use crate::mocking::source::HistoricalSource;
 
     use super::*;
     use apca::data::v2::stream::Data;
@@ -307,10 +1319,9 @@ mod tests {
         // Read from actual orcl.csv file
         let path = "files/orcl.csv";
         let symbol = "ORCL";
-        let stream = data_stream_from_csv(path, &symbol).await?;
-        tokio::pin!(stream);
+        let mut stream = HistoricalSource::csv(path, symbol, CsvLayout::default()).bars();
 
-        let mut evaluator = Evaluator::new().await;
+        let mut evaluator = Evaluator::new().await?;
 
         // Get the first item from the stream
         loop {
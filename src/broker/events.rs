@@ -0,0 +1,87 @@
+use chrono::{DateTime, Utc};
+use tokio::sync::broadcast;
+
+use crate::broker::execution::{Side, TradeRecord};
+use crate::pattern::signal::Signal;
+
+/// Events kept per subscriber, slower subscribers miss the oldest ones
+const CAPACITY: usize = 1024;
+
+/// Something the evaluator decided or did
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub timestamp: DateTime<Utc>,
+    pub symbol: String,
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventKind {
+    /// Strategy signal other than hold
+    Signal(Signal),
+    /// Order the depot confirmed
+    Order(TradeRecord),
+    /// Answer of the depot to an order
+    Fill {
+        side: Side,
+        count: i32,
+        price: f64,
+        success: bool,
+        message: String,
+        /// Cash left after the order
+        cash: f64,
+    },
+    /// Order refused or reduced by a risk rule, or trading halted
+    RiskBreach(String),
+}
+
+/// Sends events to everyone subscribed, nothing is kept without subscribers
+#[derive(Debug, Clone)]
+pub struct Events(broadcast::Sender<Event>);
+
+impl Events {
+    pub fn new() -> Self {
+        Self(broadcast::channel(CAPACITY).0)
+    }
+
+    pub fn emit(&self, symbol: &str, kind: EventKind) {
+        // Fails only without subscribers
+        let _ = self.0.send(Event {
+            timestamp: Utc::now(),
+            symbol: symbol.to_string(),
+            kind,
+        });
+    }
+
+    /// Events emitted from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.0.subscribe()
+    }
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_subscribers_receive_later_events() {
+        let events = Events::new();
+        events.emit("AAA", EventKind::Signal(Signal::Buy));
+
+        let mut receiver = events.subscribe();
+        events.emit("BBB", EventKind::RiskBreach("spread too wide".to_string()));
+        let event = receiver.recv().await.unwrap();
+        assert_eq!(event.symbol, "BBB");
+        assert_eq!(
+            event.kind,
+            EventKind::RiskBreach("spread too wide".to_string())
+        );
+        assert!(receiver.try_recv().is_err());
+    }
+}
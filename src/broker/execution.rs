@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+
+use apca::data::v2::stream::Quote;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Direction of an order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl Side {
+    /// Side closing a position opened with this one
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        }
+    }
+}
+
+/// Best bid and offer of a symbol
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Nbbo {
+    pub bid: f64,
+    pub ask: f64,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl Nbbo {
+    pub fn mid(&self) -> f64 {
+        (self.bid + self.ask) / 2.0
+    }
+
+    pub fn spread(&self) -> f64 {
+        self.ask - self.bid
+    }
+
+    /// Spread relative to the mid price, in percent
+    pub fn spread_percent(&self) -> f64 {
+        self.spread() / self.mid() * 100.0
+    }
+}
+
+/// Latest NBBO per symbol from the quote stream
+#[derive(Debug, Default)]
+pub struct QuoteBook {
+    quotes: HashMap<String, Nbbo>,
+}
+
+impl QuoteBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep `quote` if it is newer than the known one
+    ///
+    /// One-sided and crossed quotes are ignored, they can't be priced against.
+    pub fn update(&mut self, quote: &Quote) {
+        let (Some(bid), Some(ask)) = (quote.bid_price.to_f64(), quote.ask_price.to_f64()) else {
+            return;
+        };
+        self.set(&quote.symbol, bid, ask, quote.timestamp);
+    }
+
+    pub fn set(&mut self, symbol: &str, bid: f64, ask: f64, timestamp: DateTime<Utc>) {
+        if bid <= 0.0 || ask < bid {
+            return;
+        }
+        if self
+            .quotes
+            .get(symbol)
+            .is_some_and(|known| known.timestamp > timestamp)
+        {
+            return;
+        }
+        self.quotes.insert(
+            symbol.to_string(),
+            Nbbo {
+                bid,
+                ask,
+                timestamp,
+            },
+        );
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<&Nbbo> {
+        self.quotes.get(symbol)
+    }
+
+    /// Quote of `symbol` unless it is older than `max_age` at `now`
+    pub fn fresh(
+        &self,
+        symbol: &str,
+        now: DateTime<Utc>,
+        max_age: Option<Duration>,
+    ) -> Option<&Nbbo> {
+        self.get(symbol)
+            .filter(|quote| max_age.is_none_or(|max_age| now - quote.timestamp <= max_age))
+    }
+
+    pub fn clear(&mut self) {
+        self.quotes.clear();
+    }
+}
+
+/// How orders are priced against the quotes
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ExecutionSettings {
+    /// Fraction of the spread to improve on the ask or bid, 0.5 prices at the mid
+    pub price_offset: f64,
+    /// Refuse orders while the spread is wider than this, in percent of the mid
+    pub max_spread_percent: Option<f64>,
+    /// Older quotes are ignored and orders priced at the bar close, in seconds
+    pub max_quote_age_secs: Option<i64>,
+}
+
+impl ExecutionSettings {
+    pub fn max_quote_age(&self) -> Option<Duration> {
+        self.max_quote_age_secs.map(Duration::seconds)
+    }
+}
+
+/// Price of an order and what crossing the spread costs per share
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrderPrice {
+    pub price: f64,
+    /// Price paid above the mid for buys, or received below it for sells
+    pub spread_cost: f64,
+}
+
+/// Why an order was not placed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Refusal {
+    /// Spread in percent above the configured maximum
+    WideSpread(f64),
+}
+
+// buys at the ask, sells at the bid, each moved into the spread by the offset
+// without a quote the reference price is used and the spread cost is unknown
+pub fn order_price(
+    side: Side,
+    quote: Option<&Nbbo>,
+    reference_price: f64,
+    settings: &ExecutionSettings,
+) -> Result<OrderPrice, Refusal> {
+    let Some(quote) = quote else {
+        return Ok(OrderPrice {
+            price: reference_price,
+            spread_cost: 0.0,
+        });
+    };
+
+    let spread_percent = quote.spread_percent();
+    if settings
+        .max_spread_percent
+        .is_some_and(|max| spread_percent > max)
+    {
+        return Err(Refusal::WideSpread(spread_percent));
+    }
+
+    let improvement = quote.spread() * settings.price_offset.clamp(0.0, 0.5);
+    let price = match side {
+        Side::Buy => quote.ask - improvement,
+        Side::Sell => quote.bid + improvement,
+    };
+    Ok(OrderPrice {
+        price,
+        spread_cost: (price - quote.mid()).abs(),
+    })
+}
+
+/// One order sent to the depot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TradeRecord {
+    pub symbol: String,
+    pub side: Side,
+    pub count: i32,
+    pub price: f64,
+    /// Mid price at the time of the order, the order price without a quote
+    pub mid: f64,
+    /// Total cost of crossing the spread, `|price - mid| * count`
+    pub spread_cost: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(bid: f64, ask: f64) -> Nbbo {
+        Nbbo {
+            bid,
+            ask,
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_prices_at_ask_and_bid() {
+        let settings = ExecutionSettings::default();
+        let quote = quote(99.9, 100.1);
+
+        let buy = order_price(Side::Buy, Some(&quote), 100.0, &settings).unwrap();
+        assert_eq!(buy.price, 100.1);
+        assert!((buy.spread_cost - 0.1).abs() < 1e-9);
+
+        let sell = order_price(Side::Sell, Some(&quote), 100.0, &settings).unwrap();
+        assert_eq!(sell.price, 99.9);
+    }
+
+    #[test]
+    fn test_offset_inside_spread() {
+        let settings = ExecutionSettings {
+            price_offset: 0.25,
+            ..Default::default()
+        };
+        let buy = order_price(Side::Buy, Some(&quote(99.0, 101.0)), 100.0, &settings).unwrap();
+        assert_eq!(buy.price, 100.5);
+        assert_eq!(buy.spread_cost, 0.5);
+    }
+
+    #[test]
+    fn test_wide_spread_refused() {
+        let settings = ExecutionSettings {
+            max_spread_percent: Some(0.5),
+            ..Default::default()
+        };
+        let refused = order_price(Side::Buy, Some(&quote(99.0, 101.0)), 100.0, &settings);
+        assert_eq!(refused, Err(Refusal::WideSpread(2.0)));
+
+        // Without a quote the bar close is used
+        let fallback = order_price(Side::Sell, None, 100.0, &settings).unwrap();
+        assert_eq!(fallback.price, 100.0);
+        assert_eq!(fallback.spread_cost, 0.0);
+    }
+
+    #[test]
+    fn test_quote_book_keeps_newest() {
+        let mut book = QuoteBook::new();
+        let now = Utc::now();
+        book.set("AAA", 10.0, 10.1, now);
+        book.set("AAA", 9.0, 9.1, now - Duration::seconds(1));
+        // Crossed quote
+        book.set("AAA", 10.2, 10.1, now);
+        assert_eq!(book.get("AAA").unwrap().bid, 10.0);
+        assert!(book.get("BBB").is_none());
+    }
+
+    #[test]
+    fn test_stale_quote_ignored() {
+        let mut book = QuoteBook::new();
+        let now = Utc::now();
+        book.set("AAA", 10.0, 10.1, now - Duration::seconds(30));
+        assert!(book.fresh("AAA", now, None).is_some());
+        assert!(book
+            .fresh("AAA", now, Some(Duration::seconds(60)))
+            .is_some());
+        assert!(book
+            .fresh("AAA", now, Some(Duration::seconds(10)))
+            .is_none());
+    }
+}
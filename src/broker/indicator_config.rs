@@ -0,0 +1,462 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::pattern::signal::CombineRule;
+
+/// Set of indicators the evaluator runs and how their signals are combined
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndicatorConfig {
+    pub sma: Option<SMAConfig>,
+    #[serde(default)]
+    pub ema: Option<EMAConfig>,
+    #[serde(default)]
+    pub rsi: Option<RSIConfig>,
+    #[serde(default)]
+    pub bollinger: Option<BollingerConfig>,
+    #[serde(default)]
+    pub macd: Option<MACDConfig>,
+    #[serde(default = "default_rule")]
+    pub rule: CombineRule,
+    #[serde(default)]
+    pub weights: IndicatorWeights,
+}
+
+/// Simple moving average crossover
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SMAConfig {
+    pub long_range: i32,
+    pub short_range: i32,
+}
+
+/// Exponential moving average crossover
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EMAConfig {
+    pub long_range: i32,
+    pub short_range: i32,
+}
+
+/// Relative strength index with oversold/overbought thresholds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RSIConfig {
+    pub period: i32,
+    pub oversold: f64,
+    pub overbought: f64,
+}
+
+/// Bollinger bands around a simple moving average
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BollingerConfig {
+    pub period: i32,
+    pub multiplier: f64,
+}
+
+/// Moving average convergence divergence
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MACDConfig {
+    pub fast: i32,
+    pub slow: i32,
+    pub signal: i32,
+}
+
+/// Vote weights used by [`CombineRule::Weighted`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndicatorWeights {
+    pub sma: f64,
+    pub ema: f64,
+    pub rsi: f64,
+    pub bollinger: f64,
+    pub macd: f64,
+}
+
+impl Default for IndicatorWeights {
+    fn default() -> Self {
+        Self {
+            sma: 1.0,
+            ema: 1.0,
+            rsi: 1.0,
+            bollinger: 1.0,
+            macd: 1.0,
+        }
+    }
+}
+
+fn default_rule() -> CombineRule {
+    CombineRule::Any
+}
+
+impl SMAConfig {
+    pub fn random(rng: &mut impl Rng) -> Self {
+        let long_range = rng.random_range(10..=50);
+        let short_range = rng.random_range(2..=long_range - 5); // Ensure short < long
+
+        Self {
+            long_range,
+            short_range,
+        }
+    }
+
+    pub fn grid() -> Vec<Self> {
+        let mut grid = vec![];
+        for long_range in (10..=50).step_by(5) {
+            for short_range in (2..=long_range - 5).step_by(3) {
+                grid.push(Self {
+                    long_range,
+                    short_range,
+                });
+            }
+        }
+        grid
+    }
+
+    /// Bars needed before the indicator produces a signal
+    pub fn lookback(&self) -> usize {
+        self.long_range.max(self.short_range) as usize + 1
+    }
+}
+
+impl EMAConfig {
+    pub fn random(rng: &mut impl Rng) -> Self {
+        let long_range = rng.random_range(10..=50);
+        let short_range = rng.random_range(2..=long_range - 5);
+
+        Self {
+            long_range,
+            short_range,
+        }
+    }
+
+    pub fn grid() -> Vec<Self> {
+        let mut grid = vec![];
+        for long_range in (10..=50).step_by(5) {
+            for short_range in (2..=long_range - 5).step_by(3) {
+                grid.push(Self {
+                    long_range,
+                    short_range,
+                });
+            }
+        }
+        grid
+    }
+
+    pub fn lookback(&self) -> usize {
+        self.long_range.max(self.short_range) as usize + 1
+    }
+}
+
+impl RSIConfig {
+    pub fn random(rng: &mut impl Rng) -> Self {
+        let period = rng.random_range(5..=30);
+        let oversold = rng.random_range(15..=40) as f64;
+        let overbought = rng.random_range(60..=85) as f64;
+
+        Self {
+            period,
+            oversold,
+            overbought,
+        }
+    }
+
+    pub fn grid() -> Vec<Self> {
+        let mut grid = vec![];
+        for period in (6..=30).step_by(4) {
+            for oversold in (15..=40).step_by(5) {
+                for overbought in (60..=85).step_by(5) {
+                    grid.push(Self {
+                        period,
+                        oversold: oversold as f64,
+                        overbought: overbought as f64,
+                    });
+                }
+            }
+        }
+        grid
+    }
+
+    pub fn lookback(&self) -> usize {
+        self.period as usize + 1
+    }
+}
+
+impl BollingerConfig {
+    pub fn random(rng: &mut impl Rng) -> Self {
+        let period = rng.random_range(10..=40);
+        // Round to one decimal so results stay comparable
+        let multiplier = (rng.random_range(1.0..=3.0_f64) * 10.0).round() / 10.0;
+
+        Self { period, multiplier }
+    }
+
+    pub fn grid() -> Vec<Self> {
+        let mut grid = vec![];
+        for period in (10..=40).step_by(5) {
+            for step in 0..=8 {
+                grid.push(Self {
+                    period,
+                    multiplier: 1.0 + step as f64 * 0.25,
+                });
+            }
+        }
+        grid
+    }
+
+    pub fn lookback(&self) -> usize {
+        self.period as usize
+    }
+}
+
+impl MACDConfig {
+    pub fn random(rng: &mut impl Rng) -> Self {
+        let fast = rng.random_range(5..=15);
+        let slow = rng.random_range(fast + 5..=40);
+        let signal = rng.random_range(5..=15);
+
+        Self { fast, slow, signal }
+    }
+
+    pub fn grid() -> Vec<Self> {
+        let mut grid = vec![];
+        for fast in (5..=15).step_by(2) {
+            for slow in (fast + 5..=40).step_by(5) {
+                for signal in (5..=15).step_by(2) {
+                    grid.push(Self { fast, slow, signal });
+                }
+            }
+        }
+        grid
+    }
+
+    pub fn lookback(&self) -> usize {
+        (self.slow + self.signal) as usize + 1
+    }
+}
+
+impl IndicatorConfig {
+    /// Config with only an SMA crossover, as used before the other indicators existed
+    pub fn sma_only(sma: SMAConfig) -> Self {
+        Self {
+            sma: Some(sma),
+            ema: None,
+            rsi: None,
+            bollinger: None,
+            macd: None,
+            rule: default_rule(),
+            weights: IndicatorWeights::default(),
+        }
+    }
+
+    /// Random SMA crossover plus a random subset of the other indicators
+    pub fn random(rng: &mut impl Rng) -> Self {
+        let rule = match rng.random_range(0..3) {
+            0 => CombineRule::All,
+            1 => CombineRule::Any,
+            _ => CombineRule::Weighted {
+                threshold: (rng.random_range(0.1..=0.9_f64) * 10.0).round() / 10.0,
+            },
+        };
+        let weights = IndicatorWeights {
+            sma: rng.random_range(0.0..=1.0),
+            ema: rng.random_range(0.0..=1.0),
+            rsi: rng.random_range(0.0..=1.0),
+            bollinger: rng.random_range(0.0..=1.0),
+            macd: rng.random_range(0.0..=1.0),
+        };
+
+        IndicatorConfig {
+            sma: Some(SMAConfig::random(rng)),
+            ema: rng.random_bool(0.5).then(|| EMAConfig::random(rng)),
+            rsi: rng.random_bool(0.5).then(|| RSIConfig::random(rng)),
+            bollinger: rng.random_bool(0.5).then(|| BollingerConfig::random(rng)),
+            macd: rng.random_bool(0.5).then(|| MACDConfig::random(rng)),
+            rule,
+            weights,
+        }
+    }
+
+    /// Grid over every indicator on its own
+    ///
+    /// The full cartesian product of all parts is far too large to backtest,
+    /// so each indicator is swept separately with the others disabled.
+    pub fn grid() -> Vec<Self> {
+        let empty = Self {
+            sma: None,
+            ..Self::sma_only(SMAConfig {
+                long_range: 0,
+                short_range: 0,
+            })
+        };
+
+        let mut grid = vec![];
+        grid.extend(SMAConfig::grid().into_iter().map(|c| Self {
+            sma: Some(c),
+            ..empty.clone()
+        }));
+        grid.extend(EMAConfig::grid().into_iter().map(|c| Self {
+            ema: Some(c),
+            ..empty.clone()
+        }));
+        grid.extend(RSIConfig::grid().into_iter().map(|c| Self {
+            rsi: Some(c),
+            ..empty.clone()
+        }));
+        grid.extend(BollingerConfig::grid().into_iter().map(|c| Self {
+            bollinger: Some(c),
+            ..empty.clone()
+        }));
+        grid.extend(MACDConfig::grid().into_iter().map(|c| Self {
+            macd: Some(c),
+            ..empty.clone()
+        }));
+        grid
+    }
+
+    /// Bars needed before every configured indicator can produce a signal
+    pub fn lookback(&self) -> usize {
+        [
+            self.sma.as_ref().map(SMAConfig::lookback),
+            self.ema.as_ref().map(EMAConfig::lookback),
+            self.rsi.as_ref().map(RSIConfig::lookback),
+            self.bollinger.as_ref().map(BollingerConfig::lookback),
+            self.macd.as_ref().map(MACDConfig::lookback),
+        ]
+        .into_iter()
+        .flatten()
+        .max()
+        .unwrap_or(0)
+    }
+
+    /// Parameters that can't produce signals, empty if there are none
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if self.lookback() == 0 {
+            problems.push("at least one indicator is needed".to_string());
+        }
+        if let Some(sma) = &self.sma {
+            problems.extend(crossover_problems("sma", sma.short_range, sma.long_range));
+        }
+        if let Some(ema) = &self.ema {
+            problems.extend(crossover_problems("ema", ema.short_range, ema.long_range));
+        }
+        if let Some(rsi) = &self.rsi {
+            problems.extend(rsi.problems());
+        }
+        if let Some(bollinger) = &self.bollinger {
+            problems.extend(bollinger.problems());
+        }
+        if let Some(macd) = &self.macd {
+            problems.extend(crossover_problems("macd", macd.fast, macd.slow));
+            if macd.signal < 1 {
+                problems.push("macd.signal must be at least 1".to_string());
+            }
+        }
+        if let CombineRule::Weighted { threshold } = self.rule {
+            if !(0.0..=1.0).contains(&threshold) {
+                problems.push(format!(
+                    "rule threshold must be in [0, 1], is {}",
+                    threshold
+                ));
+            }
+        }
+        problems
+    }
+}
+
+impl RSIConfig {
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if self.period < 1 {
+            problems.push("rsi.period must be at least 1".to_string());
+        }
+        if !(0.0 <= self.oversold && self.oversold < self.overbought && self.overbought <= 100.0) {
+            problems.push(format!(
+                "rsi needs 0 <= oversold < overbought <= 100, has {} and {}",
+                self.oversold, self.overbought
+            ));
+        }
+        problems
+    }
+}
+
+impl BollingerConfig {
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        // The band width is a standard deviation, which needs two bars
+        if self.period < 2 {
+            problems.push("bollinger.period must be at least 2".to_string());
+        }
+        if self.multiplier <= 0.0 {
+            problems.push("bollinger.multiplier must be positive".to_string());
+        }
+        problems
+    }
+}
+
+// The short average has to be shorter than the long one to cross it
+fn crossover_problems(name: &str, short: i32, long: i32) -> Option<String> {
+    (short < 1 || short >= long).then(|| {
+        format!(
+            "{} needs 0 < short < long periods, has {} and {}",
+            name, short, long
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_ranges() {
+        for _ in 0..100 {
+            let config = IndicatorConfig::random(&mut rand::rng());
+            let sma = config.sma.unwrap();
+            assert!(sma.short_range < sma.long_range);
+            if let Some(rsi) = config.rsi {
+                assert!(rsi.oversold < rsi.overbought);
+            }
+            if let Some(macd) = config.macd {
+                assert!(macd.fast < macd.slow);
+            }
+        }
+    }
+
+    #[test]
+    fn test_grid_one_indicator_each() {
+        let grid = IndicatorConfig::grid();
+        assert!(!grid.is_empty());
+        for config in grid {
+            let active = [
+                config.sma.is_some(),
+                config.ema.is_some(),
+                config.rsi.is_some(),
+                config.bollinger.is_some(),
+                config.macd.is_some(),
+            ];
+            assert_eq!(active.iter().filter(|a| **a).count(), 1);
+        }
+    }
+
+    #[test]
+    fn test_lookback_uses_longest_indicator() {
+        let mut config = IndicatorConfig::sma_only(SMAConfig {
+            long_range: 20,
+            short_range: 5,
+        });
+        assert_eq!(config.lookback(), 21);
+
+        config.macd = Some(MACDConfig {
+            fast: 12,
+            slow: 26,
+            signal: 9,
+        });
+        assert_eq!(config.lookback(), 36);
+    }
+
+    #[test]
+    fn test_deserialize_sma_only_record() {
+        // Results stored before the other indicators existed only carry `sma`
+        let json = r#"{"sma":{"long_range":20,"short_range":5}}"#;
+        let config: IndicatorConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.rule, CombineRule::Any);
+        assert!(config.ema.is_none());
+    }
+}
@@ -0,0 +1,102 @@
+use tonic::transport::Channel;
+use tonic::Status;
+use trader_bot::indicator_client::init::calculate::{
+    indicator_client::IndicatorClient, IndicatorType, ListNumbersRequest2, Opt,
+};
+
+use crate::broker::indicator_config::{BollingerConfig, MACDConfig};
+
+/// Calculate an indicator series with the indicator service
+pub async fn series(
+    client: &mut IndicatorClient<Channel>,
+    kind: IndicatorType,
+    period: i64,
+    multiplier: f64,
+    list: &[f64],
+) -> Result<Vec<f64>, Status> {
+    let request = ListNumbersRequest2 {
+        id: kind as i32,
+        opt: Some(Opt { multiplier, period }),
+        list: list.to_vec(),
+    };
+    //grpc call
+    let response = client.gen_liste(request).await?;
+    Ok(response.into_inner().result)
+}
+
+/// Last two values of a series, oldest first
+pub fn last_two(values: &[f64]) -> Option<(f64, f64)> {
+    match values {
+        [.., prev, last] => Some((*prev, *last)),
+        _ => None,
+    }
+}
+
+/// Lower, middle and upper band for the latest bar
+///
+/// The service's `BollingerBands` response is a single flat series, so the
+/// bands are built from `SimpleMovingAverage` and `StandardDeviation` instead.
+pub async fn bollinger_bands(
+    client: &mut IndicatorClient<Channel>,
+    config: &BollingerConfig,
+    closes: &[f64],
+) -> Result<Option<(f64, f64, f64)>, Status> {
+    let period = config.period as i64;
+    let middle = series(
+        client,
+        IndicatorType::SimpleMovingAverage,
+        period,
+        0.0,
+        closes,
+    )
+    .await?;
+    let deviation = series(
+        client,
+        IndicatorType::StandardDeviation,
+        period,
+        0.0,
+        closes,
+    )
+    .await?;
+
+    Ok(match (middle.last(), deviation.last()) {
+        (Some(m), Some(d)) => Some((m - config.multiplier * d, *m, m + config.multiplier * d)),
+        _ => None,
+    })
+}
+
+/// MACD line and signal line, each as (previous, latest)
+pub async fn macd_lines(
+    client: &mut IndicatorClient<Channel>,
+    config: &MACDConfig,
+    closes: &[f64],
+) -> Result<Option<((f64, f64), (f64, f64))>, Status> {
+    let ema = IndicatorType::ExponentialMovingAverage;
+    let fast = series(client, ema, config.fast as i64, 0.0, closes).await?;
+    let slow = series(client, ema, config.slow as i64, 0.0, closes).await?;
+
+    // Align both series on their latest values
+    let macd: Vec<f64> = fast
+        .iter()
+        .rev()
+        .zip(slow.iter().rev())
+        .map(|(f, s)| f - s)
+        .collect::<Vec<f64>>()
+        .into_iter()
+        .rev()
+        .collect();
+    let signal = series(client, ema, config.signal as i64, 0.0, &macd).await?;
+
+    Ok(last_two(&macd).zip(last_two(&signal)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_two() {
+        assert_eq!(last_two(&[1.0, 2.0, 3.0]), Some((2.0, 3.0)));
+        assert_eq!(last_two(&[1.0]), None);
+    }
+}
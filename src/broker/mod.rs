@@ -1,3 +1,10 @@
 pub mod actions;
 pub mod evaluator;
+pub mod events;
+pub mod execution;
+pub mod indicator_config;
+pub mod indicators;
 pub mod position_sizing;
+pub mod reload;
+pub mod snapshot;
+pub mod strategy;
@@ -16,6 +16,23 @@ impl PositionSizer {
         }
     }
 
+    /// Limits that can't be traded with, empty if there are none
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        for (key, percent) in [
+            ("max_trade_percent", self.max_trade_percent),
+            ("max_position_percent", self.max_position_percent),
+        ] {
+            if !(percent > 0.0 && percent <= 100.0) {
+                problems.push(format!("{} must be in (0, 100], is {}", key, percent));
+            }
+        }
+        if self.max_position_percent < self.max_trade_percent {
+            problems.push("max_position_percent must not be below max_trade_percent".to_string());
+        }
+        problems
+    }
+
     /// Calculate the number of shares to buy based on portfolio value and risk limits
     ///
     /// # Arguments
@@ -135,6 +152,56 @@ impl PositionSizer {
 
         shares.max(0)
     }
+
+    /// Calculate the share counts of both legs of a pairs trade
+    ///
+    /// Both legs together count as one trade: the gross value of
+    /// `count_a * price_a + count_b * price_b` stays within the trade limit and
+    /// each leg within the position limit. `count_b` follows `count_a` by the
+    /// hedge ratio and keeps its sign: positive when b is traded against a,
+    /// negative when b moves inversely to a and is traded alongside it.
+    ///
+    /// # Arguments
+    /// * `price_a` - Current price of the first leg
+    /// * `price_b` - Current price of the second leg
+    /// * `hedge_ratio` - Shares of `b` per share of `a`
+    /// * `portfolio_value` - Total portfolio value
+    ///
+    /// # Returns
+    /// Share counts `(count_a, count_b)`, both 0 if either leg would be 0
+    pub fn calculate_pair_size(
+        &self,
+        price_a: f64,
+        price_b: f64,
+        hedge_ratio: f64,
+        portfolio_value: f64,
+    ) -> (i32, i32) {
+        if portfolio_value <= 0.0 || price_a <= 0.0 || price_b <= 0.0 {
+            return (0, 0);
+        }
+        // Values are gross, the sign only decides the side of b
+        let hedge_size = hedge_ratio.abs();
+
+        let max_trade_value = portfolio_value * (self.max_trade_percent / 100.0);
+        let max_position_value = portfolio_value * (self.max_position_percent / 100.0);
+
+        // Value of one share of a plus its hedge in b
+        let unit_value = price_a + hedge_size * price_b;
+        let mut count_a = (max_trade_value / unit_value).floor();
+
+        // Keep each leg within the position limit
+        count_a = count_a.min((max_position_value / price_a).floor());
+        if hedge_size > 0.0 {
+            count_a = count_a.min((max_position_value / (hedge_size * price_b)).floor());
+        }
+
+        let count_b = (count_a * hedge_ratio).round();
+        if count_a < 1.0 || count_b.abs() < 1.0 {
+            return (0, 0);
+        }
+
+        (count_a as i32, count_b as i32)
+    }
 }
 
 #[cfg(test)]
@@ -230,4 +297,33 @@ mod tests {
         let shares = sizer.calculate_short_size(100.0, 100_000.0, -100);
         assert_eq!(shares, 0);
     }
+
+    #[test]
+    fn test_calculate_pair_size_basic() {
+        let sizer = PositionSizer::new(1.0, 10.0);
+
+        // Portfolio: $100,000, max trade: $1,000
+        // One unit: 1 share of a at $50 + 2 shares of b at $25 = $100
+        // Should trade: 10 shares of a, 20 shares of b
+        let (a, b) = sizer.calculate_pair_size(50.0, 25.0, 2.0, 100_000.0);
+        assert_eq!((a, b), (10, 20));
+    }
+
+    #[test]
+    fn test_calculate_pair_size_too_small() {
+        let sizer = PositionSizer::new(1.0, 10.0);
+
+        // Hedge ratio so small that b rounds to 0 shares
+        let (a, b) = sizer.calculate_pair_size(100.0, 100.0, 0.01, 100_000.0);
+        assert_eq!((a, b), (0, 0));
+    }
+
+    #[test]
+    fn test_calculate_pair_size_negative_hedge() {
+        let sizer = PositionSizer::new(1.0, 10.0);
+
+        // Same unit value as with a hedge ratio of 2, b is traded alongside a
+        let (a, b) = sizer.calculate_pair_size(50.0, 25.0, -2.0, 100_000.0);
+        assert_eq!((a, b), (10, -20));
+    }
 }
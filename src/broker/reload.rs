@@ -0,0 +1,304 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::broker::position_sizing::PositionSizer;
+use crate::broker::strategy::StrategyConfig;
+use crate::error::CLIError;
+use crate::settings::Settings;
+use crate::wrangling::buffers::BUFFER_SIZE;
+
+/// How often the config files are checked for changes
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ReloadSettings {
+    pub interval_secs: u64,
+}
+
+impl Default for ReloadSettings {
+    fn default() -> Self {
+        Self { interval_secs: 5 }
+    }
+}
+
+/// Parameters that can be changed while trading live
+///
+/// Named like their settings, so changes are logged by the key that was edited.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LiveParams {
+    /// Unset while the evaluator's default strategy is traded
+    pub live_strategy: Option<StrategyConfig>,
+    pub max_trade_percent: f64,
+    pub max_position_percent: f64,
+}
+
+impl LiveParams {
+    /// Parameters of `settings`, keeping the strategy of `current` if none is set
+    pub fn from_settings(settings: &Settings, current: &LiveParams) -> Self {
+        Self {
+            live_strategy: settings
+                .live_strategy
+                .clone()
+                .or_else(|| current.live_strategy.clone()),
+            max_trade_percent: settings.max_trade_percent,
+            max_position_percent: settings.max_position_percent,
+        }
+    }
+
+    pub fn position_sizer(&self) -> PositionSizer {
+        PositionSizer::new(self.max_trade_percent, self.max_position_percent)
+    }
+
+    /// Every problem of the strategy and the position limits
+    pub fn validate(&self) -> Result<(), CLIError> {
+        let mut problems = self.position_sizer().problems();
+        if let Some(strategy) = &self.live_strategy {
+            for problem in strategy.problems() {
+                problems.push(format!("live_strategy: {}", problem));
+            }
+            let lookback = strategy.lookback();
+            if lookback > BUFFER_SIZE {
+                problems.push(format!(
+                    "live_strategy: needs {} bars, only {} are buffered",
+                    lookback, BUFFER_SIZE
+                ));
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(CLIError::InvalidSettings(problems))
+        }
+    }
+
+    /// Values that differ in `new`, by their dotted key
+    pub fn changes(&self, new: &LiveParams) -> Vec<Change> {
+        let old = leaves(self);
+        let new = leaves(new);
+        let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        keys.into_iter()
+            .filter(|key| old.get(*key) != new.get(*key))
+            .map(|key| Change {
+                key: key.clone(),
+                old: old.get(key).cloned(),
+                new: new.get(key).cloned(),
+            })
+            .collect()
+    }
+}
+
+/// One changed value, `None` where it is not set
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub key: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unset = "unset".to_string();
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.key,
+            self.old.as_ref().unwrap_or(&unset),
+            self.new.as_ref().unwrap_or(&unset)
+        )
+    }
+}
+
+// Scalar values by dotted key, unset options are left out
+fn leaves(params: &LiveParams) -> BTreeMap<String, String> {
+    let mut leaves = BTreeMap::new();
+    // Only non-finite floats fail to serialize, validation rejects those
+    if let Ok(value) = serde_json::to_value(params) {
+        flatten("", &value, &mut leaves);
+    }
+    leaves
+}
+
+fn flatten(key: &str, value: &serde_json::Value, leaves: &mut BTreeMap<String, String>) {
+    let join = |part: &str| {
+        if key.is_empty() {
+            part.to_string()
+        } else {
+            format!("{}.{}", key, part)
+        }
+    };
+    match value {
+        serde_json::Value::Object(map) => {
+            for (part, value) in map {
+                flatten(&join(part), value, leaves);
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for (i, value) in items.iter().enumerate() {
+                flatten(&format!("{}[{}]", key, i), value, leaves);
+            }
+        }
+        serde_json::Value::Null => {}
+        serde_json::Value::String(text) => {
+            leaves.insert(key.to_string(), text.clone());
+        }
+        scalar => {
+            leaves.insert(key.to_string(), scalar.to_string());
+        }
+    }
+}
+
+/// Notices when the config files are edited
+#[derive(Debug)]
+pub struct ConfigWatcher {
+    files: Vec<PathBuf>,
+    modified: Vec<Option<SystemTime>>,
+}
+
+impl ConfigWatcher {
+    pub fn new(files: Vec<PathBuf>) -> Self {
+        let modified = files.iter().map(|path| modified(path)).collect();
+        Self { files, modified }
+    }
+
+    /// Whether a file was modified, created or removed since the last call
+    pub fn changed(&mut self) -> bool {
+        let modified: Vec<Option<SystemTime>> =
+            self.files.iter().map(|path| modified(path)).collect();
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::indicator_config::{IndicatorConfig, SMAConfig};
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    fn params(long_range: i32, max_position_percent: f64) -> LiveParams {
+        LiveParams {
+            live_strategy: Some(StrategyConfig::Trend(IndicatorConfig::sma_only(
+                SMAConfig {
+                    long_range,
+                    short_range: 5,
+                },
+            ))),
+            max_trade_percent: 1.0,
+            max_position_percent,
+        }
+    }
+
+    #[test]
+    fn test_changes_by_key() {
+        let old = params(20, 10.0);
+        assert!(old.changes(&old).is_empty());
+
+        let changes = old.changes(&params(30, 5.0));
+        let logged: Vec<String> = changes.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            logged,
+            vec![
+                "live_strategy.sma.long_range: 20 -> 30",
+                "max_position_percent: 10.0 -> 5.0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_changes_of_strategy_kind() {
+        let mut new = params(20, 10.0);
+        new.live_strategy = Some(StrategyConfig::Pairs(
+            crate::broker::strategy::PairsConfig {
+                lookback: 30,
+                entry_z: 2.0,
+                exit_z: 0.5,
+            },
+        ));
+        let changes = params(20, 10.0).changes(&new);
+        assert!(changes.contains(&Change {
+            key: "live_strategy.strategy".to_string(),
+            old: Some("trend".to_string()),
+            new: Some("pairs".to_string()),
+        }));
+        assert!(changes.contains(&Change {
+            key: "live_strategy.lookback".to_string(),
+            old: None,
+            new: Some("30".to_string()),
+        }));
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_limits_and_strategy() {
+        assert!(params(20, 10.0).validate().is_ok());
+        match params(3, 0.5).validate() {
+            Err(CLIError::InvalidSettings(problems)) => {
+                assert_eq!(problems.len(), 2);
+                assert!(problems[0].starts_with("max_position_percent"));
+                assert!(problems[1].starts_with("live_strategy: sma"));
+            }
+            other => panic!("Expected invalid settings, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unset_strategy_not_validated() {
+        let unset = LiveParams {
+            live_strategy: None,
+            ..params(20, 10.0)
+        };
+        assert!(unset.validate().is_ok());
+
+        let changes = params(20, 10.0).changes(&unset);
+        assert!(changes.contains(&Change {
+            key: "live_strategy.sma.long_range".to_string(),
+            old: Some("20".to_string()),
+            new: None,
+        }));
+    }
+
+    #[test]
+    fn test_validate_rejects_lookback_beyond_buffer() {
+        // A long SMA needs one bar more than its range
+        assert!(params(BUFFER_SIZE as i32 - 1, 10.0).validate().is_ok());
+        match params(BUFFER_SIZE as i32, 10.0).validate() {
+            Err(CLIError::InvalidSettings(problems)) => {
+                assert_eq!(problems.len(), 1);
+                assert!(problems[0].contains("only 100 are buffered"));
+            }
+            other => panic!("Expected invalid settings, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_watcher_notices_edits() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let dir = tempdir()?;
+        let path = dir.path().join("default.toml");
+        fs::write(&path, "max_trade_percent = 1.0")?;
+
+        let mut watcher = ConfigWatcher::new(vec![path.clone()]);
+        assert!(!watcher.changed());
+
+        let file = fs::File::options().write(true).open(&path)?;
+        file.set_modified(SystemTime::now() + Duration::from_secs(10))?;
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        fs::remove_file(&path)?;
+        assert!(watcher.changed());
+        Ok(())
+    }
+}
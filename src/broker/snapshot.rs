@@ -0,0 +1,250 @@
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use apca::data::v2::stream::Bar;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+
+use crate::broker::execution::TradeRecord;
+use crate::broker::strategy::StrategyConfig;
+use crate::wrangling::buffers::FrameState;
+
+/// Where live state is kept and how old it may get
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SnapshotSettings {
+    /// JSON file the state is written to
+    pub path: String,
+    /// Seconds between two snapshots
+    pub interval_secs: u64,
+    /// Symbols whose last bar is older than this are backfilled, in minutes
+    pub max_age_minutes: i64,
+    /// Most recent trades and equity points kept in a snapshot, 0 keeps none
+    pub max_history: usize,
+}
+
+impl Default for SnapshotSettings {
+    fn default() -> Self {
+        Self {
+            path: "data/live_state.json".to_string(),
+            interval_secs: 60,
+            max_age_minutes: 5,
+            max_history: 1000,
+        }
+    }
+}
+
+/// Everything the evaluator needs to continue trading after a restart
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiveState {
+    pub taken_at: DateTime<Utc>,
+    pub strategy: Option<StrategyConfig>,
+    pub cash: Option<f64>,
+    pub symbols: Vec<SymbolState>,
+    /// Most recent orders placed
+    #[serde(default)]
+    pub trades: Vec<TradeRecord>,
+    /// Most recent portfolio values at the close of a bar
+    #[serde(default)]
+    pub equity: Vec<(DateTime<Utc>, f64)>,
+    /// Symbols paused through the control API
+    #[serde(default)]
+    pub paused: Vec<String>,
+}
+
+/// Buffered bars of one symbol
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SymbolState {
+    pub symbol: String,
+    /// Entry timeframe bars, oldest first
+    pub bars: Vec<Bar>,
+    /// Entry bar still being built from base bars
+    #[serde(default)]
+    pub entry: Option<Bar>,
+    /// Timestamp of the last base bar
+    #[serde(default)]
+    pub last_bar_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub timeframes: Vec<FrameState>,
+    pub last_price: Option<f64>,
+}
+
+impl LiveState {
+    /// Read the state saved at `path`, `None` if there is none
+    pub fn load(path: &str) -> Result<Option<Self>, Box<dyn std::error::Error + Send + Sync>> {
+        if !Path::new(path).exists() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(path)?;
+        Ok(Some(serde_json::from_str(&text)?))
+    }
+
+    /// Write the state to `path`
+    ///
+    /// The file is replaced in one step, a crash while saving keeps the
+    /// previous snapshot.
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(dir) = Path::new(path).parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let partial = format!("{}.partial", path);
+        fs::write(&partial, serde_json::to_vec(self)?)?;
+        fs::rename(&partial, path)?;
+        Ok(())
+    }
+
+    /// Symbols whose latest bar is older than `max_age` at `now`, with its time
+    ///
+    /// These miss the bars received while the trader was down.
+    pub fn stale(&self, now: DateTime<Utc>, max_age: Duration) -> Vec<(String, DateTime<Utc>)> {
+        self.symbols
+            .iter()
+            .filter_map(|state| {
+                let last = state
+                    .last_bar_at
+                    .or_else(|| Some(state.bars.last()?.timestamp))?;
+                (now - last > max_age).then(|| (state.symbol.clone(), last))
+            })
+            .collect()
+    }
+}
+
+/// Decides when the next snapshot is due
+#[derive(Debug)]
+pub struct Snapshotter {
+    pub path: String,
+    pub max_history: usize,
+    interval: std::time::Duration,
+    last: Option<Instant>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl Snapshotter {
+    pub fn new(settings: &SnapshotSettings) -> Self {
+        Self {
+            path: settings.path.clone(),
+            max_history: settings.max_history,
+            interval: std::time::Duration::from_secs(settings.interval_secs),
+            last: None,
+            writer: None,
+        }
+    }
+
+    /// Whether a snapshot is due at `now`, the first one always is
+    pub fn due(&mut self, now: Instant) -> bool {
+        if self
+            .last
+            .is_some_and(|last| now.duration_since(last) < self.interval)
+        {
+            return false;
+        }
+        self.last = Some(now);
+        true
+    }
+
+    /// Whether the previous snapshot is still being written
+    pub fn writing(&self) -> bool {
+        self.writer
+            .as_ref()
+            .is_some_and(|writer| !writer.is_finished())
+    }
+
+    /// Write `state` on a blocking thread, without holding up the caller
+    pub fn write(&mut self, state: LiveState) {
+        let path = self.path.clone();
+        self.writer = Some(tokio::task::spawn_blocking(move || {
+            if let Err(e) = state.save(&path) {
+                tracing::error!("Failed to save live state to {}: {:?}", path, e);
+            }
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mocking::mock::values_to_bar;
+    use chrono::TimeZone;
+    use tempfile::tempdir;
+
+    fn state(last_bars: &[(&str, DateTime<Utc>)]) -> LiveState {
+        LiveState {
+            taken_at: Utc::now(),
+            strategy: None,
+            cash: Some(1000.0),
+            symbols: last_bars
+                .iter()
+                .map(|(symbol, timestamp)| {
+                    let bar = values_to_bar(symbol, *timestamp, 1.0, 2.0, 2.0, 1.0, 1.0).unwrap();
+                    SymbolState {
+                        symbol: symbol.to_string(),
+                        bars: vec![bar],
+                        entry: None,
+                        last_bar_at: None,
+                        timeframes: vec![],
+                        last_price: Some(2.0),
+                    }
+                })
+                .collect(),
+            trades: vec![],
+            equity: vec![(Utc::now(), 1000.0)],
+            paused: vec![],
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let dir = tempdir()?;
+        let path = dir.path().join("state").join("live.json");
+        let path = path.to_str().unwrap();
+        assert!(LiveState::load(path)?.is_none());
+
+        let now = Utc.with_ymd_and_hms(2024, 1, 2, 15, 0, 0).unwrap();
+        state(&[("AAA", now)]).save(path)?;
+        let loaded = LiveState::load(path)?.expect("Snapshot not saved");
+        assert_eq!(loaded.cash, Some(1000.0));
+        assert_eq!(loaded.symbols[0].bars[0].timestamp, now);
+        assert_eq!(loaded.symbols[0].bars[0].close_price.to_f64(), Some(2.0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_written_in_background() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let dir = tempdir()?;
+        let path = dir.path().join("live.json");
+        let settings = SnapshotSettings {
+            path: path.to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+        let mut snapshots = Snapshotter::new(&settings);
+        snapshots.write(state(&[("AAA", Utc::now())]));
+        while snapshots.writing() {
+            tokio::task::yield_now().await;
+        }
+        let loaded = LiveState::load(&settings.path)?.expect("Snapshot not written");
+        assert_eq!(loaded.symbols[0].symbol, "AAA");
+        Ok(())
+    }
+
+    #[test]
+    fn test_stale_symbols() {
+        let now = Utc.with_ymd_and_hms(2024, 1, 2, 15, 0, 0).unwrap();
+        let old = now - Duration::minutes(30);
+        let saved = state(&[("AAA", now - Duration::minutes(1)), ("BBB", old)]);
+        assert_eq!(
+            saved.stale(now, Duration::minutes(5)),
+            vec![("BBB".to_string(), old)]
+        );
+    }
+
+    #[test]
+    fn test_snapshot_interval() {
+        let mut snapshots = Snapshotter::new(&SnapshotSettings::default());
+        let start = Instant::now();
+        assert!(snapshots.due(start));
+        assert!(!snapshots.due(start + std::time::Duration::from_secs(30)));
+        assert!(snapshots.due(start + std::time::Duration::from_secs(60)));
+    }
+}
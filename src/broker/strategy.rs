@@ -0,0 +1,596 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::broker::indicator_config::{BollingerConfig, IndicatorConfig, RSIConfig, SMAConfig};
+use crate::pattern::signal::CombineRule;
+
+/// Strategy family selected with `strategy` in the settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StrategyKind {
+    Trend,
+    MeanReversion,
+    Breakout,
+    Pairs,
+    Ensemble,
+}
+
+impl StrategyKind {
+    /// Whether its signals are computed by the Indicator service, pairs
+    /// compute their spread themselves
+    pub fn uses_indicators(self) -> bool {
+        self != StrategyKind::Pairs
+    }
+}
+
+/// Parameters of one strategy run, as optimized by the evaluation loop
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum StrategyConfig {
+    /// Trend following on the combined indicator signals
+    Trend(IndicatorConfig),
+    /// Bollinger band and RSI mean reversion
+    MeanReversion(MeanReversionConfig),
+    /// Donchian channel breakout
+    Breakout(BreakoutConfig),
+    /// Spread z-score of the two symbols configured in `pair`
+    Pairs(PairsConfig),
+    /// Vote of several single-symbol strategies
+    Ensemble(EnsembleConfig),
+}
+
+/// Buy below the lower band when oversold, exit at the middle band or on RSI recovery
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MeanReversionConfig {
+    pub bollinger: BollingerConfig,
+    pub rsi: RSIConfig,
+    pub exit_rsi: f64,
+}
+
+/// Enter on an N-bar high, exit on an M-bar low
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BreakoutConfig {
+    pub entry_period: i32,
+    pub exit_period: i32,
+    pub momentum: Option<MomentumFilter>,
+    pub volatility: Option<VolatilityFilter>,
+}
+
+/// Only enter when the rate of change over `period` reaches `min_rate_of_change`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MomentumFilter {
+    pub period: i32,
+    pub min_rate_of_change: f64,
+}
+
+/// Only enter when standard deviation over `period`, relative to price, is within `min..=max`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VolatilityFilter {
+    pub period: i32,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Rolling hedge ratio over `lookback` bars, enter at `entry_z`, exit at `exit_z`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PairsConfig {
+    pub lookback: i32,
+    pub entry_z: f64,
+    pub exit_z: f64,
+}
+
+/// Strategies voting on the same buffer, combined by `rule`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnsembleConfig {
+    pub members: Vec<EnsembleMember>,
+    pub rule: CombineRule,
+}
+
+/// One voting strategy of an ensemble
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnsembleMember {
+    pub strategy: StrategyConfig,
+    pub weight: f64,
+}
+
+impl MeanReversionConfig {
+    pub fn random(rng: &mut impl Rng) -> Self {
+        Self {
+            bollinger: BollingerConfig::random(rng),
+            rsi: RSIConfig::random(rng),
+            exit_rsi: rng.random_range(45..=60) as f64,
+        }
+    }
+
+    pub fn grid() -> Vec<Self> {
+        let mut grid = vec![];
+        for period in (10..=30).step_by(5) {
+            for step in 0..=4 {
+                for (oversold, overbought) in [(20.0, 80.0), (25.0, 75.0), (30.0, 70.0)] {
+                    for exit_rsi in [50.0, 55.0] {
+                        grid.push(Self {
+                            bollinger: BollingerConfig {
+                                period,
+                                multiplier: 1.5 + step as f64 * 0.25,
+                            },
+                            rsi: RSIConfig {
+                                period: 14,
+                                oversold,
+                                overbought,
+                            },
+                            exit_rsi,
+                        });
+                    }
+                }
+            }
+        }
+        grid
+    }
+
+    pub fn lookback(&self) -> usize {
+        self.bollinger.lookback().max(self.rsi.lookback())
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = self.bollinger.problems();
+        problems.extend(self.rsi.problems());
+        if !(0.0..=100.0).contains(&self.exit_rsi) {
+            problems.push(format!(
+                "exit_rsi must be in [0, 100], is {}",
+                self.exit_rsi
+            ));
+        }
+        problems
+    }
+}
+
+impl BreakoutConfig {
+    pub fn random(rng: &mut impl Rng) -> Self {
+        let entry_period = rng.random_range(10..=60);
+        let exit_period = rng.random_range(5..=entry_period);
+
+        let momentum = rng.random_bool(0.5).then(|| MomentumFilter {
+            period: rng.random_range(5..=30),
+            min_rate_of_change: rng.random_range(0..=10) as f64,
+        });
+        let volatility = rng.random_bool(0.5).then(|| {
+            let min = rng.random_range(0..=10) as f64 / 1000.0;
+            VolatilityFilter {
+                period: rng.random_range(10..=30),
+                min,
+                max: min + rng.random_range(5..=50) as f64 / 1000.0,
+            }
+        });
+
+        Self {
+            entry_period,
+            exit_period,
+            momentum,
+            volatility,
+        }
+    }
+
+    pub fn grid() -> Vec<Self> {
+        let mut grid = vec![];
+        for entry_period in (10..=60).step_by(10) {
+            for exit_period in (5..=entry_period).step_by(5) {
+                for momentum in [
+                    None,
+                    Some(MomentumFilter {
+                        period: 10,
+                        min_rate_of_change: 0.0,
+                    }),
+                ] {
+                    grid.push(Self {
+                        entry_period,
+                        exit_period,
+                        momentum,
+                        volatility: None,
+                    });
+                }
+            }
+        }
+        grid
+    }
+
+    pub fn lookback(&self) -> usize {
+        let filters = [
+            self.momentum.as_ref().map(|m| m.period as usize + 1),
+            self.volatility.as_ref().map(|v| v.period as usize),
+        ];
+        filters
+            .into_iter()
+            .flatten()
+            .chain([self.entry_period.max(self.exit_period) as usize + 1])
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if self.entry_period < 1 || self.exit_period < 1 {
+            problems.push("entry_period and exit_period must be at least 1".to_string());
+        }
+        if let Some(momentum) = &self.momentum {
+            if momentum.period < 1 {
+                problems.push("momentum.period must be at least 1".to_string());
+            }
+        }
+        if let Some(volatility) = &self.volatility {
+            if volatility.period < 2 {
+                problems.push("volatility.period must be at least 2".to_string());
+            }
+            if volatility.min > volatility.max {
+                problems.push("volatility.min must not be above volatility.max".to_string());
+            }
+        }
+        problems
+    }
+}
+
+impl PairsConfig {
+    pub fn random(rng: &mut impl Rng) -> Self {
+        let entry_z = rng.random_range(10..=30) as f64 / 10.0;
+        let exit_z = rng.random_range(0..=(entry_z * 10.0) as i32 - 5) as f64 / 10.0;
+
+        Self {
+            lookback: rng.random_range(20..=90),
+            entry_z,
+            exit_z,
+        }
+    }
+
+    pub fn grid() -> Vec<Self> {
+        let mut grid = vec![];
+        for lookback in (20..=90).step_by(10) {
+            for entry_z in [1.5, 2.0, 2.5, 3.0] {
+                for exit_z in [0.0, 0.5, 1.0] {
+                    grid.push(Self {
+                        lookback,
+                        entry_z,
+                        exit_z,
+                    });
+                }
+            }
+        }
+        grid
+    }
+
+    pub fn lookback(&self) -> usize {
+        self.lookback as usize
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        // The hedge ratio is a regression over the lookback
+        if self.lookback < 2 {
+            problems.push("lookback must be at least 2".to_string());
+        }
+        if !(0.0 <= self.exit_z && self.exit_z < self.entry_z) {
+            problems.push(format!(
+                "pairs needs 0 <= exit_z < entry_z, has {} and {}",
+                self.exit_z, self.entry_z
+            ));
+        }
+        problems
+    }
+}
+
+/// Strategy kinds that can vote in an ensemble
+const MEMBER_KINDS: [StrategyKind; 3] = [
+    StrategyKind::Trend,
+    StrategyKind::MeanReversion,
+    StrategyKind::Breakout,
+];
+
+impl EnsembleConfig {
+    /// Two to four random members with equal weights
+    pub fn random(rng: &mut impl Rng) -> Self {
+        let count = rng.random_range(2..=4);
+        let members = (0..count)
+            .map(|_| EnsembleMember {
+                strategy: StrategyConfig::random(
+                    MEMBER_KINDS[rng.random_range(0..MEMBER_KINDS.len())],
+                    rng,
+                ),
+                weight: 1.0,
+            })
+            .collect();
+
+        Self {
+            members,
+            rule: Self::random_rule(rng),
+        }
+    }
+
+    /// Members picked from backtest results, weighted by their gain
+    ///
+    /// Only strategies that made money and can vote are considered, and the
+    /// best `max_members` of them are kept. Returns `None` with fewer than two.
+    pub fn from_performance(results: &[(f64, StrategyConfig)], max_members: usize) -> Option<Self> {
+        let mut candidates: Vec<&(f64, StrategyConfig)> = results
+            .iter()
+            .filter(|(gain, config)| *gain > 0.0 && MEMBER_KINDS.contains(&config.kind()))
+            .collect();
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+        candidates.truncate(max_members);
+
+        if candidates.len() < 2 {
+            return None;
+        }
+
+        let total: f64 = candidates.iter().map(|(gain, _)| gain).sum();
+        let members = candidates
+            .into_iter()
+            .map(|(gain, config)| EnsembleMember {
+                strategy: config.clone(),
+                weight: gain / total,
+            })
+            .collect();
+
+        Some(Self {
+            members,
+            rule: CombineRule::Weighted { threshold: 0.5 },
+        })
+    }
+
+    fn random_rule(rng: &mut impl Rng) -> CombineRule {
+        match rng.random_range(0..3) {
+            0 => CombineRule::All,
+            1 => CombineRule::Majority,
+            _ => CombineRule::Weighted {
+                threshold: (rng.random_range(0.1..=0.9_f64) * 10.0).round() / 10.0,
+            },
+        }
+    }
+
+    pub fn lookback(&self) -> usize {
+        self.members
+            .iter()
+            .map(|m| m.strategy.lookback())
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if self.members.is_empty() {
+            problems.push("an ensemble needs at least one member".to_string());
+        }
+        for (i, member) in self.members.iter().enumerate() {
+            if !MEMBER_KINDS.contains(&member.strategy.kind()) {
+                problems.push(format!(
+                    "members[{}]: {:?} can't vote in an ensemble",
+                    i,
+                    member.strategy.kind()
+                ));
+                continue;
+            }
+            if member.weight < 0.0 {
+                problems.push(format!("members[{}]: weight must not be negative", i));
+            }
+            for problem in member.strategy.problems() {
+                problems.push(format!("members[{}]: {}", i, problem));
+            }
+        }
+        problems
+    }
+}
+
+/// Live parameters to start from when no strategy is configured
+impl Default for StrategyConfig {
+    fn default() -> Self {
+        StrategyConfig::Trend(IndicatorConfig::sma_only(SMAConfig {
+            long_range: 20,
+            short_range: 5,
+        }))
+    }
+}
+
+impl StrategyConfig {
+    pub fn random(kind: StrategyKind, rng: &mut impl Rng) -> Self {
+        match kind {
+            StrategyKind::Trend => StrategyConfig::Trend(IndicatorConfig::random(rng)),
+            StrategyKind::MeanReversion => {
+                StrategyConfig::MeanReversion(MeanReversionConfig::random(rng))
+            }
+            StrategyKind::Breakout => StrategyConfig::Breakout(BreakoutConfig::random(rng)),
+            StrategyKind::Pairs => StrategyConfig::Pairs(PairsConfig::random(rng)),
+            StrategyKind::Ensemble => StrategyConfig::Ensemble(EnsembleConfig::random(rng)),
+        }
+    }
+
+    pub fn grid(kind: StrategyKind) -> Vec<Self> {
+        match kind {
+            StrategyKind::Trend => IndicatorConfig::grid()
+                .into_iter()
+                .map(StrategyConfig::Trend)
+                .collect(),
+            StrategyKind::MeanReversion => MeanReversionConfig::grid()
+                .into_iter()
+                .map(StrategyConfig::MeanReversion)
+                .collect(),
+            StrategyKind::Breakout => BreakoutConfig::grid()
+                .into_iter()
+                .map(StrategyConfig::Breakout)
+                .collect(),
+            StrategyKind::Pairs => PairsConfig::grid()
+                .into_iter()
+                .map(StrategyConfig::Pairs)
+                .collect(),
+            // Ensembles are built from other results, there is nothing to sweep
+            StrategyKind::Ensemble => vec![],
+        }
+    }
+
+    pub fn kind(&self) -> StrategyKind {
+        match self {
+            StrategyConfig::Trend(_) => StrategyKind::Trend,
+            StrategyConfig::MeanReversion(_) => StrategyKind::MeanReversion,
+            StrategyConfig::Breakout(_) => StrategyKind::Breakout,
+            StrategyConfig::Pairs(_) => StrategyKind::Pairs,
+            StrategyConfig::Ensemble(_) => StrategyKind::Ensemble,
+        }
+    }
+
+    /// Bars needed before the strategy can produce a signal
+    pub fn lookback(&self) -> usize {
+        match self {
+            StrategyConfig::Trend(config) => config.lookback(),
+            StrategyConfig::MeanReversion(config) => config.lookback(),
+            StrategyConfig::Breakout(config) => config.lookback(),
+            StrategyConfig::Pairs(config) => config.lookback(),
+            StrategyConfig::Ensemble(config) => config.lookback(),
+        }
+    }
+
+    /// Parameters that can't produce signals, empty if there are none
+    pub fn problems(&self) -> Vec<String> {
+        match self {
+            StrategyConfig::Trend(config) => config.problems(),
+            StrategyConfig::MeanReversion(config) => config.problems(),
+            StrategyConfig::Breakout(config) => config.problems(),
+            StrategyConfig::Pairs(config) => config.problems(),
+            StrategyConfig::Ensemble(config) => config.problems(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_matches_kind() {
+        for kind in [
+            StrategyKind::Trend,
+            StrategyKind::MeanReversion,
+            StrategyKind::Breakout,
+            StrategyKind::Pairs,
+            StrategyKind::Ensemble,
+        ] {
+            assert_eq!(StrategyConfig::random(kind, &mut rand::rng()).kind(), kind);
+        }
+    }
+
+    #[test]
+    fn test_mean_reversion_grid() {
+        let grid = StrategyConfig::grid(StrategyKind::MeanReversion);
+        assert!(!grid.is_empty());
+        assert!(grid
+            .iter()
+            .all(|c| matches!(c, StrategyConfig::MeanReversion(_))));
+    }
+
+    #[test]
+    fn test_breakout_lookback() {
+        let config = BreakoutConfig {
+            entry_period: 20,
+            exit_period: 10,
+            momentum: Some(MomentumFilter {
+                period: 30,
+                min_rate_of_change: 0.0,
+            }),
+            volatility: None,
+        };
+        assert_eq!(config.lookback(), 31);
+    }
+
+    #[test]
+    fn test_random_reproducible_from_seed() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let config =
+            |seed| StrategyConfig::random(StrategyKind::Ensemble, &mut StdRng::seed_from_u64(seed));
+        assert_eq!(config(42), config(42));
+    }
+
+    #[test]
+    fn test_pairs_random_exit_inside_entry() {
+        for _ in 0..100 {
+            let config = PairsConfig::random(&mut rand::rng());
+            assert!(config.exit_z < config.entry_z);
+        }
+    }
+
+    #[test]
+    fn test_ensemble_from_performance() {
+        let results = vec![
+            (
+                300.0,
+                StrategyConfig::random(StrategyKind::Trend, &mut rand::rng()),
+            ),
+            (
+                -50.0,
+                StrategyConfig::random(StrategyKind::Breakout, &mut rand::rng()),
+            ),
+            (
+                100.0,
+                StrategyConfig::random(StrategyKind::MeanReversion, &mut rand::rng()),
+            ),
+            (
+                500.0,
+                StrategyConfig::random(StrategyKind::Pairs, &mut rand::rng()),
+            ),
+        ];
+
+        let ensemble = EnsembleConfig::from_performance(&results, 5).unwrap();
+        // Losing runs and pairs don't vote
+        assert_eq!(ensemble.members.len(), 2);
+        assert_eq!(ensemble.members[0].weight, 0.75);
+        assert_eq!(ensemble.members[1].weight, 0.25);
+
+        assert!(EnsembleConfig::from_performance(&results[..2], 5).is_none());
+    }
+
+    #[test]
+    fn test_generated_configs_have_no_problems() {
+        for kind in [
+            StrategyKind::Trend,
+            StrategyKind::MeanReversion,
+            StrategyKind::Breakout,
+            StrategyKind::Pairs,
+            StrategyKind::Ensemble,
+        ] {
+            for _ in 0..20 {
+                let config = StrategyConfig::random(kind, &mut rand::rng());
+                assert_eq!(config.problems(), Vec::<String>::new(), "{:?}", config);
+            }
+        }
+        assert!(StrategyConfig::default().problems().is_empty());
+    }
+
+    #[test]
+    fn test_problems_of_nested_members() {
+        let config = StrategyConfig::Ensemble(EnsembleConfig {
+            members: vec![
+                EnsembleMember {
+                    strategy: StrategyConfig::Trend(IndicatorConfig::sma_only(SMAConfig {
+                        long_range: 5,
+                        short_range: 20,
+                    })),
+                    weight: 1.0,
+                },
+                EnsembleMember {
+                    strategy: StrategyConfig::Pairs(PairsConfig::random(&mut rand::rng())),
+                    weight: 1.0,
+                },
+            ],
+            rule: CombineRule::Majority,
+        });
+        let problems = config.problems();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("members[0]: sma"));
+        assert!(problems[1].starts_with("members[1]: Pairs"));
+    }
+
+    #[test]
+    fn test_serialize_tagged() {
+        let config = StrategyConfig::MeanReversion(MeanReversionConfig::random(&mut rand::rng()));
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(json["strategy"], "mean_reversion");
+
+        let back: StrategyConfig = serde_json::from_value(json).unwrap();
+        assert_eq!(back, config);
+    }
+}
@@ -0,0 +1,297 @@
+use clap::{Args, Parser, Subcommand};
+
+/// Settings changed from the command line, `(key, value)` like `eval_iterations`
+pub type Overrides = Vec<(String, config::Value)>;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "trader-bot",
+    version,
+    about = "Backtest, optimize and trade strategies"
+)]
+pub struct Cli {
+    /// Overlay `config/<profile>.toml`, e.g. dev or live, `APP_PROFILE` if not set
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+    /// SurrealDB URL, e.g. `mem://` or `surrealkv://data/results`
+    #[arg(long, global = true)]
+    pub db_url: Option<String>,
+    /// Runs `optimize` with `use_mock_data`, `live` otherwise
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Trade the live Alpaca stream
+    Live(LiveArgs),
+    /// Replay historical bars with one config
+    Backtest(BacktestArgs),
+    /// Search for the best config over random runs
+    Optimize(OptimizeArgs),
+    /// Store the bars of a CSV file in the bar store
+    ImportData(ImportArgs),
+    /// Download Alpaca bars into the bar store
+    Download(DownloadArgs),
+    /// Inspect stored run results
+    #[command(subcommand)]
+    Results(ResultsCommand),
+    /// Manage the results database
+    #[command(subcommand)]
+    Db(DbCommand),
+    /// Inspect the depot
+    #[command(subcommand)]
+    Depot(DepotCommand),
+    /// Inspect the settings
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(Debug, Args)]
+pub struct LiveArgs {
+    /// Symbols to subscribe to, comma separated
+    #[arg(long, value_delimiter = ',')]
+    pub symbols: Vec<String>,
+    /// Append every received message to this file
+    #[arg(long)]
+    pub record: Option<String>,
+    /// File the live state is saved to and restored from
+    #[arg(long)]
+    pub snapshot: Option<String>,
+}
+
+/// What is replayed and how runs are configured
+#[derive(Debug, Args)]
+pub struct ReplayArgs {
+    #[arg(long, value_parser = ["trend", "mean_reversion", "breakout", "pairs", "ensemble"])]
+    pub strategy: Option<String>,
+    /// Symbol of the mock file
+    #[arg(long)]
+    pub symbol: Option<String>,
+    /// CSV file imported before the runs
+    #[arg(long)]
+    pub file: Option<String>,
+    /// Replay these symbols from the bar store instead of the mock file
+    #[arg(long, value_delimiter = ',')]
+    pub replay_symbols: Vec<String>,
+    /// Bar store partition, e.g. `1d`
+    #[arg(long)]
+    pub timeframe: Option<String>,
+    /// Only replay bars from this RFC 3339 time on
+    #[arg(long)]
+    pub start: Option<String>,
+    /// Only replay bars up to this RFC 3339 time
+    #[arg(long)]
+    pub end: Option<String>,
+    /// Aggregate the bars to this size, e.g. `1w`
+    #[arg(long)]
+    pub resample: Option<String>,
+    /// RNG seed of the first run, at most 2^63 - 1 as settings hold no larger integers
+    #[arg(long, value_parser = clap::value_parser!(u64).range(..=i64::MAX as u64))]
+    pub seed: Option<u64>,
+    /// Store the runs under this study
+    #[arg(long)]
+    pub study: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct BacktestArgs {
+    #[command(flatten)]
+    pub replay: ReplayArgs,
+    /// JSON file with the strategy config
+    #[arg(long, conflicts_with = "result")]
+    pub config: Option<String>,
+    /// Id of a stored result whose config is replayed, the best result if
+    /// neither this nor `--config` is given
+    #[arg(long)]
+    pub result: Option<String>,
+    /// Times the config is replayed
+    #[arg(long, default_value_t = 1)]
+    pub runs: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct OptimizeArgs {
+    #[command(flatten)]
+    pub replay: ReplayArgs,
+    #[arg(long)]
+    pub iterations: Option<usize>,
+    /// Best configs kept
+    #[arg(long)]
+    pub top_n: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+pub struct ImportArgs {
+    pub file: String,
+    pub symbol: String,
+    /// Partition the bars are stored in, `timeframe` of the settings if not set
+    #[arg(long)]
+    pub timeframe: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct DownloadArgs {
+    /// First day, `YYYY-MM-DD`
+    pub start: chrono::NaiveDate,
+    /// Day after the last one, `YYYY-MM-DD`
+    pub end: chrono::NaiveDate,
+    #[arg(required = true)]
+    pub symbols: Vec<String>,
+    #[arg(long)]
+    pub timeframe: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ResultsCommand {
+    /// Latest results, newest first
+    List {
+        #[arg(long)]
+        symbol: Option<String>,
+        /// Only results of this study
+        #[arg(long)]
+        study: Option<String>,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// A result with its metrics, fills and equity curve
+    Show { id: String },
+    /// Delete a result with its fills, equity curve and metrics
+    Delete { id: String },
+    /// Compare two studies side by side
+    Compare { a: String, b: String },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DbCommand {
+    /// Apply the pending schema migrations
+    Migrate {
+        /// Only list the pending migrations
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DepotCommand {
+    /// Cash and shares held
+    State,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the settings without secrets and every problem with them
+    Check,
+}
+
+impl Cli {
+    /// Settings set by the flags of the command
+    pub fn overrides(&self) -> Overrides {
+        let mut overrides = Overrides::new();
+        set(&mut overrides, "surreal_db_url", self.db_url.clone());
+        match &self.command {
+            Some(Command::Live(args)) => {
+                set_list(&mut overrides, "live_symbols", &args.symbols);
+                set(&mut overrides, "record_path", args.record.clone());
+                set(&mut overrides, "snapshot.path", args.snapshot.clone());
+            }
+            Some(Command::Backtest(args)) => args.replay.add_to(&mut overrides),
+            Some(Command::Optimize(args)) => {
+                args.replay.add_to(&mut overrides);
+                set(
+                    &mut overrides,
+                    "eval_iterations",
+                    args.iterations.map(|n| n as i64),
+                );
+                set(
+                    &mut overrides,
+                    "top_n_configs",
+                    args.top_n.map(|n| n as i64),
+                );
+            }
+            _ => {}
+        }
+        overrides
+    }
+}
+
+impl ReplayArgs {
+    fn add_to(&self, overrides: &mut Overrides) {
+        set(overrides, "strategy", self.strategy.clone());
+        set(overrides, "mock_symbol", self.symbol.clone());
+        set(overrides, "mock_file_path", self.file.clone());
+        set_list(overrides, "replay_symbols", &self.replay_symbols);
+        set(overrides, "timeframe", self.timeframe.clone());
+        set(overrides, "replay_start", self.start.clone());
+        set(overrides, "replay_end", self.end.clone());
+        set(overrides, "resample", self.resample.clone());
+        // The parser keeps seeds below 2^63
+        set(
+            overrides,
+            "seed",
+            self.seed.and_then(|seed| i64::try_from(seed).ok()),
+        );
+        set(overrides, "study.name", self.study.clone());
+    }
+}
+
+fn set<T: Into<config::Value>>(overrides: &mut Overrides, key: &str, value: Option<T>) {
+    if let Some(value) = value {
+        overrides.push((key.to_string(), value.into()));
+    }
+}
+
+fn set_list(overrides: &mut Overrides, key: &str, values: &[String]) {
+    if !values.is_empty() {
+        overrides.push((key.to_string(), values.to_vec().into()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seed_above_settings_range_rejected() {
+        let parse = |seed: &str| Cli::try_parse_from(["trader-bot", "backtest", "--seed", seed]);
+        let max = i64::MAX.to_string();
+        let cli = parse(&max).unwrap();
+        let overrides = cli.overrides();
+        assert_eq!(overrides[0].1.clone().into_int().unwrap(), i64::MAX);
+        assert!(parse(&(i64::MAX as u64 + 1).to_string()).is_err());
+    }
+
+    #[test]
+    fn test_flags_override_settings() {
+        let cli = Cli::parse_from([
+            "trader-bot",
+            "optimize",
+            "--strategy",
+            "breakout",
+            "--iterations",
+            "50",
+            "--replay-symbols",
+            "ORCL,MSFT",
+            "--db-url",
+            "mem://",
+        ]);
+        let overrides = cli.overrides();
+        let keys: Vec<&str> = overrides.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(
+            keys,
+            vec![
+                "surreal_db_url",
+                "strategy",
+                "replay_symbols",
+                "eval_iterations"
+            ]
+        );
+        assert_eq!(overrides[3].1.clone().into_int().unwrap(), 50);
+    }
+
+    #[test]
+    fn test_unknown_strategy_rejected() {
+        assert!(Cli::try_parse_from(["trader-bot", "backtest", "--strategy", "magic"]).is_err());
+        assert!(Cli::try_parse_from(["trader-bot", "results", "show"]).is_err());
+    }
+}
@@ -0,0 +1,557 @@
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures::Stream;
+use serde::Deserialize;
+use subtle::ConstantTimeEq;
+use tokio::sync::{broadcast, Mutex};
+use tonic::service::interceptor::InterceptedService;
+use tonic::{Request, Response, Status};
+use tracing::{info, warn};
+use trader_bot::grpc_control::init::control::{
+    self as proto,
+    control_server::{Control, ControlServer},
+    FILE_DESCRIPTOR_SET,
+};
+
+use crate::broker::actions::Alpaca;
+use crate::broker::evaluator::Evaluator;
+use crate::broker::events::{Event, EventKind, Events};
+use crate::broker::execution::{Side, TradeRecord};
+use crate::broker::reload::LiveParams;
+use crate::pattern::signal::Signal;
+use crate::settings::Secret;
+use crate::wrangling::buffers::Buffer;
+use crate::Actor;
+
+/// Where the control API of the live bot listens
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ControlSettings {
+    /// Socket address, other than localhost it needs `control_token`
+    pub addr: String,
+}
+
+/// Metadata key clients send the token in, as `Bearer <token>`
+const AUTHORIZATION: &str = "authorization";
+
+impl Default for ControlSettings {
+    fn default() -> Self {
+        Self {
+            addr: "127.0.0.1:50052".to_string(),
+        }
+    }
+}
+
+/// Serve `trader.Control` and reflection for the actor until the process ends
+///
+/// With a `token` every call must carry it, reflection included, without one
+/// only a loopback address is served.
+pub async fn serve(
+    settings: &ControlSettings,
+    token: Option<Secret>,
+    actor: Arc<Mutex<Actor>>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let addr: SocketAddr = settings.addr.parse()?;
+    if token.is_none() && !addr.ip().is_loopback() {
+        return Err(format!("Control API on {} needs a control_token", addr).into());
+    }
+    let events = actor.lock().await.evaluator.events.clone();
+    let reflection = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+        .with_service_name("trader.Control")
+        .build_v1()?;
+
+    let check = move |request: Request<()>| -> Result<Request<()>, Status> {
+        authorize(token.as_ref(), &request)?;
+        Ok(request)
+    };
+
+    info!("Control API listening on {}", addr);
+    tonic::transport::Server::builder()
+        .add_service(ControlServer::with_interceptor(
+            ControlService { actor, events },
+            check.clone(),
+        ))
+        .add_service(InterceptedService::new(reflection, check))
+        .serve(addr)
+        .await?;
+    Ok(())
+}
+
+/// Control API on the actor of the live bot
+///
+/// Calls lock the actor, so they run between two messages of the stream and
+/// never while a bar is evaluated. Depot calls are made without the lock, they
+/// don't hold up the stream.
+pub struct ControlService {
+    actor: Arc<Mutex<Actor>>,
+    /// Subscribed to without the lock, streams don't hold up trading
+    events: Events,
+}
+
+type EventStream = Pin<Box<dyn Stream<Item = Result<proto::Event, Status>> + Send>>;
+
+impl ControlService {
+    /// Depot client of the evaluator, used without holding the lock
+    async fn depot(&self) -> Alpaca {
+        self.actor.lock().await.evaluator.ap.clone()
+    }
+
+    /// Close the position in `symbol`, or every open position without one
+    ///
+    /// Paused symbols are closed as well. Orders are priced and recorded
+    /// under the lock, the depot is called without it.
+    async fn flatten_positions(&self, symbol: Option<&str>) -> Result<Vec<TradeRecord>, Status> {
+        let mut depot = self.depot().await;
+        let state = depot
+            .get_state()
+            .await
+            .ok_or_else(|| Status::unavailable("Depot not reachable"))?;
+
+        let mut orders = vec![];
+        for share in state.shares {
+            if share.count == 0 || symbol.is_some_and(|symbol| symbol != share.symbol) {
+                continue;
+            }
+            let (side, count) = if share.count > 0 {
+                (Side::Sell, share.count)
+            } else {
+                (Side::Buy, -share.count)
+            };
+            let priced = {
+                let actor = self.actor.lock().await;
+                let price = actor
+                    .evaluator
+                    .last_prices
+                    .get(&share.symbol)
+                    .copied()
+                    .unwrap_or(share.price_per_share);
+                actor.evaluator.price_order(side, &share.symbol, price)
+            };
+            let Some((order, mid)) = priced else {
+                continue;
+            };
+
+            info!("Flattening {} shares of {}", share.count, share.symbol);
+            let response =
+                Evaluator::send_order(&mut depot, side, &share.symbol, count, order.price).await;
+            let mut actor = self.actor.lock().await;
+            if let Some(record) =
+                actor
+                    .evaluator
+                    .record_order(side, &share.symbol, count, order, mid, response)
+            {
+                orders.push(record);
+            }
+        }
+        Ok(orders)
+    }
+}
+
+#[tonic::async_trait]
+impl Control for ControlService {
+    async fn list_symbols(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> Result<Response<proto::SymbolsResponse>, Status> {
+        let actor = self.actor.lock().await;
+        let mut symbols: Vec<&String> = actor.evaluator.buffer.keys().collect();
+        symbols.sort();
+        let symbols = symbols
+            .into_iter()
+            .map(|symbol| symbol_state(&actor.evaluator, symbol))
+            .collect();
+        Ok(Response::new(proto::SymbolsResponse { symbols }))
+    }
+
+    async fn get_signals(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> Result<Response<proto::SignalsResponse>, Status> {
+        let actor = self.actor.lock().await;
+        let mut signals: Vec<proto::SymbolSignal> = actor
+            .evaluator
+            .signals
+            .iter()
+            .map(|(symbol, (timestamp, signal))| proto::SymbolSignal {
+                symbol: symbol.clone(),
+                signal: signal_message(*signal) as i32,
+                timestamp: timestamp.to_rfc3339(),
+            })
+            .collect();
+        signals.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        Ok(Response::new(proto::SignalsResponse { signals }))
+    }
+
+    async fn get_positions(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> Result<Response<proto::PositionsResponse>, Status> {
+        let mut depot = self.depot().await;
+        let state = depot
+            .get_state()
+            .await
+            .ok_or_else(|| Status::unavailable("Depot not reachable"))?;
+
+        let actor = self.actor.lock().await;
+        let evaluator = &actor.evaluator;
+
+        let positions: Vec<proto::Position> = state
+            .shares
+            .iter()
+            .filter(|share| share.count != 0)
+            .map(|share| {
+                let last_price = evaluator.last_prices.get(&share.symbol).copied();
+                proto::Position {
+                    symbol: share.symbol.clone(),
+                    count: share.count,
+                    price_per_share: share.price_per_share,
+                    last_price,
+                    value: share.count as f64 * last_price.unwrap_or(share.price_per_share),
+                    paused: evaluator.paused.contains(&share.symbol),
+                }
+            })
+            .collect();
+        let portfolio_value = state.cash + positions.iter().map(|p| p.value).sum::<f64>();
+        Ok(Response::new(proto::PositionsResponse {
+            cash: state.cash,
+            positions,
+            portfolio_value,
+        }))
+    }
+
+    async fn pause_symbol(
+        &self,
+        request: Request<proto::SymbolRequest>,
+    ) -> Result<Response<proto::SymbolState>, Status> {
+        let symbol = requested_symbol(request)?;
+        let mut actor = self.actor.lock().await;
+        if actor.evaluator.paused.insert(symbol.clone()) {
+            info!("Paused trading {}", symbol);
+        }
+        Ok(Response::new(symbol_state(&actor.evaluator, &symbol)))
+    }
+
+    async fn resume_symbol(
+        &self,
+        request: Request<proto::SymbolRequest>,
+    ) -> Result<Response<proto::SymbolState>, Status> {
+        let symbol = requested_symbol(request)?;
+        let mut actor = self.actor.lock().await;
+        if actor.evaluator.paused.remove(&symbol) {
+            info!("Resumed trading {}", symbol);
+        }
+        Ok(Response::new(symbol_state(&actor.evaluator, &symbol)))
+    }
+
+    async fn flatten(
+        &self,
+        request: Request<proto::FlattenRequest>,
+    ) -> Result<Response<proto::OrdersResponse>, Status> {
+        let symbol = request.into_inner().symbol;
+        let symbol = (!symbol.is_empty()).then_some(symbol.as_str());
+        let orders = self.flatten_positions(symbol).await?;
+        Ok(Response::new(proto::OrdersResponse {
+            orders: orders.iter().map(order_message).collect(),
+        }))
+    }
+
+    async fn get_params(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> Result<Response<proto::Params>, Status> {
+        let actor = self.actor.lock().await;
+        Ok(Response::new(params_message(
+            &actor.evaluator.live_params(),
+        )?))
+    }
+
+    async fn update_params(
+        &self,
+        request: Request<proto::ParamsUpdate>,
+    ) -> Result<Response<proto::ParamsResponse>, Status> {
+        let update = request.into_inner();
+        let mut actor = self.actor.lock().await;
+        let mut params = actor.evaluator.live_params();
+        if let Some(json) = update.strategy_json {
+            params.live_strategy = Some(
+                serde_json::from_str(&json)
+                    .map_err(|e| Status::invalid_argument(format!("Invalid strategy: {}", e)))?,
+            );
+        }
+        if let Some(percent) = update.max_trade_percent {
+            params.max_trade_percent = percent;
+        }
+        if let Some(percent) = update.max_position_percent {
+            params.max_position_percent = percent;
+        }
+
+        let changes = actor
+            .evaluator
+            .apply(params)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        info!("Control API changed {} live values", changes.len());
+        Ok(Response::new(proto::ParamsResponse {
+            params: Some(params_message(&actor.evaluator.live_params())?),
+            changes: changes
+                .into_iter()
+                .map(|change| proto::ParamChange {
+                    key: change.key,
+                    old: change.old,
+                    new: change.new,
+                })
+                .collect(),
+        }))
+    }
+
+    type StreamEventsStream = EventStream;
+
+    async fn stream_events(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> Result<Response<Self::StreamEventsStream>, Status> {
+        let receiver = self.events.subscribe();
+        let stream = futures::stream::unfold(receiver, |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((Ok(event_message(event)), receiver)),
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("Event stream fell behind, {} events dropped", missed);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+/// Let a request through if it carries `token`, any request without one
+fn authorize(token: Option<&Secret>, request: &Request<()>) -> Result<(), Status> {
+    let Some(token) = token else {
+        return Ok(());
+    };
+    let sent = request
+        .metadata()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    // Compared in constant time, so the token can't be guessed byte by byte
+    let expected = token.expose().as_bytes();
+    let matches = sent.is_some_and(|sent| {
+        sent.len() == expected.len() && bool::from(sent.as_bytes().ct_eq(expected))
+    });
+    if matches {
+        Ok(())
+    } else {
+        Err(Status::unauthenticated("Missing or wrong control token"))
+    }
+}
+
+fn requested_symbol(request: Request<proto::SymbolRequest>) -> Result<String, Status> {
+    let symbol = request.into_inner().symbol;
+    if symbol.is_empty() {
+        return Err(Status::invalid_argument("Symbol is required"));
+    }
+    Ok(symbol)
+}
+
+fn symbol_state(evaluator: &Evaluator, symbol: &str) -> proto::SymbolState {
+    let timeframes = match evaluator.timeframes.get(symbol) {
+        Some(timeframes) => evaluator
+            .higher_timeframes
+            .iter()
+            .filter_map(|(name, _)| Some((name.clone(), timeframes.get(name)?.bar_count() as u32)))
+            .collect(),
+        None => Default::default(),
+    };
+    proto::SymbolState {
+        symbol: symbol.to_string(),
+        bars: evaluator.buffer.get(symbol).map_or(0, Buffer::bar_count) as u32,
+        timeframes,
+        paused: evaluator.paused.contains(symbol),
+        last_price: evaluator.last_prices.get(symbol).copied(),
+    }
+}
+
+fn params_message(params: &LiveParams) -> Result<proto::Params, Status> {
+    Ok(proto::Params {
+        strategy_json: params
+            .live_strategy
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()
+            .map_err(|e| Status::internal(e.to_string()))?,
+        max_trade_percent: params.max_trade_percent,
+        max_position_percent: params.max_position_percent,
+    })
+}
+
+fn signal_message(signal: Signal) -> proto::Signal {
+    match signal {
+        Signal::Hold => proto::Signal::Hold,
+        Signal::Buy => proto::Signal::Buy,
+        Signal::Sell => proto::Signal::Sell,
+        Signal::Exit => proto::Signal::Exit,
+    }
+}
+
+fn side_message(side: Side) -> proto::Side {
+    match side {
+        Side::Buy => proto::Side::Buy,
+        Side::Sell => proto::Side::Sell,
+    }
+}
+
+fn order_message(record: &TradeRecord) -> proto::Order {
+    proto::Order {
+        symbol: record.symbol.clone(),
+        side: side_message(record.side) as i32,
+        count: record.count,
+        price: record.price,
+    }
+}
+
+fn event_message(event: Event) -> proto::Event {
+    let kind = match event.kind {
+        EventKind::Signal(signal) => proto::event::Kind::Signal(signal_message(signal) as i32),
+        EventKind::Order(record) => proto::event::Kind::Order(order_message(&record)),
+        EventKind::Fill {
+            side,
+            count,
+            price,
+            success,
+            message,
+            cash,
+        } => proto::event::Kind::Fill(proto::Fill {
+            side: side_message(side) as i32,
+            count,
+            price,
+            success,
+            message,
+            cash,
+        }),
+        EventKind::RiskBreach(reason) => {
+            proto::event::Kind::RiskBreach(proto::RiskBreach { reason })
+        }
+    };
+    proto::Event {
+        timestamp: event.timestamp.to_rfc3339(),
+        symbol: event.symbol,
+        kind: Some(kind),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::strategy::StrategyConfig;
+    use crate::settings::Settings;
+    use apca::{ApiInfo, Client};
+    use tonic::transport::Channel;
+    use trader_bot::grpc_depot::init::depot::depot_client::DepotClient;
+    use trader_bot::indicator_client::init::calculate::indicator_client::IndicatorClient;
+
+    #[test]
+    fn test_authorize() {
+        let token: Secret = serde_json::from_str("\"s3cret\"").unwrap();
+        let request = |value: Option<&'static str>| {
+            let mut request = Request::new(());
+            if let Some(value) = value {
+                request
+                    .metadata_mut()
+                    .insert(AUTHORIZATION, value.parse().unwrap());
+            }
+            request
+        };
+
+        assert!(authorize(None, &request(None)).is_ok());
+        assert!(authorize(Some(&token), &request(Some("Bearer s3cret"))).is_ok());
+        for sent in [
+            None,
+            Some("Bearer wrong"),
+            Some("Bearer s3creT"),
+            Some("Bearer s3cre"),
+            Some("s3cret"),
+        ] {
+            let status = authorize(Some(&token), &request(sent)).unwrap_err();
+            assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        }
+    }
+
+    #[test]
+    fn test_event_message() {
+        let event = Event {
+            timestamp: chrono::Utc::now(),
+            symbol: "AAA".to_string(),
+            kind: EventKind::Order(TradeRecord {
+                symbol: "AAA".to_string(),
+                side: Side::Sell,
+                count: 3,
+                price: 10.0,
+                mid: 10.1,
+                spread_cost: 0.3,
+            }),
+        };
+        let message = event_message(event);
+        assert_eq!(message.symbol, "AAA");
+        let Some(proto::event::Kind::Order(order)) = message.kind else {
+            panic!("Expected an order, got {:?}", message.kind);
+        };
+        assert_eq!(order.side, proto::Side::Sell as i32);
+        assert_eq!(order.count, 3);
+    }
+
+    #[test]
+    fn test_params_json_round_trip() {
+        let params = LiveParams {
+            live_strategy: Some(StrategyConfig::default()),
+            max_trade_percent: 1.0,
+            max_position_percent: 10.0,
+        };
+        let message = params_message(&params).unwrap();
+        let strategy: StrategyConfig =
+            serde_json::from_str(&message.strategy_json.unwrap()).unwrap();
+        assert_eq!(Some(strategy), params.live_strategy);
+    }
+
+    #[tokio::test]
+    async fn test_update_keeps_unset_strategy(
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let settings = Settings::new()?;
+        let channel = Channel::from_static("http://127.0.0.1:9").connect_lazy();
+        let api_info = ApiInfo::from_parts(&settings.api_base_url, "", "")?;
+        let depot = Alpaca {
+            client: DepotClient::new(channel.clone()),
+            account: Arc::new(Client::new(api_info)),
+        };
+        let mut evaluator =
+            Evaluator::with_clients(&settings, depot, IndicatorClient::new(channel));
+        evaluator.eval_config = None;
+        let events = evaluator.events.clone();
+        let service = ControlService {
+            actor: Arc::new(Mutex::new(Actor::with_evaluator(evaluator))),
+            events,
+        };
+
+        let update = proto::ParamsUpdate {
+            strategy_json: None,
+            max_trade_percent: Some(settings.max_trade_percent / 2.0),
+            max_position_percent: None,
+        };
+        let response = service
+            .update_params(Request::new(update))
+            .await?
+            .into_inner();
+        assert_eq!(response.changes.len(), 1);
+        assert_eq!(response.changes[0].key, "max_trade_percent");
+        assert_eq!(
+            response.params.and_then(|params| params.strategy_json),
+            None
+        );
+        assert!(service.actor.lock().await.evaluator.eval_config.is_none());
+        Ok(())
+    }
+}
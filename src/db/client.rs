@@ -1,50 +1,144 @@
-use crate::db::models::RunResult;
+use crate::broker::strategy::StrategyKind;
+use crate::db::migrations::{Migration, MIGRATIONS, MIGRATIONS_TABLE};
+use crate::db::models::{
+    ConfigStats, EquityPoint, Fill, Metric, RunMetrics, RunRecord, RunResult, Study,
+};
+use crate::db::report::{StudyComparison, StudyReport};
 use crate::settings::Settings;
 use surrealdb::engine::any::{self, Any};
 use surrealdb::engine::remote::ws::{Client, Ws};
 use surrealdb::opt::auth::Root;
+use surrealdb::sql::{Datetime, Id};
 use surrealdb::RecordId;
 use surrealdb::Surreal;
+use tracing::info;
 
 pub struct Db {
     client: Surreal<Any>,
 }
 
+/// Engines running inside the process, they have no users to sign in as
+pub(crate) fn is_embedded(url: &str) -> bool {
+    url.starts_with("mem://") || url.starts_with("surrealkv://")
+}
+
 impl Db {
-    pub async fn new() -> Result<Self, surrealdb::Error> {
-        let settings = Settings::new().unwrap();
+    /// Connect to the database of the settings
+    pub async fn new() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let settings = Settings::new()?;
+        let db = Self::connect(
+            &settings.surreal_db_url,
+            &settings.surreal_db_user,
+            settings.db_pass(),
+        )
+        .await?;
+        Ok(db)
+    }
 
+    /// Connect to `url` and apply the pending migrations
+    pub async fn connect(url: &str, user: &str, pass: &str) -> Result<Self, surrealdb::Error> {
+        let db = Self::open(url, user, pass).await?;
+        db.migrate(false).await?;
+        Ok(db)
+    }
+
+    /// Connect to `url` without touching the schema
+    ///
+    /// `mem://` keeps the results in memory and `surrealkv://<dir>` in a
+    /// local directory. Any other URL is a server, signed in to as root.
+    pub async fn open(url: &str, user: &str, pass: &str) -> Result<Self, surrealdb::Error> {
         // Open a connection
-        let db = any::connect(settings.surreal_db_url.clone()).await?;
+        let db = any::connect(url).await?;
         db.use_ns("trader").use_db("results").await?;
-        db.signin(Root {
-            username: &settings.surreal_db_user,
-            password: &settings.surreal_db_pass,
-        })
-        .await?;
-
-        //client.use_ns("trader").use_db("results").await?;
+        if !is_embedded(url) {
+            db.signin(Root {
+                username: user,
+                password: pass,
+            })
+            .await?;
+        }
 
         Ok(Self { client: db })
     }
 
+    /// Apply the pending migrations
     pub async fn init_schema(&self) -> Result<(), surrealdb::Error> {
-        self.client
-            .query(
-                "DEFINE TABLE run_results SCHEMAFULL;
-                 DEFINE FIELD config ON TABLE run_results TYPE object;
-                 DEFINE FIELD config.long_range ON TABLE run_results TYPE int;
-                 DEFINE FIELD config.short_range ON TABLE run_results TYPE int;
-                 DEFINE FIELD symbol ON TABLE run_results TYPE string;
-                 DEFINE FIELD gain ON TABLE run_results TYPE float;
-                 DEFINE FIELD timestamp ON TABLE run_results TYPE string;",
-            )
-            .await?;
+        self.migrate(false).await?;
         Ok(())
     }
 
+    /// Versions of the migrations applied to this database
+    pub async fn applied_migrations(&self) -> Result<Vec<u32>, surrealdb::Error> {
+        self.client.query(MIGRATIONS_TABLE).await?.check()?;
+        let mut response = self
+            .client
+            .query("SELECT VALUE version FROM migrations ORDER BY version")
+            .await?;
+        response.take(0)
+    }
+
+    /// Apply every migration that is not applied yet, oldest first
+    ///
+    /// With `dry_run` nothing is changed. Returns the migrations that were,
+    /// or would be, applied.
+    pub async fn migrate(&self, dry_run: bool) -> Result<Vec<Migration>, surrealdb::Error> {
+        self.apply(MIGRATIONS, dry_run).await
+    }
+
+    async fn apply(
+        &self,
+        migrations: &[Migration],
+        dry_run: bool,
+    ) -> Result<Vec<Migration>, surrealdb::Error> {
+        let applied = self.applied_migrations().await?;
+        let pending: Vec<Migration> = migrations
+            .iter()
+            .filter(|m| !applied.contains(&m.version))
+            .copied()
+            .collect();
+
+        for migration in &pending {
+            if dry_run {
+                info!(
+                    "Migration {} ({}) is pending",
+                    migration.version, migration.name
+                );
+                continue;
+            }
+            // The migration and its bookkeeping succeed or fail together
+            let sql = format!(
+                "BEGIN TRANSACTION;
+                 {}
+                 CREATE type::thing('migrations', $version)
+                     SET version = $version, name = $name, applied_at = time::now();
+                 COMMIT TRANSACTION;",
+                migration.up
+            );
+            self.client
+                .query(sql)
+                .bind(("version", migration.version))
+                .bind(("name", migration.name))
+                .await?
+                .check()?;
+            info!(
+                "Applied migration {} ({})",
+                migration.version, migration.name
+            );
+        }
+        Ok(pending)
+    }
+
     pub async fn delete_schema(&self) -> Result<(), surrealdb::Error> {
-        self.client.query("REMOVE TABLE run_results").await?;
+        self.client
+            .query(
+                "REMOVE TABLE IF EXISTS fills;
+                 REMOVE TABLE IF EXISTS equity_points;
+                 REMOVE TABLE IF EXISTS run_metrics;
+                 REMOVE TABLE IF EXISTS migrations;
+                 REMOVE TABLE IF EXISTS studies;
+                 REMOVE TABLE run_results",
+            )
+            .await?;
         Ok(())
     }
 
@@ -61,103 +155,388 @@ impl Db {
         Ok(results)
     }
 
+    /// Delete a result together with its fills, equity curve and metrics
     pub async fn delete_result(&self, id: RecordId) -> Result<(), surrealdb::Error> {
+        self.client
+            .query(
+                "DELETE fills WHERE run = $run;
+                 DELETE equity_points WHERE run = $run;
+                 DELETE run_metrics WHERE run = $run;",
+            )
+            .bind(("run", id.clone()))
+            .await?
+            .check()?;
         let _: Option<RunResult> = self.client.delete(&id).await?;
         Ok(())
     }
+
+    /// Save a result and link its fills, equity curve and metrics to it
+    ///
+    /// Everything is written in one transaction, a failed insert leaves no
+    /// partial run behind.
+    pub async fn save_run(&self, record: RunRecord) -> Result<RecordId, surrealdb::Error> {
+        let run = RecordId::from_table_key("run_results", Id::ulid().to_raw());
+        let fills: Vec<Fill> = record
+            .fills
+            .into_iter()
+            .map(|fill| Fill {
+                run: Some(run.clone()),
+                ..fill
+            })
+            .collect();
+        let equity: Vec<EquityPoint> = record
+            .equity
+            .into_iter()
+            .map(|point| EquityPoint {
+                run: Some(run.clone()),
+                ..point
+            })
+            .collect();
+        let metrics = RunMetrics {
+            run: Some(run.clone()),
+            ..record.metrics
+        };
+
+        let mut sql = vec!["BEGIN TRANSACTION;", "CREATE $run CONTENT $result;"];
+        if !fills.is_empty() {
+            sql.push("INSERT INTO fills $fills;");
+        }
+        if !equity.is_empty() {
+            sql.push("INSERT INTO equity_points $equity;");
+        }
+        sql.push("CREATE run_metrics CONTENT $metrics;");
+        sql.push("COMMIT TRANSACTION;");
+
+        self.client
+            .query(sql.join("\n"))
+            .bind(("run", run.clone()))
+            .bind(("result", record.result))
+            .bind(("fills", fills))
+            .bind(("equity", equity))
+            .bind(("metrics", metrics))
+            .await?
+            .check()?;
+        Ok(run)
+    }
+
+    /// A saved run with its fills and equity curve in time order
+    pub async fn load_run(&self, id: RecordId) -> Result<Option<RunRecord>, surrealdb::Error> {
+        let Some(result) = self.client.select::<Option<RunResult>>(&id).await? else {
+            return Ok(None);
+        };
+
+        let mut response = self
+            .client
+            .query(
+                "SELECT * FROM fills WHERE run = $run ORDER BY timestamp;
+                 SELECT * FROM equity_points WHERE run = $run ORDER BY timestamp;
+                 SELECT * FROM run_metrics WHERE run = $run;",
+            )
+            .bind(("run", id))
+            .await?;
+        let fills: Vec<Fill> = response.take(0)?;
+        let equity: Vec<EquityPoint> = response.take(1)?;
+        let metrics: Option<RunMetrics> = response.take(2)?;
+
+        Ok(Some(RunRecord {
+            result,
+            fills,
+            equity,
+            metrics: metrics.unwrap_or_default(),
+        }))
+    }
+
+    /// Best `n` results of `symbol` by `metric`, only of `strategy` if given
+    pub async fn best_results(
+        &self,
+        symbol: &str,
+        strategy: Option<StrategyKind>,
+        metric: Metric,
+        n: usize,
+    ) -> Result<Vec<RunResult>, surrealdb::Error> {
+        let filter = match strategy {
+            Some(_) => " AND config.strategy = $strategy",
+            None => "",
+        };
+        let sql = format!(
+            "SELECT *, {} AS metric FROM run_results WHERE symbol = $symbol{} \
+             ORDER BY metric {} LIMIT $n",
+            metric.expression(),
+            filter,
+            metric.order()
+        );
+        let mut response = self
+            .client
+            .query(sql)
+            .bind(("symbol", symbol.to_string()))
+            .bind(("strategy", strategy))
+            .bind(("n", n))
+            .await?;
+        response.take(0)
+    }
+
+    /// Latest `n` results of `symbol`, newest first
+    pub async fn recent_results(
+        &self,
+        symbol: &str,
+        n: usize,
+    ) -> Result<Vec<RunResult>, surrealdb::Error> {
+        let mut response = self
+            .client
+            .query(
+                "SELECT * FROM run_results WHERE symbol = $symbol \
+                 ORDER BY timestamp DESC LIMIT $n",
+            )
+            .bind(("symbol", symbol.to_string()))
+            .bind(("n", n))
+            .await?;
+        response.take(0)
+    }
+
+    /// Results of `symbol`, or all symbols, stored at or after `since`
+    pub async fn results_since(
+        &self,
+        symbol: Option<&str>,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<RunResult>, surrealdb::Error> {
+        let mut response = self
+            .client
+            .query(
+                "SELECT * FROM run_results \
+                 WHERE ($symbol = NONE OR symbol = $symbol) AND timestamp >= $since \
+                 ORDER BY timestamp",
+            )
+            .bind(("symbol", symbol.map(str::to_string)))
+            .bind(("since", Datetime::from(since)))
+            .await?;
+        response.take(0)
+    }
+
+    /// Results whose config `parameter` lies within `min..=max`
+    ///
+    /// `parameter` is a path into the config, e.g. `sma.long_range` or
+    /// `entry_period`. Results without the parameter are left out.
+    pub async fn results_in_range(
+        &self,
+        symbol: Option<&str>,
+        parameter: &str,
+        min: f64,
+        max: f64,
+    ) -> Result<Vec<RunResult>, Box<dyn std::error::Error + Send + Sync>> {
+        // The path is part of the query text, so only plain field names pass
+        let valid = parameter.split('.').all(|field| {
+            !field.is_empty() && field.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        });
+        if !valid {
+            return Err(format!("Invalid config parameter: {}", parameter).into());
+        }
+
+        let sql = format!(
+            "SELECT * FROM run_results \
+             WHERE ($symbol = NONE OR symbol = $symbol) \
+             AND config.{parameter} >= $min AND config.{parameter} <= $max \
+             ORDER BY gain DESC"
+        );
+        let mut response = self
+            .client
+            .query(sql)
+            .bind(("symbol", symbol.map(str::to_string)))
+            .bind(("min", min))
+            .bind(("max", max))
+            .await?;
+        Ok(response.take(0)?)
+    }
+
+    /// Gain statistics of every config run on `symbol`, best mean first
+    pub async fn config_stats(&self, symbol: &str) -> Result<Vec<ConfigStats>, surrealdb::Error> {
+        let mut response = self
+            .client
+            .query(
+                "SELECT config, count() AS runs, math::mean(gain) AS mean_gain, \
+                 math::max(gain) AS best_gain, math::min(gain) AS worst_gain, \
+                 math::stddev(gain) AS gain_stddev \
+                 FROM run_results WHERE symbol = $symbol GROUP BY config",
+            )
+            .bind(("symbol", symbol.to_string()))
+            .await?;
+        let mut stats: Vec<ConfigStats> = response.take(0)?;
+        for stat in &mut stats {
+            // Undefined for a single run
+            if !stat.gain_stddev.is_finite() {
+                stat.gain_stddev = 0.0;
+            }
+        }
+        // ORDER BY is not applied to the aggregates of a grouped select
+        stats.sort_by(|a, b| b.mean_gain.total_cmp(&a.mean_gain));
+        Ok(stats)
+    }
+
+    /// Study called `name`, if there is one
+    pub async fn find_study(&self, name: &str) -> Result<Option<Study>, surrealdb::Error> {
+        let mut response = self
+            .client
+            .query("SELECT * FROM studies WHERE name = $name")
+            .bind(("name", name.to_string()))
+            .await?;
+        response.take(0)
+    }
+
+    /// The stored study with the name of `study`, created from it if missing.
+    /// Fails if the stored study searches another strategy or objective.
+    pub async fn open_study(
+        &self,
+        study: Study,
+    ) -> Result<Study, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(existing) = self.find_study(&study.name).await? {
+            if existing.strategy != study.strategy || existing.objective != study.objective {
+                return Err(format!(
+                    "Study {} searches {:?} by {:?}, not {:?} by {:?}",
+                    existing.name,
+                    existing.strategy,
+                    existing.objective,
+                    study.strategy,
+                    study.objective
+                )
+                .into());
+            }
+            return Ok(existing);
+        }
+        let created: Option<Study> = self.client.create("studies").content(study.clone()).await?;
+        Ok(created.unwrap_or(study))
+    }
+
+    /// All studies, oldest first
+    pub async fn list_studies(&self) -> Result<Vec<Study>, surrealdb::Error> {
+        let mut response = self
+            .client
+            .query("SELECT * FROM studies ORDER BY created_at")
+            .await?;
+        response.take(0)
+    }
+
+    /// Runs of a study with their metrics, oldest first
+    pub async fn study_runs(
+        &self,
+        study: &RecordId,
+    ) -> Result<(Vec<RunResult>, Vec<RunMetrics>), surrealdb::Error> {
+        let mut response = self
+            .client
+            .query(
+                "SELECT * FROM run_results WHERE study = $study ORDER BY timestamp;
+                 SELECT * FROM run_metrics WHERE run.study = $study;",
+            )
+            .bind(("study", study.clone()))
+            .await?;
+        Ok((response.take(0)?, response.take(1)?))
+    }
+
+    /// Report of the study called `name`
+    pub async fn study_report(&self, name: &str) -> Result<Option<StudyReport>, surrealdb::Error> {
+        let Some(study) = self.find_study(name).await? else {
+            return Ok(None);
+        };
+        let Some(id) = study.id.clone() else {
+            return Ok(None);
+        };
+        let (results, metrics) = self.study_runs(&id).await?;
+        Ok(Some(StudyReport::new(study, &results, &metrics)))
+    }
+
+    /// Reports of the studies `a` and `b`, `None` if either is missing
+    pub async fn compare_studies(
+        &self,
+        a: &str,
+        b: &str,
+    ) -> Result<Option<StudyComparison>, surrealdb::Error> {
+        let (Some(a), Some(b)) = (self.study_report(a).await?, self.study_report(b).await?) else {
+            return Ok(None);
+        };
+        Ok(Some(StudyComparison { a, b }))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::broker::evaluator::SMAConfig;
-
-    #[tokio::test]
-    //#[ignore] // Requires network connection to SurrealDB
-    async fn test_init_schema() {
-        let db = Db::new().await.expect("Failed to connect to DB");
+    use crate::broker::indicator_config::{IndicatorConfig, SMAConfig};
+    use crate::broker::strategy::StrategyConfig;
 
-        // Clean up first in case schema exists
-        let _ = db.delete_schema().await;
+    async fn memory() -> Db {
+        Db::connect("mem://", "", "")
+            .await
+            .expect("Failed to open in-memory DB")
+    }
 
-        // Initialize schema
-        db.init_schema().await.expect("Failed to init schema");
+    fn result(gain: f64) -> RunResult {
+        sma_result(20, gain)
+    }
 
-        // Verify we can create a record (schema exists and is valid)
-        let config = SMAConfig {
-            long_range: 10,
-            short_range: 3,
-        };
-        let res = RunResult {
+    fn sma_result(long_range: i32, gain: f64) -> RunResult {
+        RunResult {
             id: None,
-            config,
+            config: StrategyConfig::Trend(IndicatorConfig::sma_only(SMAConfig {
+                long_range,
+                short_range: 5,
+            })),
             symbol: "TEST".to_string(),
-            gain: 50.0,
+            gain,
             timestamp: chrono::Utc::now(),
-        };
+            trades: 0,
+            spread_cost: 0.0,
+            source: None,
+            git_revision: None,
+            settings: None,
+            seed: None,
+            study: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_init_schema() {
+        let db = memory().await;
+
+        // Connecting defined the schema, defining it again is a no-op
+        db.init_schema().await.expect("Failed to init schema");
 
         let created = db
-            .add_result(res)
+            .add_result(result(50.0))
             .await
             .expect("Failed to add result with new schema");
         assert!(created.is_some());
+    }
 
-        // Clean up
-        db.delete_schema().await.expect("Failed to delete schema");
+    #[tokio::test]
+    async fn test_schema_rejects_wrong_types() {
+        let db = memory().await;
+        let response = db
+            .client
+            .query("CREATE run_results SET symbol = 'TEST', gain = 'much', config = {}")
+            .await
+            .expect("Failed to send query");
+        assert!(response.check().is_err());
     }
 
     #[tokio::test]
-    #[ignore] // Requires network connection to SurrealDB
     async fn test_delete_schema() {
-        let db = Db::new().await.expect("Failed to connect to DB");
-
-        // Initialize schema first
-        db.init_schema().await.expect("Failed to init schema");
+        let db = memory().await;
+        db.add_result(result(50.0))
+            .await
+            .expect("Failed to add result");
 
-        // Delete schema
+        // Removing the table drops its records
         db.delete_schema().await.expect("Failed to delete schema");
-
-        // Verify schema is deleted by trying to add a result (should fail or return empty)
-        let config = SMAConfig {
-            long_range: 10,
-            short_range: 3,
-        };
-        let res = RunResult {
-            id: None,
-            config,
-            symbol: "TEST".to_string(),
-            gain: 50.0,
-            timestamp: chrono::Utc::now(),
-        };
-
-        // This should fail because the table doesn't exist
-        let result = db.add_result(res).await;
-        // We expect this to either fail or return None since table doesn't exist
-        assert!(result.is_err() || result.unwrap().is_none());
+        let list = db.list_results().await.expect("Failed to list results");
+        assert!(list.is_empty());
     }
 
     #[tokio::test]
-    #[ignore] // Requires network connection to SurrealDB
     async fn test_crud_cycle() {
-        let db = Db::new().await.expect("Failed to connect to DB");
-
-        // Clean up and initialize
-        let _ = db.delete_schema().await;
-        db.init_schema().await.expect("Failed to init schema");
-
-        let config = SMAConfig {
-            long_range: 20,
-            short_range: 5,
-        };
-        let res = RunResult {
-            id: None,
-            config,
-            symbol: "TEST".to_string(),
-            gain: 123.45,
-            timestamp: chrono::Utc::now(),
-        };
+        let db = memory().await;
 
-        let created = db.add_result(res).await.expect("Failed to add result");
+        let created = db
+            .add_result(result(123.45))
+            .await
+            .expect("Failed to add result");
         assert!(created.is_some());
         let created = created.unwrap();
         assert!(created.id.is_some());
@@ -175,8 +554,419 @@ mod tests {
             .await
             .expect("Failed to list results after delete");
         assert!(!list_after.iter().any(|r| r.id == Some(id.clone())));
+    }
 
-        // Clean up
-        db.delete_schema().await.expect("Failed to delete schema");
+    #[tokio::test]
+    async fn test_best_results() {
+        let db = memory().await;
+        for (gain, trades) in [(10.0, 1), (30.0, 10), (20.0, 4)] {
+            db.add_result(RunResult {
+                trades,
+                ..result(gain)
+            })
+            .await
+            .expect("Failed to add result");
+        }
+        db.add_result(RunResult {
+            symbol: "OTHER".to_string(),
+            ..result(100.0)
+        })
+        .await
+        .expect("Failed to add result");
+        db.add_result(RunResult {
+            config: StrategyConfig::grid(StrategyKind::Breakout)[0].clone(),
+            ..result(50.0)
+        })
+        .await
+        .expect("Failed to add result");
+
+        let best = db
+            .best_results("TEST", None, Metric::Gain, 2)
+            .await
+            .unwrap();
+        let gains: Vec<f64> = best.iter().map(|r| r.gain).collect();
+        assert_eq!(gains, [50.0, 30.0]);
+
+        let trend = Some(StrategyKind::Trend);
+        let best = db
+            .best_results("TEST", trend, Metric::Gain, 2)
+            .await
+            .unwrap();
+        let gains: Vec<f64> = best.iter().map(|r| r.gain).collect();
+        assert_eq!(gains, [30.0, 20.0]);
+
+        // 10 per trade against 5 and 3
+        let best = db
+            .best_results("TEST", trend, Metric::GainPerTrade, 1)
+            .await
+            .unwrap();
+        assert_eq!(best[0].gain, 10.0);
+    }
+
+    #[tokio::test]
+    async fn test_results_in_range_and_since() {
+        let db = memory().await;
+        let start = chrono::Utc::now();
+        for (long_range, gain) in [(10, 1.0), (20, 2.0), (30, 3.0)] {
+            db.add_result(sma_result(long_range, gain))
+                .await
+                .expect("Failed to add result");
+        }
+
+        let in_range = db
+            .results_in_range(Some("TEST"), "sma.long_range", 15.0, 30.0)
+            .await
+            .unwrap();
+        let gains: Vec<f64> = in_range.iter().map(|r| r.gain).collect();
+        assert_eq!(gains, [3.0, 2.0]);
+        assert!(db
+            .results_in_range(None, "sma; DELETE run_results", 0.0, 1.0)
+            .await
+            .is_err());
+
+        let since = db.results_since(None, start).await.unwrap();
+        assert_eq!(since.len(), 3);
+        let later = start + chrono::Duration::hours(1);
+        assert!(db
+            .results_since(Some("TEST"), later)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_config_stats() {
+        let db = memory().await;
+        for (long_range, gain) in [(10, 1.0), (10, 3.0), (20, 5.0)] {
+            db.add_result(sma_result(long_range, gain))
+                .await
+                .expect("Failed to add result");
+        }
+
+        let stats = db.config_stats("TEST").await.unwrap();
+        assert_eq!(stats.len(), 2);
+        assert_eq!((stats[0].runs, stats[0].mean_gain), (1, 5.0));
+        assert_eq!(stats[0].gain_stddev, 0.0);
+        assert_eq!((stats[1].runs, stats[1].mean_gain), (2, 2.0));
+        assert_eq!((stats[1].best_gain, stats[1].worst_gain), (3.0, 1.0));
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load_run() {
+        use crate::broker::execution::Side;
+        use crate::db::models::DataSource;
+
+        let db = memory().await;
+        let start = chrono::Utc::now();
+        let fill = |side, count| Fill {
+            id: None,
+            run: None,
+            symbol: "TEST".to_string(),
+            side,
+            count,
+            price_per_share: 10.0,
+            cash_difference: -10.0 * count as f64,
+            timestamp: start.to_rfc3339(),
+        };
+        let record = RunRecord {
+            result: RunResult {
+                source: Some(DataSource {
+                    kind: "bar_store".to_string(),
+                    location: "data/bars".to_string(),
+                    timeframe: "1d".to_string(),
+                    symbols: vec!["TEST".to_string()],
+                    start: Some(start),
+                    end: None,
+                }),
+                git_revision: Some("abc1234".to_string()),
+                settings: Some(serde_json::json!({ "strategy": "trend" })),
+                seed: Some(42),
+                ..result(10.0)
+            },
+            fills: vec![fill(Side::Buy, 5), fill(Side::Sell, 5)],
+            equity: (0..3)
+                .map(|i| EquityPoint {
+                    id: None,
+                    run: None,
+                    timestamp: start + chrono::Duration::days(i),
+                    equity: 100.0 + i as f64,
+                })
+                .collect(),
+            metrics: RunMetrics {
+                total_return: 0.02,
+                bars: 3,
+                fills: 2,
+                ..Default::default()
+            },
+        };
+
+        let id = db.save_run(record).await.unwrap();
+        let loaded = db
+            .load_run(id.clone())
+            .await
+            .unwrap()
+            .expect("Run not found");
+        assert_eq!(loaded.result.seed, Some(42));
+        assert_eq!(loaded.result.git_revision.as_deref(), Some("abc1234"));
+        assert_eq!(loaded.result.source.unwrap().symbols, ["TEST"]);
+        assert_eq!(loaded.result.settings.unwrap()["strategy"], "trend");
+        assert_eq!(loaded.fills.len(), 2);
+        assert_eq!(loaded.fills[0].run, Some(id.clone()));
+        let equity: Vec<f64> = loaded.equity.iter().map(|p| p.equity).collect();
+        assert_eq!(equity, [100.0, 101.0, 102.0]);
+        assert_eq!(loaded.metrics.total_return, 0.02);
+
+        // Deleting the run removes its children too
+        db.delete_result(id.clone()).await.unwrap();
+        assert!(db.load_run(id).await.unwrap().is_none());
+        let fills: Vec<Fill> = db.client.select("fills").await.unwrap();
+        assert!(fills.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_run_not_saved() {
+        use crate::broker::execution::Side;
+
+        let db = memory().await;
+        // Both fills claim the same record, the second insert fails
+        let fill = Fill {
+            id: Some(RecordId::from_table_key("fills", "taken")),
+            run: None,
+            symbol: "TEST".to_string(),
+            side: Side::Buy,
+            count: 1,
+            price_per_share: 10.0,
+            cash_difference: -10.0,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        };
+        let record = RunRecord {
+            result: result(10.0),
+            fills: vec![fill.clone(), fill],
+            equity: vec![],
+            metrics: RunMetrics::default(),
+        };
+
+        assert!(db.save_run(record).await.is_err());
+        assert!(db.list_results().await.unwrap().is_empty());
+        let metrics: Vec<RunMetrics> = db.client.select("run_metrics").await.unwrap();
+        assert!(metrics.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_migrations_applied_once() {
+        let db = memory().await;
+        let versions: Vec<u32> = MIGRATIONS.iter().map(|m| m.version).collect();
+        assert_eq!(db.applied_migrations().await.unwrap(), versions);
+        assert!(db.migrate(false).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_migrate_dry_run() {
+        let db = Db::open("mem://", "", "").await.unwrap();
+        let pending = db.migrate(true).await.unwrap();
+        assert_eq!(pending.len(), MIGRATIONS.len());
+        assert!(db.applied_migrations().await.unwrap().is_empty());
+
+        db.migrate(false).await.unwrap();
+        assert!(db.migrate(true).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_string_timestamps_migrated() {
+        let db = Db::open("mem://", "", "").await.unwrap();
+        db.apply(&MIGRATIONS[..1], false).await.unwrap();
+        db.client
+            .query(
+                "CREATE run_results SET config = {}, symbol = 'TEST', gain = 1.0, \
+                 timestamp = '2024-01-02T03:04:05Z'",
+            )
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+
+        db.migrate(false).await.unwrap();
+        let mut response = db
+            .client
+            .query("SELECT VALUE timestamp FROM run_results")
+            .await
+            .unwrap();
+        let timestamps: Vec<Datetime> = response.take(0).unwrap();
+        assert_eq!(
+            timestamps[0].0,
+            chrono::DateTime::parse_from_rfc3339("2024-01-02T03:04:05Z").unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_studies_migrated_over_existing_table() {
+        let db = Db::open("mem://", "", "").await.unwrap();
+        db.apply(&MIGRATIONS[..2], false).await.unwrap();
+        db.client
+            .query(
+                "DEFINE TABLE studies SCHEMAFULL;
+                 DEFINE FIELD name ON TABLE studies TYPE string;",
+            )
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+
+        db.migrate(false).await.unwrap();
+        let versions: Vec<u32> = MIGRATIONS.iter().map(|m| m.version).collect();
+        assert_eq!(db.applied_migrations().await.unwrap(), versions);
+    }
+
+    #[tokio::test]
+    async fn test_untagged_configs_migrated() {
+        let db = Db::open("mem://", "", "").await.unwrap();
+        db.apply(&MIGRATIONS[..3], false).await.unwrap();
+        db.client
+            .query(
+                "CREATE run_results SET symbol = 'SMA', gain = 1.0, timestamp = time::now(),
+                     config = { long_range: 20, short_range: 5 };
+                 CREATE run_results SET symbol = 'INDICATORS', gain = 2.0,
+                     timestamp = time::now(),
+                     config = { sma: { long_range: 30, short_range: 10 } }",
+            )
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+
+        db.migrate(false).await.unwrap();
+        let mut results = db.list_results().await.unwrap();
+        results.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        let sma = |long_range, short_range| SMAConfig {
+            long_range,
+            short_range,
+        };
+        assert_eq!(
+            results[0].config,
+            StrategyConfig::Trend(IndicatorConfig::sma_only(sma(30, 10)))
+        );
+        assert_eq!(
+            results[1].config,
+            StrategyConfig::Trend(IndicatorConfig::sma_only(sma(20, 5)))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_result_round_trip() {
+        let db = memory().await;
+        let opened = db.open_study(study("baseline")).await.unwrap();
+        let saved = RunResult {
+            study: opened.id.clone(),
+            git_revision: Some("abc1234".to_string()),
+            seed: Some(7),
+            trades: 3,
+            spread_cost: 1.5,
+            ..result(12.5)
+        };
+        let created = db
+            .add_result(saved.clone())
+            .await
+            .unwrap()
+            .expect("Result not saved");
+
+        let loaded: Option<RunResult> = db
+            .client
+            .select(created.id.as_ref().unwrap())
+            .await
+            .unwrap();
+        let loaded = loaded.expect("Result not found");
+        assert!(loaded.study.is_some());
+        assert_eq!(loaded.study, opened.id);
+        assert_eq!(RunResult { id: None, ..loaded }, saved);
+    }
+
+    fn study(name: &str) -> Study {
+        Study {
+            id: None,
+            name: name.to_string(),
+            description: "SMA crossovers".to_string(),
+            strategy: StrategyKind::Trend,
+            parameter_space: serde_json::json!({ "search": "random", "iterations": 2 }),
+            source: None,
+            objective: Metric::Gain,
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_open_study_reuses_name() {
+        let db = memory().await;
+        let first = db.open_study(study("baseline")).await.unwrap();
+        let second = db.open_study(study("baseline")).await.unwrap();
+        assert!(first.id.is_some());
+        assert_eq!(first.id, second.id);
+        assert_eq!(db.list_studies().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_open_study_rejects_other_search() {
+        let db = memory().await;
+        db.open_study(study("baseline")).await.unwrap();
+        let breakout = Study {
+            strategy: StrategyKind::Breakout,
+            ..study("baseline")
+        };
+        assert!(db.open_study(breakout).await.is_err());
+        let per_trade = Study {
+            objective: Metric::GainPerTrade,
+            ..study("baseline")
+        };
+        assert!(db.open_study(per_trade).await.is_err());
+        assert_eq!(db.list_studies().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_compare_studies() {
+        let db = memory().await;
+        for (name, gains) in [("baseline", [5.0, -1.0]), ("wider", [8.0, 2.0])] {
+            let opened = db.open_study(study(name)).await.unwrap();
+            for (i, gain) in gains.into_iter().enumerate() {
+                db.save_run(RunRecord {
+                    result: RunResult {
+                        study: opened.id.clone(),
+                        ..sma_result(20 + i as i32, gain)
+                    },
+                    fills: vec![],
+                    equity: vec![],
+                    metrics: RunMetrics {
+                        sharpe: gain / 10.0,
+                        ..Default::default()
+                    },
+                })
+                .await
+                .unwrap();
+            }
+        }
+        // Runs outside of a study are left out
+        db.add_result(result(100.0)).await.unwrap();
+
+        let comparison = db
+            .compare_studies("baseline", "wider")
+            .await
+            .unwrap()
+            .expect("Studies not found");
+        assert_eq!(comparison.a.runs(), 2);
+        assert_eq!(comparison.a.profitable, 0.5);
+        assert_eq!(comparison.b.gain.mean, 5.0);
+        assert_eq!(comparison.b.sharpe.max, 0.8);
+        assert_eq!(comparison.b.best[0].0, 8.0);
+
+        assert!(db
+            .compare_studies("baseline", "missing")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires network connection to SurrealDB
+    async fn test_remote_connection() {
+        let db = Db::new().await.expect("Failed to connect to DB");
+        db.list_results().await.expect("Failed to list results");
     }
 }
@@ -0,0 +1,136 @@
+/// One numbered change of the results schema
+///
+/// Migrations are applied in `version` order, each at most once. Applied
+/// versions are recorded in the `migrations` table. Never edit a migration
+/// that was released, add a new one instead.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    /// SurrealQL run inside the migration's transaction
+    pub up: &'static str,
+}
+
+/// Bookkeeping table of the applied migrations
+pub(crate) const MIGRATIONS_TABLE: &str = "DEFINE TABLE IF NOT EXISTS migrations SCHEMAFULL;
+     DEFINE FIELD IF NOT EXISTS version ON TABLE migrations TYPE int;
+     DEFINE FIELD IF NOT EXISTS name ON TABLE migrations TYPE string;
+     DEFINE FIELD IF NOT EXISTS applied_at ON TABLE migrations TYPE datetime;";
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "run results with fills, equity curve and metrics",
+        // Databases created before migrations already have these tables
+        up: "DEFINE TABLE IF NOT EXISTS run_results SCHEMAFULL;
+             DEFINE FIELD IF NOT EXISTS config ON TABLE run_results FLEXIBLE TYPE object;
+             DEFINE FIELD IF NOT EXISTS symbol ON TABLE run_results TYPE string;
+             DEFINE FIELD IF NOT EXISTS gain ON TABLE run_results TYPE float;
+             DEFINE FIELD IF NOT EXISTS timestamp ON TABLE run_results TYPE string;
+             DEFINE FIELD IF NOT EXISTS trades ON TABLE run_results TYPE int DEFAULT 0;
+             DEFINE FIELD IF NOT EXISTS spread_cost ON TABLE run_results TYPE float DEFAULT 0.0;
+             DEFINE INDEX IF NOT EXISTS run_results_symbol ON TABLE run_results FIELDS symbol;
+             DEFINE FIELD IF NOT EXISTS source ON TABLE run_results FLEXIBLE TYPE option<object>;
+             DEFINE FIELD IF NOT EXISTS git_revision ON TABLE run_results TYPE option<string>;
+             DEFINE FIELD IF NOT EXISTS settings ON TABLE run_results FLEXIBLE TYPE option<object>;
+             DEFINE FIELD IF NOT EXISTS seed ON TABLE run_results TYPE option<int>;
+
+             DEFINE TABLE IF NOT EXISTS fills SCHEMAFULL;
+             DEFINE FIELD IF NOT EXISTS run ON TABLE fills TYPE record<run_results>;
+             DEFINE FIELD IF NOT EXISTS symbol ON TABLE fills TYPE string;
+             DEFINE FIELD IF NOT EXISTS side ON TABLE fills TYPE string;
+             DEFINE FIELD IF NOT EXISTS count ON TABLE fills TYPE int;
+             DEFINE FIELD IF NOT EXISTS price_per_share ON TABLE fills TYPE float;
+             DEFINE FIELD IF NOT EXISTS cash_difference ON TABLE fills TYPE float;
+             DEFINE FIELD IF NOT EXISTS timestamp ON TABLE fills TYPE string;
+             DEFINE INDEX IF NOT EXISTS fills_run ON TABLE fills FIELDS run;
+
+             DEFINE TABLE IF NOT EXISTS equity_points SCHEMAFULL;
+             DEFINE FIELD IF NOT EXISTS run ON TABLE equity_points TYPE record<run_results>;
+             DEFINE FIELD IF NOT EXISTS timestamp ON TABLE equity_points TYPE string;
+             DEFINE FIELD IF NOT EXISTS equity ON TABLE equity_points TYPE float;
+             DEFINE INDEX IF NOT EXISTS equity_points_run ON TABLE equity_points FIELDS run;
+
+             DEFINE TABLE IF NOT EXISTS run_metrics SCHEMAFULL;
+             DEFINE FIELD IF NOT EXISTS run ON TABLE run_metrics TYPE record<run_results>;
+             DEFINE FIELD IF NOT EXISTS total_return ON TABLE run_metrics TYPE float;
+             DEFINE FIELD IF NOT EXISTS max_drawdown ON TABLE run_metrics TYPE float;
+             DEFINE FIELD IF NOT EXISTS sharpe ON TABLE run_metrics TYPE float;
+             DEFINE FIELD IF NOT EXISTS bars ON TABLE run_metrics TYPE int;
+             DEFINE FIELD IF NOT EXISTS fills ON TABLE run_metrics TYPE int;
+             DEFINE INDEX IF NOT EXISTS run_metrics_run ON TABLE run_metrics FIELDS run UNIQUE;",
+    },
+    Migration {
+        version: 2,
+        name: "datetime timestamps",
+        // Fill timestamps come from the depot as text and stay strings
+        up: "DEFINE FIELD OVERWRITE timestamp ON TABLE run_results TYPE datetime;
+             UPDATE run_results SET timestamp = <datetime> timestamp
+                 WHERE type::is::string(timestamp);
+             DEFINE FIELD OVERWRITE timestamp ON TABLE equity_points TYPE datetime;
+             UPDATE equity_points SET timestamp = <datetime> timestamp
+                 WHERE type::is::string(timestamp);",
+    },
+    Migration {
+        version: 3,
+        name: "studies",
+        up: "DEFINE TABLE IF NOT EXISTS studies SCHEMAFULL;
+             DEFINE FIELD IF NOT EXISTS name ON TABLE studies TYPE string;
+             DEFINE FIELD IF NOT EXISTS description ON TABLE studies TYPE string;
+             DEFINE FIELD IF NOT EXISTS strategy ON TABLE studies TYPE string;
+             DEFINE FIELD IF NOT EXISTS parameter_space ON TABLE studies FLEXIBLE TYPE object;
+             DEFINE FIELD IF NOT EXISTS source ON TABLE studies FLEXIBLE TYPE option<object>;
+             DEFINE FIELD IF NOT EXISTS objective ON TABLE studies TYPE string;
+             DEFINE FIELD IF NOT EXISTS created_at ON TABLE studies TYPE datetime;
+             DEFINE INDEX IF NOT EXISTS studies_name ON TABLE studies FIELDS name UNIQUE;
+
+             DEFINE FIELD IF NOT EXISTS study ON TABLE run_results TYPE option<record<studies>>;
+             DEFINE INDEX IF NOT EXISTS run_results_study ON TABLE run_results FIELDS study;",
+    },
+    Migration {
+        version: 4,
+        name: "strategy tagged configs",
+        // Results from before the strategy kinds held a plain SMA crossover,
+        // later ones an indicator config, both are trend following
+        up: "UPDATE run_results SET config = { strategy: 'trend', sma: config }
+                 WHERE config.strategy = NONE AND config.long_range != NONE;
+             UPDATE run_results SET config.strategy = 'trend'
+                 WHERE config.strategy = NONE;",
+    },
+];
+
+/// Serde helpers storing `chrono` timestamps as SurrealDB datetimes
+///
+/// Plain `chrono` values serialize to strings, which `TYPE datetime` fields
+/// reject.
+pub mod datetime {
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use surrealdb::sql::Datetime;
+
+    pub fn serialize<S: Serializer>(
+        value: &DateTime<Utc>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        Datetime::from(*value).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<DateTime<Utc>, D::Error> {
+        Ok(Datetime::deserialize(deserializer)?.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versions_ascending() {
+        for pair in MIGRATIONS.windows(2) {
+            assert_eq!(pair[1].version, pair[0].version + 1);
+        }
+        assert_eq!(MIGRATIONS[0].version, 1);
+    }
+}
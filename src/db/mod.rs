@@ -1,5 +1,7 @@
 pub mod client;
+pub mod migrations;
 pub mod models;
+pub mod report;
 
 pub use client::Db;
-pub use models::RunResult;
+pub use models::{DataSource, EquityPoint, Fill, Metric, RunMetrics, RunRecord, RunResult, Study};
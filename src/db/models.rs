@@ -1,13 +1,183 @@
-use crate::broker::evaluator::SMAConfig;
+use crate::broker::execution::Side;
+use crate::broker::strategy::{StrategyConfig, StrategyKind};
 use serde::{Deserialize, Serialize};
 use surrealdb::RecordId;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct RunResult {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub id: Option<RecordId>,
-    pub config: SMAConfig,
+    pub config: StrategyConfig,
     pub symbol: String,
     pub gain: f64,
+    #[serde(with = "crate::db::migrations::datetime")]
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    /// Orders placed during the run
+    #[serde(default)]
+    pub trades: usize,
+    /// Total cost of crossing the spread
+    #[serde(default)]
+    pub spread_cost: f64,
+    /// Bars the run replayed
+    #[serde(default)]
+    pub source: Option<DataSource>,
+    /// Commit the binary was built from
+    #[serde(default)]
+    pub git_revision: Option<String>,
+    /// Settings of the run without secrets
+    #[serde(default)]
+    pub settings: Option<serde_json::Value>,
+    /// Seed of the RNG the config was generated with
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Study the run was part of
+    #[serde(default)]
+    pub study: Option<RecordId>,
+}
+
+/// What "best" means when ranking results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// Highest gain
+    #[default]
+    Gain,
+    /// Highest gain per order
+    GainPerTrade,
+    /// Lowest spread cost
+    SpreadCost,
+}
+
+impl Metric {
+    /// SurrealQL expression of the metric
+    pub(crate) fn expression(&self) -> &'static str {
+        match self {
+            Metric::Gain => "gain",
+            Metric::GainPerTrade => "gain / math::max([trades, 1])",
+            Metric::SpreadCost => "spread_cost",
+        }
+    }
+
+    pub(crate) fn order(&self) -> &'static str {
+        if self.higher_is_better() {
+            "DESC"
+        } else {
+            "ASC"
+        }
+    }
+
+    pub fn higher_is_better(&self) -> bool {
+        match self {
+            Metric::Gain | Metric::GainPerTrade => true,
+            Metric::SpreadCost => false,
+        }
+    }
+
+    /// Value of the metric for one result, same as `expression`
+    pub fn value(&self, result: &RunResult) -> f64 {
+        match self {
+            Metric::Gain => result.gain,
+            Metric::GainPerTrade => result.gain / result.trades.max(1) as f64,
+            Metric::SpreadCost => result.spread_cost,
+        }
+    }
+}
+
+/// A named batch of runs searching one strategy's parameters
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Study {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<RecordId>,
+    /// Unique, runs of later batches with the same name join the study
+    pub name: String,
+    pub description: String,
+    pub strategy: StrategyKind,
+    /// How configs are drawn, e.g. `{"search": "random", "iterations": 10}`
+    pub parameter_space: serde_json::Value,
+    /// Data the study's runs replay
+    pub source: Option<DataSource>,
+    /// Metric the study ranks its runs by
+    pub objective: Metric,
+    #[serde(with = "crate::db::migrations::datetime")]
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Gains of every run with the same config
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConfigStats {
+    pub config: StrategyConfig,
+    pub runs: usize,
+    pub mean_gain: f64,
+    pub best_gain: f64,
+    pub worst_gain: f64,
+    /// Standard deviation of the gain, 0 for a single run
+    pub gain_stddev: f64,
+}
+
+/// Where the bars of a run came from
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DataSource {
+    /// `bar_store` or `recording`
+    pub kind: String,
+    /// Bar store root or recording file
+    pub location: String,
+    pub timeframe: String,
+    pub symbols: Vec<String>,
+    pub start: Option<chrono::DateTime<chrono::Utc>>,
+    pub end: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// One executed order of a run, as reported by the depot
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Fill {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<RecordId>,
+    /// Run record the fill belongs to, set when it is saved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run: Option<RecordId>,
+    pub symbol: String,
+    pub side: Side,
+    pub count: i32,
+    pub price_per_share: f64,
+    pub cash_difference: f64,
+    /// Depot time of the transaction
+    pub timestamp: String,
+}
+
+/// Portfolio value at the close of one bar
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct EquityPoint {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<RecordId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run: Option<RecordId>,
+    #[serde(with = "crate::db::migrations::datetime")]
     pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub equity: f64,
+}
+
+/// Performance of a run, computed from its equity curve
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct RunMetrics {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<RecordId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run: Option<RecordId>,
+    /// Fraction gained over the run
+    pub total_return: f64,
+    /// Largest fall from a peak, as a fraction of the peak
+    pub max_drawdown: f64,
+    /// Annualized Sharpe ratio of the per-bar returns
+    pub sharpe: f64,
+    pub bars: usize,
+    pub fills: usize,
+}
+
+/// A run result with everything needed to examine it afterwards
+#[derive(Debug, Clone)]
+pub struct RunRecord {
+    pub result: RunResult,
+    pub fills: Vec<Fill>,
+    pub equity: Vec<EquityPoint>,
+    pub metrics: RunMetrics,
 }
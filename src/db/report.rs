@@ -0,0 +1,315 @@
+use std::fmt;
+
+use crate::broker::strategy::StrategyConfig;
+use crate::db::models::{RunMetrics, RunResult, Study};
+
+/// Configs listed as the best of a study
+const BEST_CONFIGS: usize = 3;
+
+/// Summary statistics of one value over the runs of a study
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Distribution {
+    pub count: usize,
+    pub mean: f64,
+    /// Sample standard deviation, 0 for less than two values
+    pub stddev: f64,
+    pub min: f64,
+    pub p25: f64,
+    pub median: f64,
+    pub p75: f64,
+    pub max: f64,
+}
+
+impl Distribution {
+    /// Statistics of the finite `values`, all zero without any
+    pub fn new(values: &[f64]) -> Self {
+        let mut sorted: Vec<f64> = values.iter().copied().filter(|v| v.is_finite()).collect();
+        if sorted.is_empty() {
+            return Self::default();
+        }
+        sorted.sort_by(f64::total_cmp);
+
+        let count = sorted.len();
+        let mean = sorted.iter().sum::<f64>() / count as f64;
+        let stddev = if count > 1 {
+            let variance =
+                sorted.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (count - 1) as f64;
+            variance.sqrt()
+        } else {
+            0.0
+        };
+        Self {
+            count,
+            mean,
+            stddev,
+            min: sorted[0],
+            p25: quantile(&sorted, 0.25),
+            median: quantile(&sorted, 0.5),
+            p75: quantile(&sorted, 0.75),
+            max: sorted[count - 1],
+        }
+    }
+}
+
+// linear interpolation between the closest ranks of sorted values
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let rank = q * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
+
+/// What a study found, built from its stored runs
+#[derive(Debug, Clone)]
+pub struct StudyReport {
+    pub study: Study,
+    /// Objective of every run
+    pub objective: Distribution,
+    pub gain: Distribution,
+    pub sharpe: Distribution,
+    pub max_drawdown: Distribution,
+    /// Best configs by the objective, averaged over their repeated runs
+    pub best: Vec<(f64, StrategyConfig)>,
+    /// Share of runs that made money
+    pub profitable: f64,
+    /// Mean standard deviation of the objective of configs run more than once,
+    /// `None` without repeated configs
+    pub repeat_stddev: Option<f64>,
+}
+
+impl StudyReport {
+    pub fn new(study: Study, results: &[RunResult], metrics: &[RunMetrics]) -> Self {
+        let objective = study.objective;
+        let values: Vec<f64> = results.iter().map(|r| objective.value(r)).collect();
+        let gains: Vec<f64> = results.iter().map(|r| r.gain).collect();
+
+        // Runs of the same config, e.g. with different seeds of an ensemble
+        let mut configs: Vec<(&StrategyConfig, Vec<f64>)> = vec![];
+        for (result, value) in results.iter().zip(&values) {
+            match configs.iter_mut().find(|(c, _)| **c == result.config) {
+                Some((_, runs)) => runs.push(*value),
+                None => configs.push((&result.config, vec![*value])),
+            }
+        }
+
+        let mut best: Vec<(f64, StrategyConfig)> = configs
+            .iter()
+            .map(|(config, runs)| (Distribution::new(runs).mean, (*config).clone()))
+            .collect();
+        best.sort_by(|a, b| {
+            let order = a.0.total_cmp(&b.0);
+            if objective.higher_is_better() {
+                order.reverse()
+            } else {
+                order
+            }
+        });
+        best.truncate(BEST_CONFIGS);
+
+        let repeated: Vec<f64> = configs
+            .iter()
+            .filter(|(_, runs)| runs.len() > 1)
+            .map(|(_, runs)| Distribution::new(runs).stddev)
+            .collect();
+        let repeat_stddev =
+            (!repeated.is_empty()).then(|| repeated.iter().sum::<f64>() / repeated.len() as f64);
+
+        let profitable = if gains.is_empty() {
+            0.0
+        } else {
+            gains.iter().filter(|gain| **gain > 0.0).count() as f64 / gains.len() as f64
+        };
+
+        Self {
+            study,
+            objective: Distribution::new(&values),
+            gain: Distribution::new(&gains),
+            sharpe: Distribution::new(&metrics.iter().map(|m| m.sharpe).collect::<Vec<_>>()),
+            max_drawdown: Distribution::new(
+                &metrics.iter().map(|m| m.max_drawdown).collect::<Vec<_>>(),
+            ),
+            best,
+            profitable,
+            repeat_stddev,
+        }
+    }
+
+    /// Runs in the study
+    pub fn runs(&self) -> usize {
+        self.gain.count
+    }
+}
+
+/// Two studies side by side
+#[derive(Debug, Clone)]
+pub struct StudyComparison {
+    pub a: StudyReport,
+    pub b: StudyReport,
+}
+
+impl fmt::Display for StudyComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (a, b) = (&self.a, &self.b);
+        row(f, "", a.study.name.clone(), b.study.name.clone())?;
+        row(
+            f,
+            "strategy",
+            format!("{:?}", a.study.strategy),
+            format!("{:?}", b.study.strategy),
+        )?;
+        row(
+            f,
+            "objective",
+            format!("{:?}", a.study.objective),
+            format!("{:?}", b.study.objective),
+        )?;
+        row(f, "runs", a.runs().to_string(), b.runs().to_string())?;
+        distribution(f, "objective", &a.objective, &b.objective)?;
+        distribution(f, "gain", &a.gain, &b.gain)?;
+        distribution(f, "sharpe", &a.sharpe, &b.sharpe)?;
+        distribution(f, "max drawdown", &a.max_drawdown, &b.max_drawdown)?;
+        row(
+            f,
+            "profitable runs",
+            format!("{:.0}%", a.profitable * 100.0),
+            format!("{:.0}%", b.profitable * 100.0),
+        )?;
+        row(
+            f,
+            "repeat stddev",
+            a.repeat_stddev.map(num).unwrap_or_else(|| "-".to_string()),
+            b.repeat_stddev.map(num).unwrap_or_else(|| "-".to_string()),
+        )?;
+
+        for report in [a, b] {
+            writeln!(f)?;
+            writeln!(f, "Best configs of {}:", report.study.name)?;
+            for (value, config) in &report.best {
+                writeln!(f, "  {} {:?}", num(*value), config)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn row(f: &mut fmt::Formatter<'_>, label: &str, a: String, b: String) -> fmt::Result {
+    writeln!(f, "{:<22} {:>24} {:>24}", label, a, b)
+}
+
+fn distribution(
+    f: &mut fmt::Formatter<'_>,
+    label: &str,
+    a: &Distribution,
+    b: &Distribution,
+) -> fmt::Result {
+    row(f, &format!("{} mean", label), num(a.mean), num(b.mean))?;
+    row(
+        f,
+        &format!("{} stddev", label),
+        num(a.stddev),
+        num(b.stddev),
+    )?;
+    row(
+        f,
+        &format!("{} p25/p50/p75", label),
+        quartiles(a),
+        quartiles(b),
+    )?;
+    row(
+        f,
+        &format!("{} min/max", label),
+        format!("{}/{}", num(a.min), num(a.max)),
+        format!("{}/{}", num(b.min), num(b.max)),
+    )
+}
+
+fn num(value: f64) -> String {
+    format!("{:.2}", value)
+}
+
+fn quartiles(d: &Distribution) -> String {
+    format!("{}/{}/{}", num(d.p25), num(d.median), num(d.p75))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::indicator_config::{IndicatorConfig, SMAConfig};
+    use crate::broker::strategy::StrategyKind;
+    use crate::db::models::Metric;
+
+    fn config(long_range: i32) -> StrategyConfig {
+        StrategyConfig::Trend(IndicatorConfig::sma_only(SMAConfig {
+            long_range,
+            short_range: 5,
+        }))
+    }
+
+    fn run(long_range: i32, gain: f64) -> RunResult {
+        RunResult {
+            id: None,
+            config: config(long_range),
+            symbol: "TEST".to_string(),
+            gain,
+            timestamp: chrono::Utc::now(),
+            trades: 0,
+            spread_cost: 0.0,
+            source: None,
+            git_revision: None,
+            settings: None,
+            seed: None,
+            study: None,
+        }
+    }
+
+    fn study(name: &str) -> Study {
+        Study {
+            id: None,
+            name: name.to_string(),
+            description: String::new(),
+            strategy: StrategyKind::Trend,
+            parameter_space: serde_json::json!({ "search": "random" }),
+            source: None,
+            objective: Metric::Gain,
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_distribution() {
+        let d = Distribution::new(&[4.0, 1.0, 3.0, 2.0, f64::NAN]);
+        assert_eq!(d.count, 4);
+        assert_eq!(d.mean, 2.5);
+        assert_eq!(d.min, 1.0);
+        assert_eq!(d.median, 2.5);
+        assert_eq!(d.p25, 1.75);
+        assert_eq!(d.max, 4.0);
+        assert_eq!(Distribution::new(&[]), Distribution::default());
+    }
+
+    #[test]
+    fn test_report_groups_repeated_configs() {
+        let results = [run(20, 10.0), run(20, 20.0), run(30, -5.0), run(40, 12.0)];
+        let report = StudyReport::new(study("a"), &results, &[]);
+
+        assert_eq!(report.runs(), 4);
+        assert_eq!(report.profitable, 0.75);
+        assert_eq!(report.best[0], (15.0, config(20)));
+        assert_eq!(report.best[1], (12.0, config(40)));
+        assert_eq!(report.best.len(), 3);
+        // Only config 20 was run twice
+        assert!((report.repeat_stddev.unwrap() - 7.0710678).abs() < 1e-6);
+        assert_eq!(report.sharpe.count, 0);
+    }
+
+    #[test]
+    fn test_comparison_lists_both_studies() {
+        let comparison = StudyComparison {
+            a: StudyReport::new(study("baseline"), &[run(20, 1.0)], &[]),
+            b: StudyReport::new(study("wider"), &[run(30, 2.0)], &[]),
+        };
+        let text = comparison.to_string();
+        assert!(text.contains("baseline"));
+        assert!(text.contains("Best configs of wider:"));
+    }
+}
@@ -6,6 +6,14 @@ pub enum CLIError {
     ConfigError(#[from] config::ConfigError),
     #[error("Polars error: {0}")]
     PolarsError(#[from] polars::prelude::PolarsError),
+    #[error("Missing CSV column: {0}")]
+    MissingColumn(String),
+    #[error("Invalid timestamp: {0}")]
+    InvalidTimestamp(String),
+    #[error("Bad bar: {0}")]
+    BadBar(String),
+    #[error("Invalid settings:\n  {}", .0.join("\n  "))]
+    InvalidSettings(Vec<String>),
 }
 
 use std::error::Error;
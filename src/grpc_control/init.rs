@@ -0,0 +1,6 @@
+pub mod control {
+    tonic::include_proto!("trader");
+
+    /// Descriptors of all compiled protos, served by the reflection service
+    pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("trader_descriptor");
+}
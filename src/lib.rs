@@ -1,3 +1,4 @@
+pub mod grpc_control;
 pub mod grpc_depot;
 pub mod indicator_client;
 //mod decisions;
@@ -7,6 +7,8 @@ use futures::{FutureExt, TryStreamExt};
 use tracing::{info, Level};
 use tracing_subscriber::FmtSubscriber;
 mod broker;
+mod cli;
+mod control;
 mod decisions;
 mod error;
 mod mocking;
@@ -16,14 +18,44 @@ mod wrangling;
 mod db;
 mod settings; // Added module declaration
 
-use depot::depot_client::DepotClient;
+use clap::Parser;
+use depot::transaction::TransactionType;
+use rand::{rngs::StdRng, SeedableRng};
 use settings::Settings;
+use surrealdb::RecordId;
 use trader_bot::grpc_depot::init::depot;
 
 use crate::broker::actions::Alpaca;
 use crate::broker::evaluator::Evaluator;
-use crate::db::Db; // Added import
-use crate::wrangling::buffers::Buffer;
+use crate::broker::events::EventKind;
+use crate::broker::execution::Side;
+use crate::broker::reload::{ConfigWatcher, LiveParams, ReloadSettings};
+use crate::broker::snapshot::{LiveState, Snapshotter};
+use crate::broker::strategy::{EnsembleConfig, StrategyConfig, StrategyKind};
+use crate::cli::{
+    BacktestArgs, Cli, Command, ConfigCommand, DbCommand, DepotCommand, DownloadArgs, ImportArgs,
+    ResultsCommand,
+};
+use crate::db::client::is_embedded;
+use crate::db::{
+    DataSource, Db, EquityPoint, Fill, Metric, RunMetrics, RunRecord, RunResult, Study,
+}; // Added import
+use crate::error::CLIError;
+use crate::mocking::adjust::{Adjustment, Dividends};
+use crate::mocking::download::Downloader;
+use crate::mocking::layout::{CsvLayout, CsvRow};
+use crate::mocking::recording::{recording, Recorder};
+use crate::mocking::source::{parse_bar_size, replay, HistoricalSource};
+use crate::mocking::store::BarStore;
+use crate::wrangling::buffers::{Buffer, BUFFER_SIZE};
+use crate::wrangling::performance;
+use crate::wrangling::quality::BarValidator;
+
+/// Size of the bars Alpaca streams live, backfilled bars must match them
+const LIVE_BAR_SIZE: &str = "1m";
+
+/// Trades and equity points kept in memory while trading live
+const LIVE_HISTORY: usize = 10_000;
 
 fn buffer<T>(size: usize, data: T, _symbol: &str) -> Vec<T> {
     let mut buf = vec![];
@@ -36,19 +68,135 @@ fn buffer<T>(size: usize, data: T, _symbol: &str) -> Vec<T> {
     buf
 }
 
+/// Fill of a depot buy or sell transaction
+fn to_fill(transaction: &depot::Transaction) -> Option<Fill> {
+    let side = match transaction.r#type() {
+        TransactionType::Buy => Side::Buy,
+        TransactionType::Sell => Side::Sell,
+        TransactionType::Deposit | TransactionType::Withdraw => return None,
+    };
+    Some(Fill {
+        id: None,
+        run: None,
+        symbol: transaction.symbol.clone(),
+        side,
+        count: transaction.count.abs(),
+        price_per_share: transaction.price_per_share,
+        cash_difference: transaction.cash_difference,
+        timestamp: transaction.timestamp.clone(),
+    })
+}
+
 struct Actor {
     evaluator: Evaluator,
+    /// Dividends per share still to be credited, by symbol and ex-date
+    dividends: Dividends,
+    /// Writes every received message to disk, live mode only
+    recorder: Option<Recorder>,
+    /// Checks live bars, historical sources are validated when they are read
+    validator: Option<BarValidator>,
+    /// Set when the validator halts, no more data is traded afterwards
+    halted: bool,
+    /// Saves the live state now and then, live mode only
+    snapshots: Option<Snapshotter>,
 }
 
 impl Actor {
-    pub async fn new() -> Self {
+    pub async fn new() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self::with_evaluator(Evaluator::new().await?))
+    }
+
+    fn with_evaluator(evaluator: Evaluator) -> Self {
         Self {
-            evaluator: Evaluator::new().await,
+            evaluator,
+            dividends: Dividends::new(),
+            recorder: None,
+            validator: None,
+            halted: false,
+            snapshots: None,
+        }
+    }
+
+    /// Write the live state if a snapshot is due
+    ///
+    /// The state is written on a blocking thread once the actor is released,
+    /// a snapshot is skipped while the previous one is still being written.
+    fn save_snapshot(&mut self) {
+        let Some(snapshots) = &mut self.snapshots else {
+            return;
+        };
+        if snapshots.writing() || !snapshots.due(std::time::Instant::now()) {
+            return;
+        }
+        match self
+            .evaluator
+            .snapshot(chrono::Utc::now(), snapshots.max_history)
+        {
+            Ok(state) => snapshots.write(state),
+            Err(e) => tracing::error!("Failed to snapshot live state: {:?}", e),
+        }
+    }
+
+    /// Run a live bar through the validator, `None` if it is dropped
+    fn validate(&mut self, bar: Bar) -> Option<Bar> {
+        let Some(validator) = &mut self.validator else {
+            return Some(bar);
+        };
+        let row = CsvRow::from_bar(&bar).ok()?;
+        match validator.check(row.clone()) {
+            Ok(Some(checked)) if checked == row => Some(bar),
+            Ok(Some(filled)) => filled.to_bar().ok(),
+            Ok(None) => None,
+            Err(e) => {
+                tracing::error!("Halting on bad market data: {}", e);
+                self.evaluator.events.emit(
+                    &bar.symbol,
+                    EventKind::RiskBreach(format!("halted on bad market data: {}", e)),
+                );
+                self.halted = true;
+                None
+            }
+        }
+    }
+
+    /// Pay out the dividend of the bar's ex-date on the open position
+    ///
+    /// Short positions owe the dividend, so it is withdrawn instead.
+    async fn credit_dividend(&mut self, client: &mut Alpaca, bar: &Bar) {
+        let key = (bar.symbol.clone(), bar.timestamp.date_naive());
+        let Some(amount) = self.dividends.remove(&key) else {
+            return;
+        };
+
+        let shares = client.get_position(&bar.symbol).await.unwrap_or(0);
+        let cash = shares as f64 * amount;
+        if cash > 0.0 {
+            info!(
+                "Dividend of {} on {} shares of {}",
+                amount, shares, bar.symbol
+            );
+            client.deposit(cash).await;
+            self.evaluator.holdings = None;
+        } else if cash < 0.0 {
+            info!(
+                "Dividend of {} owed on {} short shares of {}",
+                amount, -shares, bar.symbol
+            );
+            client.withdraw(-cash).await;
+            self.evaluator.holdings = None;
         }
     }
 
     async fn trader(&mut self, client: &mut Alpaca, data: Data<Bar, Quote, Trade>) {
         //info!("Received data: {:?}", data);
+        if let Some(recorder) = &self.recorder {
+            if let Err(e) = recorder.record(&data) {
+                tracing::error!("Failed to record market data: {:?}", e);
+            }
+        }
+        if self.halted {
+            return;
+        }
         match data {
             Data::Trade(trade) => {
                 let symbol = &trade.symbol;
@@ -58,30 +206,54 @@ impl Actor {
                 //client.eval_trade(data).await;
             }
             Data::Bar(bar) => {
-                let symbol = &bar.symbol;
-                let buf = match self.evaluator.buffer.get_mut(symbol) {
-                    Some(s) => s,
-                    None => {
-                        info!("New symbol: {}", symbol);
-                        self.evaluator
-                            .buffer
-                            .insert(symbol.to_string(), Buffer::new(symbol.to_string(), 100));
-                        self.evaluator.buffer.get_mut(symbol).unwrap()
-                    }
+                let Some(bar) = self.validate(bar) else {
+                    return;
                 };
-                //add bars to buffer
-                buf.add_bar(bar.clone());
+                if !self.evaluator.in_session(bar.timestamp) {
+                    return;
+                }
+                self.credit_dividend(client, &bar).await;
+                let symbol = &bar.symbol;
+                self.evaluator.add_to_timeframes(&bar);
+                self.evaluator.record_equity(&bar).await;
+                let entry = self.evaluator.entry_bar(&bar);
+                if let Some(entry) = &entry {
+                    let buf = match self.evaluator.buffer.get_mut(symbol) {
+                        Some(s) => s,
+                        None => {
+                            info!("New symbol: {}", symbol);
+                            self.evaluator.buffer.insert(
+                                symbol.to_string(),
+                                Buffer::new(symbol.to_string(), BUFFER_SIZE),
+                            );
+                            self.evaluator.buffer.get_mut(symbol).unwrap()
+                        }
+                    };
+                    //add bars to buffer
+                    buf.add_bar(entry.clone());
+                }
+                let price = bar.close_price.to_f64().unwrap();
+                if self
+                    .evaluator
+                    .flatten_before_close(symbol, bar.timestamp, price)
+                    .await
+                {
+                    return;
+                }
                 /* info!(
                     "New se: {:?}",
                     self.evaluator.buffer.get(symbol).unwrap().bar_count()
                 ); */
+                let Some(entry) = entry else {
+                    return;
+                };
                 if self.evaluator.buffer.get(symbol).unwrap().bar_count() >= 20 {
                     self.evaluator
-                        .eval_bars(symbol, bar.close_price.to_f64().unwrap())
+                        .eval_bars(symbol, entry.close_price.to_f64().unwrap())
                         .await;
                 }
             }
-            Data::Quote(_) => {}
+            Data::Quote(quote) => self.evaluator.quotes.update(&quote),
             _ => {}
         }
     }
@@ -94,109 +266,399 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         .finish();
     tracing::subscriber::set_global_default(subscriber)?;
 
-    // Load configuration
+    // Flags win over the configuration files and the environment
+    let cli = Cli::parse();
+    Settings::set_profile(cli.profile.clone());
+    Settings::set_overrides(cli.overrides());
     let settings = Settings::new()?;
-    let depot_url = settings.depot_url.clone();
-    let api_key = settings.api_key_id.clone();
-    let api_secret = settings.api_secret_key.clone();
-    let api_base = settings.api_base_url.clone();
-    info!("Starting trader with API Base: {}", api_base);
+    if let Some(Command::Config(ConfigCommand::Check)) = &cli.command {
+        return config_check(&settings);
+    }
+    settings.validate()?;
 
-    // Connect to Depot
-    info!("Connecting to Depot at {}", depot_url);
-    let _depot_client = DepotClient::connect(depot_url).await?;
+    // Every command only connects to the services it uses
+    match cli.command {
+        Some(Command::Live(_)) => live(&settings).await,
+        Some(Command::Backtest(args)) => backtest(&settings, args).await,
+        Some(Command::Optimize(_)) => evaluate(&settings, None, settings.eval_iterations).await,
+        Some(Command::ImportData(args)) => import_data(&settings, args),
+        Some(Command::Download(args)) => download(&settings, args).await,
+        Some(Command::Results(command)) => results(command).await,
+        Some(Command::Db(DbCommand::Migrate { dry_run })) => migrate(&settings, dry_run).await,
+        Some(Command::Depot(DepotCommand::State)) => depot_state().await,
+        Some(Command::Config(ConfigCommand::Check)) => Ok(()),
+        None if settings.use_mock_data => evaluate(&settings, None, settings.eval_iterations).await,
+        None => live(&settings).await,
+    }
+}
 
-    // Setup Alpaca
-    info!("Connecting to Alpaca");
-    //TODO put in function
-    let api_info = ApiInfo::from_parts(api_base.clone(), api_key.clone(), api_secret.clone())?;
-    let client_broker = Client::new(api_info);
+/// Store the bars of a CSV file in the bar store
+fn import_data(
+    settings: &Settings,
+    args: ImportArgs,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Date-only timestamps are read as exchange midnight, like Alpaca's daily bars
+    let calendar = settings.trading_calendar()?;
+    let layout = settings.csv_layout.clone().or_timezone(calendar.timezone());
+    let timeframe = args.timeframe.as_ref().unwrap_or(&settings.timeframe);
+    let store = BarStore::new(&settings.bar_store);
+    for report in store.import_csv(&args.file, &args.symbol, timeframe, layout)? {
+        info!(
+            "Imported {}: {} bars stored, {} duplicates, {} invalid bars dropped",
+            report.symbol, report.rows, report.duplicates, report.invalid
+        );
+    }
+    Ok(())
+}
 
-    let (mut stream, mut subscription) = client_broker
-        .subscribe::<RealtimeData<IEX, Bar, Quote, Trade>>()
+/// Download Alpaca bars of whole days into the bar store
+async fn download(
+    settings: &Settings,
+    args: DownloadArgs,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let day = |date: chrono::NaiveDate| date.and_time(chrono::NaiveTime::MIN).and_utc();
+    let timeframe = args.timeframe.as_ref().unwrap_or(&settings.timeframe);
+    let (api_key, api_secret) = settings.alpaca_credentials()?;
+    let api_info = ApiInfo::from_parts(&settings.api_base_url, api_key, api_secret)?;
+    let store = BarStore::new(&settings.bar_store);
+    let reports = Downloader::new(Client::new(api_info))
+        .download(
+            &store,
+            &args.symbols,
+            day(args.start),
+            day(args.end),
+            timeframe,
+        )
         .await?;
-    let symbols = ["FAKEPACA"];
-    info!("Subscribing to {:?}", symbols);
-    let mut market_data = MarketData::default();
-    market_data.set_trades(symbols);
-    market_data.set_quotes(["FAKEPACA"]);
+    for report in reports {
+        info!(
+            "Stored {}: {} bars, {} duplicates, {} invalid bars dropped",
+            report.symbol, report.rows, report.duplicates, report.invalid
+        );
+    }
+    Ok(())
+}
 
-    info!("Subscribing to {:?}", symbols);
-    let subscribe = subscription.subscribe(&market_data).boxed();
+/// Key the results of the replayed symbols are stored under
+fn result_symbol(settings: &Settings) -> String {
+    match (&settings.pair, settings.strategy) {
+        (Some(pair), StrategyKind::Pairs) => format!("{}/{}", pair.symbol_a, pair.symbol_b),
+        _ => settings.mock_symbol.clone(),
+    }
+}
 
-    // Actually subscribe with the websocket server.
-    // Actually subscribe with the websocket server.
-    let () = drive(subscribe, &mut stream)
-        .await
-        .unwrap()
-        .unwrap()
-        .unwrap();
+/// Record id of a result given as `run_results:<key>` or just the key
+fn result_id(id: &str) -> RecordId {
+    let key = id.strip_prefix("run_results:").unwrap_or(id);
+    let key = key.trim_start_matches('⟨').trim_end_matches('⟩');
+    RecordId::from_table_key("run_results", key)
+}
 
-    // Initialize Alpaca with all clients
-    let client = std::sync::Arc::new(Alpaca::new().await);
-    let actor = std::sync::Arc::new(tokio::sync::Mutex::new(Actor::new().await));
+/// Replay one config, from a file, a stored result or the best stored result
+async fn backtest(
+    settings: &Settings,
+    args: BacktestArgs,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let config: StrategyConfig = match (&args.config, &args.result) {
+        (Some(file), _) => serde_json::from_str(&std::fs::read_to_string(file)?)?,
+        (None, id) => {
+            let db = Db::new().await?;
+            let result = match id {
+                Some(id) => db.load_run(result_id(id)).await?.map(|run| run.result),
+                None => db
+                    .best_results(&result_symbol(settings), None, Metric::Gain, 1)
+                    .await?
+                    .into_iter()
+                    .next(),
+            };
+            result.ok_or("No stored result to backtest")?.config
+        }
+    };
+    info!("Backtesting {:?}", config);
+    evaluate(settings, Some(config), args.runs).await
+}
 
-    if settings.use_mock_data {
-        info!("Using mock data from {}", settings.mock_file_path);
-        let eval_iterations = settings.eval_iterations;
-        let top_n = settings.top_n_configs;
-        let mut reset_client = (*client).clone();
-        let symbol = "ORCL";
+/// Replay the historical bars `runs` times and store every run
+///
+/// Each run trades `fixed`, or a new random config of `strategy`.
+async fn evaluate(
+    settings: &Settings,
+    fixed: Option<StrategyConfig>,
+    runs: usize,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let calendar = settings.trading_calendar()?;
+    let exchange_layout = |layout: CsvLayout| layout.or_timezone(calendar.timezone());
+    // One depot connection for the runs and the evaluator
+    let client = Alpaca::new().await?;
+    let kind = fixed
+        .as_ref()
+        .map_or(settings.strategy, StrategyConfig::kind);
+    let evaluator = Evaluator::with_depot(settings, client.clone(), kind).await?;
+    let client = std::sync::Arc::new(client);
+    let actor = std::sync::Arc::new(tokio::sync::Mutex::new(Actor::with_evaluator(evaluator)));
 
-        // Initialize DB
-        let db: Option<Db> = match Db::new().await {
-            Ok(d) => Some(d),
-            Err(e) => {
-                tracing::error!("Failed to connect to DB: {:?}", e);
-                None
+    info!("Using mock data from {}", settings.mock_file_path);
+    let eval_iterations = runs;
+    let top_n = settings.top_n_configs;
+    let mut reset_client = (*client).clone();
+    // The pairs strategy replays both legs, everything else a single symbol
+    let pair = settings
+        .pair
+        .clone()
+        .filter(|_| settings.strategy == StrategyKind::Pairs);
+    let symbols: Vec<String> = match &pair {
+        Some(pair) => vec![pair.symbol_a.clone(), pair.symbol_b.clone()],
+        None => vec![settings.mock_symbol.clone()],
+    };
+    let symbol = symbols.join("/");
+
+    // Parse the CSV exports once, every run replays them from the bar store
+    let store = BarStore::new(&settings.bar_store);
+    let files = match &pair {
+        Some(pair) => vec![
+            (
+                &pair.file_a,
+                &pair.symbol_a,
+                exchange_layout(CsvLayout::default()),
+            ),
+            (
+                &pair.file_b,
+                &pair.symbol_b,
+                exchange_layout(CsvLayout::default()),
+            ),
+        ],
+        None => vec![(
+            &settings.mock_file_path,
+            &symbols[0],
+            exchange_layout(settings.csv_layout.clone()),
+        )],
+    };
+    let mut stored = settings.replay_symbols.clone();
+    if stored.is_empty() && settings.replay_recording.is_none() {
+        for (file, symbol, layout) in files {
+            for report in store.import_csv(file, symbol, &settings.timeframe, layout)? {
+                info!(
+                    "Imported {}: {} bars stored, {} duplicates, {} invalid bars dropped",
+                    report.symbol, report.rows, report.duplicates, report.invalid
+                );
+                stored.push(report.symbol);
             }
-        };
+        }
+    }
+    // Only single symbol runs are adjusted, the adjustment file is per symbol
+    let adjustment = match &pair {
+        Some(_) => Adjustment::None,
+        None => settings.adjustment.clone(),
+    };
 
-        for i in 0..eval_iterations {
-            info!("Starting evaluation run {}/{}", i + 1, eval_iterations);
+    // Initialize DB
+    let db: Option<Db> = match Db::new().await {
+        Ok(d) => Some(d),
+        Err(e) => {
+            tracing::error!("Failed to connect to DB: {:?}", e);
+            None
+        }
+    };
 
-            // 1. Generate Config
-            let config = crate::broker::evaluator::IndicatorConfig::random();
-            info!("Generated config: {:#?}", config);
-            let cash = 100000.0;
-            let eval_config = crate::broker::evaluator::EvalConfig {
-                cash,
-                indicator_config: config.clone(),
-            };
+    // Ensembles are weighted by the gains of recent runs on the same symbol
+    let mut performance: Vec<(f64, StrategyConfig)> = vec![];
+    if let (Some(db), StrategyKind::Ensemble, None) = (&db, settings.strategy, &fixed) {
+        match db.recent_results(&symbol, 100).await {
+            Ok(results) => {
+                performance = results.into_iter().map(|r| (r.gain, r.config)).collect();
+            }
+            Err(e) => tracing::error!("Failed to load results from DB: {:?}", e),
+        }
+    }
 
-            // 2. Update Actor
-            {
+    // Start from the best configs of earlier runs of the strategy instead of an empty list
+    if let Some(db) = &db {
+        match db
+            .best_results(&symbol, Some(settings.strategy), Metric::Gain, top_n)
+            .await
+        {
+            Ok(results) => {
+                info!("Loaded {} best configurations from DB", results.len());
                 let mut actor_guard = actor.lock().await;
-                actor_guard.evaluator.eval_config = Some(eval_config);
-                actor_guard.evaluator.buffer.clear();
+                for result in results {
+                    actor_guard.evaluator.update_best_configs(
+                        result.gain as i32,
+                        result.config,
+                        top_n,
+                    );
+                }
             }
+            Err(e) => tracing::error!("Failed to load best results from DB: {:?}", e),
+        }
+    }
 
-            // 3. Reset Environment
-            reset_client.reset_cash().await;
-            reset_client.reset_stock(symbol).await;
-            reset_client.deposit(cash).await;
-
-            // 4. Run Stream
-            let stream =
-                crate::mocking::mock::mock_data_stream(&settings.mock_file_path, symbol, 0).await?;
+    let resample = settings
+        .resample
+        .as_deref()
+        .map(|size| parse_bar_size(size).ok_or(CLIError::ConvertingError))
+        .transpose()?;
 
-            let client_for_stream = client.clone();
-            let actor_for_stream = actor.clone();
+    // Stored with every run so results can be traced back to their inputs
+    let source = match &settings.replay_recording {
+        Some(path) => DataSource {
+            kind: "recording".to_string(),
+            location: path.clone(),
+            timeframe: settings.timeframe.clone(),
+            symbols: symbols.clone(),
+            start: None,
+            end: None,
+        },
+        None => DataSource {
+            kind: "bar_store".to_string(),
+            location: settings.bar_store.clone(),
+            timeframe: settings
+                .resample
+                .clone()
+                .unwrap_or_else(|| settings.timeframe.clone()),
+            symbols: stored.clone(),
+            start: settings.replay_start,
+            end: settings.replay_end,
+        },
+    };
+    let snapshot = Settings::snapshot()
+        .map_err(|e| tracing::error!("Failed to snapshot settings: {:?}", e))
+        .ok();
 
-            stream
-                .try_for_each(move |data| {
-                    let client = client_for_stream.clone();
-                    let actor = actor_for_stream.clone();
-                    async move {
-                        let mut client_clone = (*client).clone();
-                        let mut actor_guard = actor.lock().await;
-                        actor_guard.trader(&mut client_clone, data).await;
-                        Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
-                    }
+    // Every run of this batch joins the configured study
+    let study = match (&db, &settings.study) {
+        (Some(db), Some(study)) => {
+            let parameter_space = study
+                .parameter_space
+                .clone()
+                .unwrap_or_else(|| match &fixed {
+                    Some(config) => serde_json::json!({ "search": "fixed", "config": config }),
+                    None => serde_json::json!({
+                        "search": "random",
+                        "strategy": settings.strategy,
+                        "iterations": eval_iterations,
+                    }),
+                });
+            let opened = db
+                .open_study(Study {
+                    id: None,
+                    name: study.name.clone(),
+                    description: study.description.clone(),
+                    strategy: settings.strategy,
+                    parameter_space,
+                    source: Some(source.clone()),
+                    objective: study.objective,
+                    created_at: chrono::Utc::now(),
                 })
                 .await?;
+            info!("Adding runs to study {}", opened.name);
+            opened.id
+        }
+        _ => None,
+    };
+    let periods_per_year = resample
+        .or_else(|| parse_bar_size(&settings.timeframe))
+        .map(performance::periods_per_year)
+        .unwrap_or(252.0);
 
-            // 5. Sell remaining shares
+    for i in 0..eval_iterations {
+        info!("Starting evaluation run {}/{}", i + 1, eval_iterations);
+
+        // 1. Generate Config
+        let weighted = (i == 0)
+            .then(|| EnsembleConfig::from_performance(&performance, top_n))
+            .flatten();
+        let seed = settings
+            .seed
+            .map(|seed| seed.wrapping_add(i as u64))
+            .unwrap_or_else(|| rand::random::<u64>() >> 1);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let config = match (&fixed, weighted) {
+            (Some(config), _) => config.clone(),
+            (None, Some(ensemble)) => StrategyConfig::Ensemble(ensemble),
+            (None, None) => StrategyConfig::random(settings.strategy, &mut rng),
+        };
+        info!("Generated config: {:#?}", config);
+        let cash = 100000.0;
+        let eval_config = crate::broker::evaluator::EvalConfig {
+            cash,
+            strategy: config.clone(),
+        };
+
+        // 2. Update Actor
+        {
+            let mut actor_guard = actor.lock().await;
+            actor_guard.evaluator.eval_config = Some(eval_config);
+            actor_guard.evaluator.buffer.clear();
+            actor_guard.evaluator.timeframes.clear();
+            actor_guard.evaluator.entry_bars.clear();
+            actor_guard.evaluator.last_bars.clear();
+            actor_guard.evaluator.quotes.clear();
+            actor_guard.evaluator.trades.clear();
+            actor_guard.evaluator.last_prices.clear();
+            actor_guard.evaluator.equity.clear();
+            actor_guard.evaluator.holdings = None;
+            actor_guard.evaluator.signals.clear();
+        }
+
+        // 3. Reset Environment
+        reset_client.reset_cash().await;
+        for symbol in &symbols {
+            reset_client.reset_stock(symbol).await;
+        }
+        reset_client.deposit(cash).await;
+
+        // 4. Run Stream
+        let validator = std::sync::Arc::new(std::sync::Mutex::new(
+            BarValidator::new(settings.quality_policy).with_calendar(calendar.clone()),
+        ));
+        let sources: Vec<HistoricalSource> = stored
+            .iter()
+            .map(|symbol| {
+                store
+                    .source(symbol, &settings.timeframe)
+                    .adjusted(adjustment.clone())
+                    .between(settings.replay_start, settings.replay_end)
+                    .resample(resample)
+                    .validated(validator.clone())
+            })
+            .collect();
+
+        let mut dividends = Dividends::new();
+        for source in &sources {
+            dividends.extend(source.dividends()?);
+        }
+        actor.lock().await.dividends = dividends;
+        let stream = match &settings.replay_recording {
+            Some(path) => recording(path, settings.replay_speed)?,
+            None => replay(sources, settings.replay_speed),
+        };
+
+        let client_for_stream = client.clone();
+        let actor_for_stream = actor.clone();
+
+        let result = stream
+            .try_for_each(move |data| {
+                let client = client_for_stream.clone();
+                let actor = actor_for_stream.clone();
+                async move {
+                    let mut client_clone = (*client).clone();
+                    let mut actor_guard = actor.lock().await;
+                    actor_guard.trader(&mut client_clone, data).await;
+                    Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+                }
+            })
+            .await;
+        if let Ok(validator) = validator.lock() {
+            info!("Run {} data quality: {:?}", i + 1, validator.report());
+        }
+        result?;
+
+        // 5. Close remaining positions of every symbol that was replayed
+        let traded: Vec<String> = {
+            let actor_guard = actor.lock().await;
+            actor_guard.evaluator.buffer.keys().cloned().collect()
+        };
+        for symbol in &traded {
             let last_price_opt = {
                 let actor_guard = actor.lock().await;
                 actor_guard.evaluator.buffer.get(symbol).and_then(|buf| {
@@ -217,90 +679,657 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
                         price_per_share: last_price,
                     };
                     reset_client.sell(req).await;
+                } else if shares < 0 {
+                    info!("Covering remaining {} shares at {}", -shares, last_price);
+                    let req = depot::BuyRequest {
+                        symbol: symbol.to_string(),
+                        count: -shares,
+                        price_per_share: last_price,
+                    };
+                    reset_client.buy(req).await;
                 }
             }
+        }
 
-            // 6. Evaluate
-            if let Some(gain) = reset_client.get_gain().await {
-                info!("Run {} result: Gain = {}", i + 1, gain);
-                let mut actor_guard = actor.lock().await;
-                actor_guard
+        // 6. Evaluate
+        if let Some(gain) = reset_client.get_gain().await {
+            info!("Run {} result: Gain = {}", i + 1, gain);
+            let mut actor_guard = actor.lock().await;
+            let trades = actor_guard.evaluator.trades.len();
+            let spread_cost: f64 = actor_guard
+                .evaluator
+                .trades
+                .iter()
+                .map(|t| t.spread_cost)
+                .sum();
+            info!(
+                "Run {}: {} orders, spread cost {:.2}",
+                i + 1,
+                trades,
+                spread_cost
+            );
+            actor_guard
+                .evaluator
+                .update_best_configs(gain as i32, config.clone(), top_n);
+
+            // 7. Save to DB
+            if let Some(db) = &db {
+                let mut fills = vec![];
+                for symbol in &symbols {
+                    let transactions = reset_client
+                        .get_transactions(symbol)
+                        .await
+                        .unwrap_or_default();
+                    fills.extend(transactions.iter().filter_map(to_fill));
+                }
+                let equity: Vec<EquityPoint> = actor_guard
                     .evaluator
-                    .update_best_configs(gain as i32, config.clone(), top_n);
-
-                // 7. Save to DB
-                if let Some(db) = &db {
-                    if let Some(sma_config) = config.sma.clone() {
-                        let res = crate::db::RunResult {
-                            id: None,
-                            config: sma_config,
-                            symbol: symbol.to_string(),
-                            gain,
-                            timestamp: chrono::Utc::now(),
-                        };
-                        if let Err(e) = db.add_result(res).await {
-                            tracing::error!("Failed to save result to DB: {:?}", e);
-                        } else {
-                            info!("Saved result to DB");
-                        }
+                    .equity
+                    .iter()
+                    .map(|(timestamp, equity)| EquityPoint {
+                        id: None,
+                        run: None,
+                        timestamp: *timestamp,
+                        equity: *equity,
+                    })
+                    .collect();
+                let values: Vec<f64> = equity.iter().map(|p| p.equity).collect();
+                let metrics = RunMetrics {
+                    id: None,
+                    run: None,
+                    total_return: performance::total_return(&values),
+                    max_drawdown: performance::max_drawdown(&values),
+                    sharpe: performance::sharpe(&values, periods_per_year),
+                    bars: values.len(),
+                    fills: fills.len(),
+                };
+                info!("Run {} metrics: {:?}", i + 1, metrics);
+
+                let record = RunRecord {
+                    result: RunResult {
+                        id: None,
+                        config: config.clone(),
+                        symbol: symbol.to_string(),
+                        gain,
+                        timestamp: chrono::Utc::now(),
+                        trades,
+                        spread_cost,
+                        source: Some(source.clone()),
+                        git_revision: option_env!("GIT_REVISION").map(str::to_string),
+                        settings: snapshot.clone(),
+                        seed: Some(seed),
+                        study: study.clone(),
+                    },
+                    fills,
+                    equity,
+                    metrics,
+                };
+                if let Err(e) = db.save_run(record).await {
+                    tracing::error!("Failed to save result to DB: {:?}", e);
+                } else {
+                    info!("Saved result to DB");
+                }
+            }
+        } else {
+            tracing::error!("Failed to get gain for run {}", i + 1);
+        }
+    }
+
+    let actor_guard = actor.lock().await;
+    info!("Best configurations:");
+    for (gain, config) in &actor_guard.evaluator.best_eval_config {
+        info!("Gain: {}, Config: {:?}", gain, config);
+    }
+    Ok(())
+}
+
+/// Trade the live stream of `live_symbols`
+async fn live(settings: &Settings) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let calendar = settings.trading_calendar()?;
+    let (api_key, api_secret) = settings.alpaca_credentials()?;
+    let api_base = settings.api_base_url.clone();
+    info!("Starting trader with API Base: {}", api_base);
+    let client = std::sync::Arc::new(Alpaca::new().await?);
+    let mut trader = Actor::new().await?;
+    trader.evaluator.max_history = Some(LIVE_HISTORY);
+    let actor = std::sync::Arc::new(tokio::sync::Mutex::new(trader));
+
+    // Continue from the last snapshot, filling the bars missed while down
+    if let Some(snapshot) = &settings.snapshot {
+        match LiveState::load(&snapshot.path) {
+            Ok(Some(state)) => {
+                let now = chrono::Utc::now();
+                let stale = state.stale(now, chrono::Duration::minutes(snapshot.max_age_minutes));
+                info!("Restoring live state saved at {}", state.taken_at);
+                let mut actor_guard = actor.lock().await;
+                actor_guard.evaluator.restore(state)?;
+
+                let api_info = ApiInfo::from_parts(&api_base, api_key, api_secret)?;
+                let downloader = Downloader::new(Client::new(api_info));
+                for (symbol, since) in stale {
+                    info!("Backfilling {} since {}", symbol, since);
+                    match downloader.bars(&symbol, since, now, LIVE_BAR_SIZE).await {
+                        Ok(rows) => actor_guard.evaluator.backfill(rows)?,
+                        Err(e) => tracing::error!("Failed to backfill {}: {:?}", symbol, e),
                     }
                 }
-            } else {
-                tracing::error!("Failed to get gain for run {}", i + 1);
             }
+            Ok(None) => info!("No live state at {}, starting empty", snapshot.path),
+            Err(e) => tracing::error!("Failed to load live state: {:?}", e),
         }
+        actor.lock().await.snapshots = Some(Snapshotter::new(snapshot));
+    }
+    if let Some(reload) = &settings.reload {
+        info!(
+            "Reloading live parameters when {:?} change",
+            Settings::config_files()
+        );
+        tokio::spawn(watch_config(actor.clone(), reload.clone()));
+    }
+    if let Some(control) = settings.control.clone() {
+        let actor = actor.clone();
+        let token = settings.control_token.clone();
+        tokio::spawn(async move {
+            if let Err(e) = control::serve(&control, token, actor).await {
+                tracing::error!("Control API stopped: {:?}", e);
+            }
+        });
+    }
 
-        let actor_guard = actor.lock().await;
-        info!("Best configurations:");
-        for (gain, config) in &actor_guard.evaluator.best_eval_config {
-            info!("Gain: {}, Config: {:?}", gain, config);
-        }
-    } else {
-        // Setup Alpaca
-        info!("Connecting to Alpaca");
-        let api_info = ApiInfo::from_parts(api_base.clone(), api_key.clone(), api_secret.clone())?;
-        let client_broker = Client::new(api_info);
-        let (mut stream, mut subscription) = client_broker
-            .subscribe::<RealtimeData<IEX, Bar, Quote, Trade>>()
-            .await?;
-        let symbols = ["FAKEPACA"];
-        let mut market_data = MarketData::default();
-        market_data.set_trades(symbols);
-        market_data.set_quotes(["FAKEPACA"]);
-
-        info!("Subscribing to {:?}", symbols);
-        let subscribe = subscription.subscribe(&market_data).boxed();
-
-        // Actually subscribe with the websocket server.
-        let () = drive(subscribe, &mut stream)
-            .await
-            .unwrap()
-            .unwrap()
-            .unwrap();
+    // Setup Alpaca
+    info!("Connecting to Alpaca");
+    let api_info = ApiInfo::from_parts(&api_base, api_key, api_secret)?;
+    let client_broker = Client::new(api_info);
+    let (mut stream, mut subscription) = client_broker
+        .subscribe::<RealtimeData<IEX, Bar, Quote, Trade>>()
+        .await?;
+    let symbols = &settings.live_symbols;
+    let mut market_data = MarketData::default();
+    market_data.set_bars(symbols.clone());
+    market_data.set_trades(symbols.clone());
+    market_data.set_quotes(symbols.clone());
 
-        info!("Connected to Alpaca");
-        info!("Stream started...");
+    info!("Subscribing to {:?}", symbols);
+    let subscribe = subscription.subscribe(&market_data).boxed();
 
-        let () = stream
-            .map_err(Error::WebSocket)
-            .try_for_each(move |result| {
-                let client = client.clone();
-                let actor = actor.clone();
-                async move {
-                    match result {
-                        Ok(data) => {
-                            let mut client_clone = (*client).clone();
-                            let mut actor_guard = actor.lock().await;
-                            actor_guard.trader(&mut client_clone, data).await;
-                            Ok::<(), apca::Error>(())
-                        }
-                        Err(e) => Err(Error::Json(e)),
+    // Actually subscribe with the websocket server.
+    let () = drive(subscribe, &mut stream)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    info!("Connected to Alpaca");
+    actor.lock().await.validator =
+        Some(BarValidator::new(settings.quality_policy).with_calendar(calendar.clone()));
+    if let Some(path) = &settings.record_path {
+        info!("Recording market data to {}", path);
+        actor.lock().await.recorder = Some(Recorder::open(path)?);
+    }
+    info!("Stream started...");
+
+    let () = stream
+        .map_err(Error::WebSocket)
+        .try_for_each(move |result| {
+            let client = client.clone();
+            let actor = actor.clone();
+            async move {
+                match result {
+                    Ok(data) => {
+                        let mut client_clone = (*client).clone();
+                        let mut actor_guard = actor.lock().await;
+                        actor_guard.trader(&mut client_clone, data).await;
+                        actor_guard.save_snapshot();
+                        Ok::<(), apca::Error>(())
                     }
+                    Err(e) => Err(Error::Json(e)),
                 }
-            })
-            .await
-            .unwrap();
+            }
+        })
+        .await
+        .unwrap();
+    Ok(())
+}
+
+/// Apply edits of the strategy and position limits in the config files
+///
+/// The actor is locked while the parameters are swapped, so they change
+/// between two messages and never while a bar is evaluated.
+async fn watch_config(actor: std::sync::Arc<tokio::sync::Mutex<Actor>>, reload: ReloadSettings) {
+    let mut watcher = ConfigWatcher::new(Settings::config_files());
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(reload.interval_secs));
+    loop {
+        interval.tick().await;
+        if !watcher.changed() {
+            continue;
+        }
+        let settings = match Settings::new() {
+            Ok(settings) => settings,
+            Err(e) => {
+                tracing::error!("Failed to reload settings, keeping the current ones: {}", e);
+                continue;
+            }
+        };
+        let mut actor = actor.lock().await;
+        let params = LiveParams::from_settings(&settings, &actor.evaluator.live_params());
+        match actor.evaluator.apply(params) {
+            Ok(changes) if changes.is_empty() => info!("Settings reloaded, nothing live changed"),
+            Ok(changes) => info!("Settings reloaded, {} live values changed", changes.len()),
+            Err(e) => tracing::error!(
+                "Rejected reloaded settings, keeping the current ones: {}",
+                e
+            ),
+        }
     }
+}
 
+/// Inspect and delete stored results
+async fn results(command: ResultsCommand) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db = Db::new().await?;
+    match command {
+        ResultsCommand::List {
+            symbol,
+            study,
+            limit,
+        } => {
+            let mut results = match (&study, &symbol) {
+                (Some(name), _) => {
+                    let study = db
+                        .find_study(name)
+                        .await?
+                        .ok_or(format!("No study {}", name))?;
+                    let id = study.id.ok_or("Study without id")?;
+                    let (mut results, _) = db.study_runs(&id).await?;
+                    results.reverse();
+                    results
+                }
+                (None, Some(symbol)) => db.recent_results(symbol, limit).await?,
+                (None, None) => {
+                    let mut results = db.list_results().await?;
+                    results.sort_by_key(|r| std::cmp::Reverse(r.timestamp));
+                    results
+                }
+            };
+            results.retain(|r| symbol.as_ref().is_none_or(|symbol| &r.symbol == symbol));
+            results.truncate(limit);
+            for result in results {
+                println!(
+                    "{}  {}  {:<10} gain {:>10.2}  {:>4} orders  {}",
+                    result.id.map(|id| id.to_string()).unwrap_or_default(),
+                    result.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                    result.symbol,
+                    result.gain,
+                    result.trades,
+                    serde_json::to_string(&result.config)?
+                );
+            }
+        }
+        ResultsCommand::Show { id } => {
+            let run = db
+                .load_run(result_id(&id))
+                .await?
+                .ok_or(format!("No result {}", id))?;
+            println!("{}", serde_json::to_string_pretty(&run.result)?);
+            println!("{:?}", run.metrics);
+            for fill in &run.fills {
+                println!(
+                    "{}  {:?} {} {} at {:.2}",
+                    fill.timestamp, fill.side, fill.count, fill.symbol, fill.price_per_share
+                );
+            }
+            if let (Some(first), Some(last)) = (run.equity.first(), run.equity.last()) {
+                println!(
+                    "Equity {:.2} at {} to {:.2} at {} over {} bars",
+                    first.equity,
+                    first.timestamp,
+                    last.equity,
+                    last.timestamp,
+                    run.equity.len()
+                );
+            }
+        }
+        ResultsCommand::Delete { id } => {
+            db.delete_result(result_id(&id)).await?;
+            info!("Deleted result {}", id);
+        }
+        ResultsCommand::Compare { a, b } => {
+            let comparison = db
+                .compare_studies(&a, &b)
+                .await?
+                .ok_or(format!("Study {} or {} not found", a, b))?;
+            println!("{}", comparison);
+        }
+    }
     Ok(())
 }
+
+/// Apply, or with `dry_run` list, the pending schema migrations
+async fn migrate(
+    settings: &Settings,
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db = Db::open(
+        &settings.surreal_db_url,
+        &settings.surreal_db_user,
+        settings.db_pass(),
+    )
+    .await?;
+    let migrations = db.migrate(dry_run).await?;
+    if migrations.is_empty() {
+        println!("Schema is up to date");
+    }
+    for migration in migrations {
+        let state = if dry_run { "Pending" } else { "Applied" };
+        println!("{} {} {}", state, migration.version, migration.name);
+    }
+    Ok(())
+}
+
+/// Print the cash and shares of the depot
+async fn depot_state() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut depot = Alpaca::new().await?;
+    let state = depot.get_state().await.ok_or("Failed to get depot state")?;
+    println!("Cash {:.2}", state.cash);
+    for share in state.shares {
+        println!(
+            "{:<10} {:>8} at {:.2}",
+            share.symbol, share.count, share.price_per_share
+        );
+    }
+    Ok(())
+}
+
+/// Print the settings and what is wrong with them, fails if they are invalid
+fn config_check(settings: &Settings) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    println!(
+        "Profile: {}",
+        Settings::profile().unwrap_or_else(|| "none".to_string())
+    );
+    println!("{:#?}", settings);
+
+    // Missing credentials only matter to the commands using them
+    if let Err(e) = settings.alpaca_credentials() {
+        println!("Warning, live and download will fail: {}", e);
+    }
+    if settings.surreal_db_pass.is_none() && !is_embedded(&settings.surreal_db_url) {
+        println!(
+            "Warning, surreal_db_pass is not set for {}",
+            settings.surreal_db_url
+        );
+    }
+    settings.validate()?;
+    println!("Settings are valid");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mocking::mock::values_to_bar;
+    use chrono::{TimeZone, Utc};
+    use tonic::transport::server::TcpIncoming;
+    use tonic::transport::Channel;
+    use tonic::{Request, Response, Status};
+    use trader_bot::grpc_depot::init::depot;
+    use trader_bot::grpc_depot::init::depot::depot_client::DepotClient;
+    use trader_bot::grpc_depot::init::depot::depot_server::{Depot, DepotServer};
+    use trader_bot::indicator_client::init::calculate::indicator_client::IndicatorClient;
+
+    /// Actor as `live` sets it up, with services that are never reached
+    fn live_actor() -> Result<(Actor, Alpaca), Box<dyn std::error::Error + Send + Sync>> {
+        live_actor_on(Channel::from_static("http://127.0.0.1:9").connect_lazy())
+    }
+
+    /// Actor as `live` sets it up, with its services behind `channel`
+    fn live_actor_on(
+        channel: Channel,
+    ) -> Result<(Actor, Alpaca), Box<dyn std::error::Error + Send + Sync>> {
+        let settings = Settings::new()?;
+        let api_info = ApiInfo::from_parts(&settings.api_base_url, "", "")?;
+        let depot = Alpaca {
+            client: DepotClient::new(channel.clone()),
+            account: std::sync::Arc::new(Client::new(api_info)),
+        };
+        let evaluator =
+            Evaluator::with_clients(&settings, depot.clone(), IndicatorClient::new(channel));
+        Ok((Actor::with_evaluator(evaluator), depot))
+    }
+
+    #[tokio::test]
+    async fn test_live_bar_is_buffered() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let (mut actor, mut depot) = live_actor()?;
+        // Tuesday 10:00 in New York
+        let timestamp = Utc.with_ymd_and_hms(2024, 11, 26, 15, 0, 0).unwrap();
+        let bar = values_to_bar("TEST", timestamp, 10.0, 10.5, 11.0, 9.0, 100.0)?;
+
+        actor.trader(&mut depot, Data::Bar(bar)).await;
+
+        let buffer = actor
+            .evaluator
+            .buffer
+            .get("TEST")
+            .expect("Bar not buffered");
+        assert_eq!(buffer.bar_count(), 1);
+        assert_eq!(actor.evaluator.last_prices.get("TEST"), Some(&10.5));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_live_bars_build_entry_bars(
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let (mut actor, mut depot) = live_actor()?;
+        actor.evaluator.entry_timeframe = Some(chrono::Duration::minutes(5));
+        let start = Utc.with_ymd_and_hms(2024, 11, 26, 15, 0, 0).unwrap();
+        for i in 0..6 {
+            let timestamp = start + chrono::Duration::minutes(i);
+            let close = 10.0 + i as f64;
+            let bar = values_to_bar("TEST", timestamp, close, close, close, close, 100.0)?;
+            actor.trader(&mut depot, Data::Bar(bar)).await;
+        }
+
+        // 10:05 opened the second five minutes, the first is buffered as one bar
+        let bars = actor
+            .evaluator
+            .buffer
+            .get("TEST")
+            .and_then(|b| b.get_bars())
+            .unwrap();
+        assert_eq!(bars.len(), 1);
+        assert_eq!(bars[0].timestamp, start);
+        assert_eq!(bars[0].close_price.to_f64(), Some(14.0));
+        assert_eq!(actor.evaluator.last_prices.get("TEST"), Some(&15.0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_paused_symbols_restored() -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+    {
+        let (mut actor, _) = live_actor()?;
+        actor.evaluator.paused.insert("TEST".to_string());
+        let state = actor.evaluator.snapshot(Utc::now(), 10)?;
+
+        let (mut restarted, _) = live_actor()?;
+        restarted.evaluator.restore(state)?;
+        assert!(restarted.evaluator.paused.contains("TEST"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reload_keeps_default_strategy(
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let (mut actor, _) = live_actor()?;
+        actor.evaluator.eval_config = None;
+        let mut settings = Settings::new()?;
+        settings.live_strategy = None;
+
+        let params = LiveParams::from_settings(&settings, &actor.evaluator.live_params());
+        assert!(actor.evaluator.apply(params)?.is_empty());
+        assert!(actor.evaluator.eval_config.is_none());
+        Ok(())
+    }
+
+    /// Depot filling the orders of `filled` and rejecting every other one
+    struct FakeDepot {
+        filled: String,
+        orders: std::sync::Arc<std::sync::Mutex<Vec<(String, Side)>>>,
+    }
+
+    impl FakeDepot {
+        fn order(&self, symbol: &str, side: Side) -> Response<depot::TransactionResponse> {
+            self.orders.lock().unwrap().push((symbol.to_string(), side));
+            let success = symbol == self.filled;
+            Response::new(depot::TransactionResponse {
+                success,
+                message: if success { "filled" } else { "rejected" }.to_string(),
+                ..Default::default()
+            })
+        }
+    }
+
+    #[tonic::async_trait]
+    impl Depot for FakeDepot {
+        async fn deposit(
+            &self,
+            _: Request<depot::DepositRequest>,
+        ) -> Result<Response<depot::TransactionResponse>, Status> {
+            Err(Status::unimplemented("deposit"))
+        }
+        async fn withdraw(
+            &self,
+            _: Request<depot::WithdrawRequest>,
+        ) -> Result<Response<depot::TransactionResponse>, Status> {
+            Err(Status::unimplemented("withdraw"))
+        }
+        async fn buy_shares(
+            &self,
+            request: Request<depot::BuyRequest>,
+        ) -> Result<Response<depot::TransactionResponse>, Status> {
+            Ok(self.order(&request.into_inner().symbol, Side::Buy))
+        }
+        async fn sell_shares(
+            &self,
+            request: Request<depot::SellRequest>,
+        ) -> Result<Response<depot::TransactionResponse>, Status> {
+            Ok(self.order(&request.into_inner().symbol, Side::Sell))
+        }
+        async fn get_state(
+            &self,
+            _: Request<depot::Empty>,
+        ) -> Result<Response<depot::StateResponse>, Status> {
+            Err(Status::unimplemented("get_state"))
+        }
+        async fn get_gain(
+            &self,
+            _: Request<depot::Empty>,
+        ) -> Result<Response<depot::GainResponse>, Status> {
+            Err(Status::unimplemented("get_gain"))
+        }
+        async fn get_share_balance(
+            &self,
+            _: Request<depot::StockRequest>,
+        ) -> Result<Response<depot::ShareBalanceResponse>, Status> {
+            Err(Status::unimplemented("get_share_balance"))
+        }
+        async fn get_transactions(
+            &self,
+            _: Request<depot::StockRequest>,
+        ) -> Result<Response<depot::TransactionsList>, Status> {
+            Err(Status::unimplemented("get_transactions"))
+        }
+        async fn reset_stock(
+            &self,
+            _: Request<depot::StockRequest>,
+        ) -> Result<Response<depot::StateResponse>, Status> {
+            Err(Status::unimplemented("reset_stock"))
+        }
+        async fn reset_cash(
+            &self,
+            _: Request<depot::Empty>,
+        ) -> Result<Response<depot::StateResponse>, Status> {
+            Err(Status::unimplemented("reset_cash"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unhedged_pair_leg_closed() -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+    {
+        let orders = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let fake = FakeDepot {
+            filled: "AAA".to_string(),
+            orders: orders.clone(),
+        };
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(DepotServer::new(fake))
+                .serve_with_incoming(TcpIncoming::from(listener)),
+        );
+        let channel = Channel::from_shared(format!("http://{}", addr))?.connect_lazy();
+        let (mut actor, _) = live_actor_on(channel)?;
+        let mut events = actor.evaluator.events.subscribe();
+
+        // The hedge BBB is rejected, so AAA is sold again
+        let placed = actor
+            .evaluator
+            .place_pair(("AAA", Side::Buy, 10, 5.0), ("BBB", Side::Sell, 4, 12.0))
+            .await;
+        assert!(!placed);
+        assert_eq!(
+            *orders.lock().unwrap(),
+            vec![
+                ("AAA".to_string(), Side::Buy),
+                ("BBB".to_string(), Side::Sell),
+                ("AAA".to_string(), Side::Sell),
+            ]
+        );
+
+        let mut breached = vec![];
+        while let Ok(event) = events.try_recv() {
+            if let EventKind::RiskBreach(_) = event.kind {
+                breached.push(event.symbol);
+            }
+        }
+        assert_eq!(breached, vec!["AAA", "BBB"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unconfirmed_order_not_recorded(
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let (mut actor, _) = live_actor()?;
+        // The depot can't be reached
+        let price = actor.evaluator.place(Side::Buy, "TEST", 1, 10.0).await;
+        assert_eq!(price, None);
+        assert!(actor.evaluator.trades.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_equity_valued_from_cached_holdings(
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let (mut actor, _) = live_actor()?;
+        let start = Utc.with_ymd_and_hms(2024, 11, 26, 15, 0, 0).unwrap();
+        let holdings = depot::StateResponse {
+            cash: 1000.0,
+            shares: vec![depot::ShareDetails {
+                symbol: "TEST".to_string(),
+                count: 10,
+                ..Default::default()
+            }],
+        };
+        actor.evaluator.holdings = Some((start, holdings));
+        actor.evaluator.max_history = Some(2);
+
+        // The depot can't be reached, every point is valued from the holdings
+        for i in 0..3 {
+            let close = 10.0 + i as f64;
+            let timestamp = start + chrono::Duration::minutes(i);
+            let bar = values_to_bar("TEST", timestamp, close, close, close, close, 100.0)?;
+            actor.evaluator.record_equity(&bar).await;
+        }
+        let equity: Vec<f64> = actor.evaluator.equity.iter().map(|p| p.1).collect();
+        assert_eq!(equity, vec![1110.0, 1120.0]);
+        Ok(())
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use csv::ReaderBuilder;
+use serde::Deserialize;
+
+use crate::error::CLIError;
+use crate::mocking::layout::CsvRow;
+
+/// How historical prices are adjusted before replay
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum Adjustment {
+    /// Replay the prices as written
+    #[default]
+    None,
+    /// Scale OHLC by `Adj Close / Close`, which covers splits and dividends
+    AdjClose,
+    /// Back-adjust for the splits in `file` and credit its dividends as cash
+    ///
+    /// Dividends are not taken out of the prices, otherwise they would be
+    /// counted twice.
+    Events { file: String },
+}
+
+/// A split or dividend taking effect on `date`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorporateAction {
+    pub date: NaiveDate,
+    pub kind: ActionKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ActionKind {
+    /// New shares per old share, 2.0 for a 2:1 split
+    Split(f64),
+    /// Cash per share
+    Dividend(f64),
+}
+
+/// Dividend per adjusted share, keyed by symbol and ex-date
+pub type Dividends = HashMap<(String, NaiveDate), f64>;
+
+#[derive(Debug, Deserialize)]
+struct ActionRecord {
+    #[serde(rename = "Date")]
+    date: String,
+    #[serde(rename = "Action")]
+    action: String,
+    #[serde(rename = "Value")]
+    value: String,
+}
+
+/// Parse a split ratio written as `2`, `2.0` or `2:1`
+fn parse_ratio(value: &str) -> Option<f64> {
+    let ratio = match value.split_once(':') {
+        Some((new, old)) => {
+            let (new, old) = (
+                new.trim().parse::<f64>().ok()?,
+                old.trim().parse::<f64>().ok()?,
+            );
+            (old > 0.0).then_some(new / old)?
+        }
+        None => value.trim().parse::<f64>().ok()?,
+    };
+    (ratio > 0.0).then_some(ratio)
+}
+
+/// Read splits and dividends from a CSV file with `Date,Action,Value` columns
+///
+/// `Action` is `split` or `dividend`, dates are `%Y-%m-%d`.
+pub fn read_actions(
+    filename: &str,
+) -> Result<Vec<CorporateAction>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut reader = ReaderBuilder::new().has_headers(true).from_path(filename)?;
+    let mut actions = Vec::new();
+
+    for result in reader.deserialize() {
+        let record: ActionRecord = result?;
+        let date = NaiveDate::parse_from_str(record.date.trim(), "%Y-%m-%d")
+            .map_err(|_| CLIError::InvalidTimestamp(record.date.clone()))?;
+        let kind = match record.action.trim().to_lowercase().as_str() {
+            "split" => {
+                ActionKind::Split(parse_ratio(&record.value).ok_or(CLIError::ConvertingError)?)
+            }
+            "dividend" => ActionKind::Dividend(
+                record
+                    .value
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|_| CLIError::ConvertingError)?,
+            ),
+            _ => return Err(CLIError::ConvertingError.into()),
+        };
+        actions.push(CorporateAction { date, kind });
+    }
+
+    actions.sort_by_key(|a| a.date);
+    Ok(actions)
+}
+
+/// Scale each row's OHLC by its `adj_close / close` factor
+///
+/// Rows without an adjusted close are left as they are.
+pub fn adjust_by_adj_close(rows: &mut [CsvRow]) {
+    for row in rows.iter_mut() {
+        let Some(adj_close) = row.adj_close else {
+            continue;
+        };
+        if row.close <= 0.0 {
+            continue;
+        }
+        let factor = adj_close / row.close;
+        row.open *= factor;
+        row.high *= factor;
+        row.low *= factor;
+        row.close = adj_close;
+    }
+}
+
+/// Product of `1 / ratio` over all splits after `date`
+fn split_factor(date: NaiveDate, actions: &[CorporateAction]) -> f64 {
+    actions
+        .iter()
+        .filter(|a| a.date > date)
+        .filter_map(|a| match a.kind {
+            ActionKind::Split(ratio) => Some(1.0 / ratio),
+            ActionKind::Dividend(_) => None,
+        })
+        .product()
+}
+
+/// Back-adjust prices and volumes of all rows before each split
+pub fn adjust_for_splits(rows: &mut [CsvRow], actions: &[CorporateAction]) {
+    for row in rows.iter_mut() {
+        let factor = split_factor(row.timestamp.date_naive(), actions);
+        if factor == 1.0 {
+            continue;
+        }
+        row.open *= factor;
+        row.high *= factor;
+        row.low *= factor;
+        row.close *= factor;
+        row.volume /= factor;
+    }
+}
+
+/// Dividends per split-adjusted share of `symbol`
+pub fn dividends(symbol: &str, actions: &[CorporateAction]) -> Dividends {
+    actions
+        .iter()
+        .filter_map(|a| match a.kind {
+            ActionKind::Dividend(amount) => Some((
+                (symbol.to_string(), a.date),
+                amount * split_factor(a.date, actions),
+            )),
+            ActionKind::Split(_) => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn row(day: u32, close: f64, adj_close: f64) -> CsvRow {
+        CsvRow {
+            symbol: "TEST".to_string(),
+            timestamp: Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap(),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            adj_close: Some(adj_close),
+            volume: 100.0,
+        }
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    #[test]
+    fn test_parse_ratio() {
+        assert_eq!(parse_ratio("2:1"), Some(2.0));
+        assert_eq!(parse_ratio("1:4"), Some(0.25));
+        assert_eq!(parse_ratio("3"), Some(3.0));
+        assert_eq!(parse_ratio("0:1"), None);
+    }
+
+    #[test]
+    fn test_adjust_by_adj_close() {
+        let mut rows = vec![row(2, 100.0, 50.0)];
+        adjust_by_adj_close(&mut rows);
+        assert_eq!(rows[0].open, 50.0);
+        assert_eq!(rows[0].close, 50.0);
+    }
+
+    #[test]
+    fn test_adjust_for_splits() {
+        let mut rows = vec![row(2, 100.0, 100.0), row(3, 50.0, 50.0)];
+        let actions = vec![CorporateAction {
+            date: date(3),
+            kind: ActionKind::Split(2.0),
+        }];
+
+        adjust_for_splits(&mut rows, &actions);
+        assert_eq!(rows[0].close, 50.0);
+        assert_eq!(rows[0].volume, 200.0);
+        // Bars from the split date on are already post-split
+        assert_eq!(rows[1].close, 50.0);
+    }
+
+    #[test]
+    fn test_dividends_split_adjusted() {
+        let actions = vec![
+            CorporateAction {
+                date: date(2),
+                kind: ActionKind::Dividend(1.0),
+            },
+            CorporateAction {
+                date: date(3),
+                kind: ActionKind::Split(2.0),
+            },
+            CorporateAction {
+                date: date(4),
+                kind: ActionKind::Dividend(0.5),
+            },
+        ];
+
+        let dividends = dividends("TEST", &actions);
+        assert_eq!(dividends[&("TEST".to_string(), date(2))], 0.5);
+        assert_eq!(dividends[&("TEST".to_string(), date(4))], 0.5);
+    }
+
+    #[test]
+    fn test_read_actions() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "Date,Action,Value")?;
+        writeln!(file, "2024-01-03,split,2:1")?;
+        writeln!(file, "2024-01-02,dividend,0.25")?;
+
+        let actions = read_actions(file.path().to_str().unwrap())?;
+        assert_eq!(actions[0].kind, ActionKind::Dividend(0.25));
+        assert_eq!(actions[1].kind, ActionKind::Split(2.0));
+
+        Ok(())
+    }
+}
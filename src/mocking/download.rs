@@ -0,0 +1,254 @@
+use apca::data::v2::bars::{self, ListReqInit, TimeFrame};
+use apca::{Client, RequestError};
+use chrono::{DateTime, Utc};
+use num_traits::ToPrimitive;
+use std::future::Future;
+use tracing::{info, warn};
+
+use crate::error::CLIError;
+use crate::mocking::layout::CsvRow;
+use crate::mocking::store::{BarStore, ImportReport};
+
+/// Bars requested per page, the maximum of the data API
+const PAGE_LIMIT: usize = 10_000;
+
+/// Source of bar pages, the data API outside of tests
+pub trait BarPages {
+    fn page(
+        &self,
+        request: &bars::ListReq,
+    ) -> impl Future<Output = Result<bars::Bars, RequestError<bars::ListError>>> + Send;
+}
+
+impl BarPages for Client {
+    fn page(
+        &self,
+        request: &bars::ListReq,
+    ) -> impl Future<Output = Result<bars::Bars, RequestError<bars::ListError>>> + Send {
+        self.issue::<bars::List>(request)
+    }
+}
+
+/// Downloads historical bars from the Alpaca data API into the bar store
+///
+/// Requests are spaced by `min_interval` to stay below the API's rate limit.
+/// Rejected requests (HTTP 429) are retried with a doubling backoff.
+pub struct Downloader<P: BarPages = Client> {
+    client: P,
+    min_interval: std::time::Duration,
+    max_retries: u32,
+}
+
+impl<P: BarPages> Downloader<P> {
+    pub fn new(client: P) -> Self {
+        Self {
+            client,
+            // 200 requests per minute on the free plan
+            min_interval: std::time::Duration::from_millis(300),
+            max_retries: 5,
+        }
+    }
+
+    /// Space requests by `interval` and back off starting at it
+    pub fn with_interval(mut self, interval: std::time::Duration) -> Self {
+        self.min_interval = interval;
+        self
+    }
+
+    /// All bars of `symbol` in `start..end`, following the page tokens
+    pub async fn bars(
+        &self,
+        symbol: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        timeframe: &str,
+    ) -> Result<Vec<CsvRow>, Box<dyn std::error::Error + Send + Sync>> {
+        let time_frame = time_frame(timeframe).ok_or(CLIError::ConvertingError)?;
+        let mut rows = vec![];
+        let mut page_token = None;
+
+        loop {
+            let request = ListReqInit {
+                limit: Some(PAGE_LIMIT),
+                page_token: page_token.take(),
+                ..Default::default()
+            }
+            .init(symbol, start, end, time_frame);
+
+            let page = self.issue(&request).await?;
+            for bar in page.bars {
+                rows.push(CsvRow {
+                    symbol: symbol.to_string(),
+                    timestamp: bar.time,
+                    open: bar.open.to_f64().ok_or(CLIError::ConvertingError)?,
+                    high: bar.high.to_f64().ok_or(CLIError::ConvertingError)?,
+                    low: bar.low.to_f64().ok_or(CLIError::ConvertingError)?,
+                    close: bar.close.to_f64().ok_or(CLIError::ConvertingError)?,
+                    adj_close: None,
+                    volume: bar.volume.to_f64().ok_or(CLIError::ConvertingError)?,
+                });
+            }
+
+            match page.next_page_token {
+                Some(token) => page_token = Some(token),
+                None => return Ok(rows),
+            }
+        }
+    }
+
+    /// Download `symbols` and merge them into their `timeframe` partitions
+    pub async fn download(
+        &self,
+        store: &BarStore,
+        symbols: &[String],
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        timeframe: &str,
+    ) -> Result<Vec<ImportReport>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut reports = vec![];
+        for symbol in symbols {
+            let rows = self.bars(symbol, start, end, timeframe).await?;
+            info!("Downloaded {} bars of {}", rows.len(), symbol);
+            reports.push(store.write(symbol, timeframe, rows)?);
+        }
+        Ok(reports)
+    }
+
+    /// Issue one page request, waiting out the rate limit
+    async fn issue(
+        &self,
+        request: &bars::ListReq,
+    ) -> Result<bars::Bars, Box<dyn std::error::Error + Send + Sync>> {
+        let mut backoff = self.min_interval;
+        let mut retries = 0;
+
+        loop {
+            tokio::time::sleep(self.min_interval).await;
+            match self.client.page(request).await {
+                Ok(page) => return Ok(page),
+                Err(RequestError::Endpoint(bars::ListError::UnexpectedStatus(status, _)))
+                    if status.as_u16() == 429 && retries < self.max_retries =>
+                {
+                    warn!("Rate limited, retrying in {:?}", backoff);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    retries += 1;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+/// Data API timeframe of a bar store timeframe like `1d`
+fn time_frame(timeframe: &str) -> Option<TimeFrame> {
+    match timeframe {
+        "1m" => Some(TimeFrame::OneMinute),
+        "1h" => Some(TimeFrame::OneHour),
+        "1d" => Some(TimeFrame::OneDay),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+    use tempfile::tempdir;
+    use tonic::codegen::http::StatusCode;
+
+    /// Serves `responses` in order and records the page token of every request
+    struct ScriptedPages {
+        responses: Mutex<VecDeque<(u16, String)>>,
+        tokens: Mutex<Vec<Option<String>>>,
+    }
+
+    impl ScriptedPages {
+        fn new(responses: Vec<(u16, String)>) -> Self {
+            Self {
+                responses: Mutex::new(responses.into()),
+                tokens: Mutex::new(vec![]),
+            }
+        }
+    }
+
+    impl BarPages for ScriptedPages {
+        fn page(
+            &self,
+            request: &bars::ListReq,
+        ) -> impl Future<Output = Result<bars::Bars, RequestError<bars::ListError>>> + Send
+        {
+            self.tokens.lock().unwrap().push(request.page_token.clone());
+            let (status, body) = self.responses.lock().unwrap().pop_front().unwrap();
+            async move {
+                match status {
+                    200 => Ok(serde_json::from_str(&body).unwrap()),
+                    _ => Err(RequestError::Endpoint(bars::ListError::UnexpectedStatus(
+                        StatusCode::from_u16(status).unwrap(),
+                        Err(body.into_bytes()),
+                    ))),
+                }
+            }
+        }
+    }
+
+    fn page(day: u32, close: f64, next_page_token: Option<&str>) -> String {
+        serde_json::json!({
+            "bars": [{
+                "t": format!("2024-01-{:02}T05:00:00Z", day),
+                "o": close, "h": close + 1.0, "l": close - 1.0, "c": close,
+                "v": 1000, "n": 10, "vw": close
+            }],
+            "symbol": "ORCL",
+            "next_page_token": next_page_token,
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_download_pages_and_rate_limit(
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let pages = ScriptedPages::new(vec![
+            (429, r#"{"message":"too many requests"}"#.to_string()),
+            (200, page(2, 100.0, Some("next"))),
+            (200, page(3, 101.0, None)),
+        ]);
+        let downloader = Downloader::new(pages).with_interval(std::time::Duration::ZERO);
+
+        let dir = tempdir()?;
+        let store = BarStore::new(dir.path().to_str().unwrap());
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(2024, 1, 31, 0, 0, 0).unwrap();
+        let reports = downloader
+            .download(&store, &["ORCL".to_string()], start, end, "1d")
+            .await?;
+
+        assert_eq!(reports[0].rows, 2);
+        let closes: Vec<f64> = store.read("ORCL", "1d")?.iter().map(|r| r.close).collect();
+        assert_eq!(closes, [100.0, 101.0]);
+
+        let tokens = downloader.client.tokens.lock().unwrap();
+        assert_eq!(*tokens, [None, None, Some("next".to_string())]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_gives_up() {
+        let pages = ScriptedPages::new(vec![(429, String::new()); 6]);
+        let downloader = Downloader::new(pages).with_interval(std::time::Duration::ZERO);
+
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(2024, 1, 31, 0, 0, 0).unwrap();
+        assert!(downloader.bars("ORCL", start, end, "1d").await.is_err());
+        assert_eq!(downloader.client.tokens.lock().unwrap().len(), 6);
+    }
+
+    #[test]
+    fn test_time_frame() {
+        assert!(time_frame("1d").is_some());
+        assert!(time_frame("5m").is_none());
+    }
+}
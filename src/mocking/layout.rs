@@ -0,0 +1,219 @@
+use apca::data::v2::stream::Bar;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+use crate::error::CLIError;
+use crate::mocking::mock::values_to_bar;
+
+/// Column names and timestamp format of an OHLCV CSV export
+///
+/// The default matches Yahoo Finance downloads like `files/orcl.csv`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CsvLayout {
+    pub timestamp: String,
+    pub open: String,
+    pub high: String,
+    pub low: String,
+    pub close: String,
+    pub adj_close: Option<String>,
+    pub volume: String,
+    /// Column holding the symbol, for files with several symbols
+    pub symbol: Option<String>,
+    pub timestamp_format: TimestampFormat,
+    /// IANA timezone of timestamps without an offset, e.g. `America/New_York`
+    pub timezone: Option<String>,
+    pub delimiter: char,
+}
+
+/// How the timestamp column is written
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TimestampFormat {
+    /// Date only, the bar is placed at midnight, e.g. `%Y-%m-%d`
+    Date { format: String },
+    /// Date and time without offset, e.g. `%Y-%m-%d %H:%M:%S`
+    DateTime { format: String },
+    /// Date and time with offset, e.g. `2024-01-02T14:30:00Z`
+    Rfc3339,
+    /// Milliseconds since the Unix epoch
+    EpochMillis,
+    /// Seconds since the Unix epoch
+    EpochSeconds,
+}
+
+impl Default for CsvLayout {
+    fn default() -> Self {
+        Self {
+            timestamp: "Date".to_string(),
+            open: "Open".to_string(),
+            high: "High".to_string(),
+            low: "Low".to_string(),
+            close: "Close".to_string(),
+            adj_close: Some("Adj Close".to_string()),
+            volume: "Volume".to_string(),
+            symbol: None,
+            timestamp_format: TimestampFormat::default(),
+            timezone: None,
+            delimiter: ',',
+        }
+    }
+}
+
+impl Default for TimestampFormat {
+    fn default() -> Self {
+        TimestampFormat::Date {
+            format: "%Y-%m-%d".to_string(),
+        }
+    }
+}
+
+/// One parsed CSV line
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvRow {
+    pub symbol: String,
+    pub timestamp: DateTime<Utc>,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub adj_close: Option<f64>,
+    pub volume: f64,
+}
+
+impl CsvRow {
+    pub fn to_bar(&self) -> Result<Bar, CLIError> {
+        values_to_bar(
+            &self.symbol,
+            self.timestamp,
+            self.open,
+            self.close,
+            self.high,
+            self.low,
+            self.volume,
+        )
+    }
+
+    /// Row of a streamed bar, fails if a price does not fit a `f64`
+    pub fn from_bar(bar: &Bar) -> Result<Self, CLIError> {
+        let value = |num: &num_decimal::Num| num.to_f64().ok_or(CLIError::ConvertingError);
+        Ok(Self {
+            symbol: bar.symbol.clone(),
+            timestamp: bar.timestamp,
+            open: value(&bar.open_price)?,
+            high: value(&bar.high_price)?,
+            low: value(&bar.low_price)?,
+            close: value(&bar.close_price)?,
+            adj_close: None,
+            volume: value(&bar.volume)?,
+        })
+    }
+}
+
+impl CsvLayout {
+    /// Read timestamps without offset in `timezone`, unless one is configured
+    pub fn or_timezone(mut self, timezone: Tz) -> Self {
+        if self.timezone.is_none() {
+            self.timezone = Some(timezone.name().to_string());
+        }
+        self
+    }
+
+    /// Parsed `timezone`, `None` for UTC
+    pub fn timezone(&self) -> Result<Option<Tz>, CLIError> {
+        self.timezone
+            .as_ref()
+            .map(|tz| {
+                tz.parse::<Tz>()
+                    .map_err(|_| CLIError::InvalidTimestamp(format!("unknown timezone {}", tz)))
+            })
+            .transpose()
+    }
+}
+
+/// Parse a timestamp written in `format`
+///
+/// `timezone` is used for formats without an offset and defaults to UTC.
+pub fn parse_timestamp(
+    value: &str,
+    format: &TimestampFormat,
+    timezone: Option<Tz>,
+) -> Result<DateTime<Utc>, CLIError> {
+    let invalid = || CLIError::InvalidTimestamp(value.to_string());
+    let value = value.trim();
+
+    let naive = match format {
+        TimestampFormat::Date { format } => NaiveDate::parse_from_str(value, format)
+            .map_err(|_| invalid())?
+            .and_hms_opt(0, 0, 0)
+            .ok_or_else(invalid)?,
+        TimestampFormat::DateTime { format } => {
+            NaiveDateTime::parse_from_str(value, format).map_err(|_| invalid())?
+        }
+        TimestampFormat::Rfc3339 => {
+            return DateTime::parse_from_rfc3339(value)
+                .map(|t| t.with_timezone(&Utc))
+                .map_err(|_| invalid());
+        }
+        TimestampFormat::EpochMillis => {
+            let millis = value.parse::<i64>().map_err(|_| invalid())?;
+            return DateTime::from_timestamp_millis(millis).ok_or_else(invalid);
+        }
+        TimestampFormat::EpochSeconds => {
+            let seconds = value.parse::<i64>().map_err(|_| invalid())?;
+            return DateTime::from_timestamp(seconds, 0).ok_or_else(invalid);
+        }
+    };
+
+    match timezone {
+        Some(tz) => tz
+            .from_local_datetime(&naive)
+            .earliest()
+            .map(|t| t.with_timezone(&Utc))
+            .ok_or_else(invalid),
+        None => Ok(Utc.from_utc_datetime(&naive)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timestamp_formats() {
+        let expected = Utc.with_ymd_and_hms(2024, 1, 2, 14, 30, 0).unwrap();
+
+        let format = TimestampFormat::DateTime {
+            format: "%Y-%m-%d %H:%M".to_string(),
+        };
+        assert_eq!(
+            parse_timestamp("2024-01-02 14:30", &format, None).unwrap(),
+            expected
+        );
+        assert_eq!(
+            parse_timestamp("2024-01-02T09:30:00-05:00", &TimestampFormat::Rfc3339, None).unwrap(),
+            expected
+        );
+        assert_eq!(
+            parse_timestamp("1704205800000", &TimestampFormat::EpochMillis, None).unwrap(),
+            expected
+        );
+        assert_eq!(
+            parse_timestamp("1704205800", &TimestampFormat::EpochSeconds, None).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_parse_timestamp_timezone() {
+        let format = TimestampFormat::DateTime {
+            format: "%Y-%m-%d %H:%M".to_string(),
+        };
+        let tz: Tz = "America/New_York".parse().unwrap();
+        assert_eq!(
+            parse_timestamp("2024-01-02 09:30", &format, Some(tz)).unwrap(),
+            Utc.with_ymd_and_hms(2024, 1, 2, 14, 30, 0).unwrap()
+        );
+    }
+}
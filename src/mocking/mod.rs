@@ -1 +1,7 @@
+pub mod adjust;
+pub mod download;
+pub mod layout;
 pub mod mock;
+pub mod recording;
+pub mod source;
+pub mod store;
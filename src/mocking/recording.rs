@@ -0,0 +1,195 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader};
+
+use apca::data::v2::stream::{Bar, Data, Quote, Trade};
+use chrono::{DateTime, Utc};
+use futures::stream::{self, BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::mocking::source::{pace, BarStream, ReplaySpeed};
+
+/// How often buffered messages are written to the recording
+const FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// One recorded message of the live stream
+#[derive(Debug, Serialize, Deserialize)]
+struct Record {
+    /// When the message was received, which is what replays are paced by
+    received_at: DateTime<Utc>,
+    data: Message,
+}
+
+/// Market data of a record, tagged like the Alpaca stream
+///
+/// `Data` of the stream can't be serialized, its variants can.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "T")]
+enum Message {
+    #[serde(rename = "b")]
+    Bar(Bar),
+    #[serde(rename = "q")]
+    Quote(Quote),
+    #[serde(rename = "t")]
+    Trade(Trade),
+}
+
+impl From<Message> for Data<Bar, Quote, Trade> {
+    fn from(message: Message) -> Self {
+        match message {
+            Message::Bar(bar) => Data::Bar(bar),
+            Message::Quote(quote) => Data::Quote(quote),
+            Message::Trade(trade) => Data::Trade(trade),
+        }
+    }
+}
+
+/// Appends every live market data message to a JSON lines file
+///
+/// Messages are handed to a writer task that buffers them and flushes every
+/// `FLUSH_INTERVAL`, so the stream never waits for the disk. A crashed
+/// session loses at most the messages of the last interval.
+pub struct Recorder {
+    sender: mpsc::UnboundedSender<Record>,
+    writer: JoinHandle<std::io::Result<()>>,
+}
+
+impl Recorder {
+    /// Open `path` for appending, creating it if needed
+    pub fn open(path: &str) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let writer = tokio::spawn(write_records(tokio::fs::File::from_std(file), receiver));
+        Ok(Self { sender, writer })
+    }
+
+    pub fn record(
+        &self,
+        data: &Data<Bar, Quote, Trade>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.record_at(Utc::now(), data)
+    }
+
+    fn record_at(
+        &self,
+        received_at: DateTime<Utc>,
+        data: &Data<Bar, Quote, Trade>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let data = match data {
+            Data::Bar(bar) => Message::Bar(bar.clone()),
+            Data::Quote(quote) => Message::Quote(quote.clone()),
+            Data::Trade(trade) => Message::Trade(trade.clone()),
+            // Kinds added to the stream later aren't recorded
+            _ => return Ok(()),
+        };
+        let record = Record { received_at, data };
+        // Only fails once the writer stopped on an error
+        self.sender
+            .send(record)
+            .map_err(|_| "Recording writer stopped")?;
+        Ok(())
+    }
+
+    /// Write the remaining messages and close the file
+    pub async fn close(self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        drop(self.sender);
+        self.writer.await??;
+        Ok(())
+    }
+}
+
+async fn write_records(
+    file: tokio::fs::File,
+    mut receiver: mpsc::UnboundedReceiver<Record>,
+) -> std::io::Result<()> {
+    let mut file = BufWriter::new(file);
+    let mut flush = tokio::time::interval(FLUSH_INTERVAL);
+    loop {
+        tokio::select! {
+            record = receiver.recv() => {
+                let Some(record) = record else { break };
+                let mut line = serde_json::to_string(&record)?;
+                line.push('\n');
+                file.write_all(line.as_bytes()).await?;
+            }
+            _ = flush.tick() => file.flush().await?,
+        }
+    }
+    file.flush().await
+}
+
+/// Stream a recording back, paced by the receive timestamps
+///
+/// `ReplaySpeed::RealTime { multiple: 1.0 }` reproduces the original timing.
+pub fn recording(
+    path: &str,
+    speed: ReplaySpeed,
+) -> Result<BarStream, Box<dyn std::error::Error + Send + Sync>> {
+    let lines = BufReader::new(File::open(path)?).lines();
+
+    let records: BoxStream<'static, Result<Record, Box<dyn std::error::Error + Send + Sync>>> =
+        stream::iter(lines)
+            .filter_map(|line| async move {
+                match line {
+                    Ok(line) if line.trim().is_empty() => None,
+                    Ok(line) => Some(serde_json::from_str::<Record>(&line).map_err(|e| e.into())),
+                    Err(e) => Some(Err(e.into())),
+                }
+            })
+            .boxed();
+
+    let paced = pace(records, speed, |item| {
+        item.as_ref().ok().map(|record| record.received_at)
+    });
+    Ok(paced
+        .map(|item| item.map(|record| record.data.into()))
+        .boxed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mocking::mock::values_to_bar;
+    use chrono::{Duration, TimeZone};
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_record_and_replay() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let dir = tempdir()?;
+        let path = dir.path().join("live.jsonl");
+        let path = path.to_str().unwrap();
+
+        let start = Utc.with_ymd_and_hms(2024, 1, 2, 14, 30, 0).unwrap();
+        let recorder = Recorder::open(path)?;
+        for (i, close) in [10.0, 10.5].into_iter().enumerate() {
+            let bar = values_to_bar("TEST", start, close, close, close, close, 100.0)?;
+            recorder.record_at(
+                start + Duration::milliseconds(50 * i as i64),
+                &Data::Bar(bar),
+            )?;
+        }
+        recorder.close().await?;
+        // Reopening appends instead of truncating
+        let recorder = Recorder::open(path)?;
+        let bar = values_to_bar("TEST", start, 11.0, 11.0, 11.0, 11.0, 100.0)?;
+        recorder.record_at(start + Duration::milliseconds(100), &Data::Bar(bar))?;
+        recorder.close().await?;
+
+        let started = std::time::Instant::now();
+        let closes: Vec<String> = recording(path, ReplaySpeed::RealTime { multiple: 1.0 })?
+            .map(|item| match item.unwrap() {
+                Data::Bar(bar) => bar.close_price.to_string(),
+                _ => panic!("Expected Data::Bar"),
+            })
+            .collect()
+            .await;
+
+        assert_eq!(closes, ["10", "10.5", "11"]);
+        // The original 100ms between the first and last message are kept
+        assert!(started.elapsed() >= std::time::Duration::from_millis(100));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1017 @@
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use apca::data::v2::stream::{Bar, Data, Quote, Trade};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use futures::stream::{self, BoxStream, Peekable};
+use futures::StreamExt;
+use polars::prelude::{
+    col, lit, DataFrame, DataType, Expr, IdxSize, LazyCsvReader, LazyFileListReader, LazyFrame,
+    PlPath, PolarsError, ScanArgsParquet, TimeUnit,
+};
+use serde::Deserialize;
+
+use crate::error::CLIError;
+use crate::mocking::adjust::{self, Adjustment, CorporateAction, Dividends};
+use crate::mocking::layout::{parse_timestamp, CsvLayout, CsvRow, TimestampFormat};
+use crate::wrangling::calendar::TradingCalendar;
+use crate::wrangling::quality::BarValidator;
+
+/// Stream of historical market data, as consumed by `Actor::trader`
+pub type BarStream =
+    BoxStream<'static, Result<Data<Bar, Quote, Trade>, Box<dyn std::error::Error + Send + Sync>>>;
+
+/// Rows collected and converted into bars at a time
+const BATCH_SIZE: usize = 10_000;
+
+/// File format of a historical source
+#[derive(Debug, Clone)]
+pub enum SourceFormat {
+    Csv(Box<CsvLayout>),
+    /// Columns `symbol`, `timestamp` (milliseconds, UTC), `open`, `high`,
+    /// `low`, `close`, `volume` and optionally `adj_close`
+    Parquet,
+}
+
+/// How fast bars are handed out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum ReplaySpeed {
+    #[default]
+    AsFastAsPossible,
+    /// Sleep the same time before every bar
+    FixedDelay { millis: u64 },
+    /// Keep the time between bars, divided by `multiple`
+    RealTime { multiple: f64 },
+}
+
+/// Historical bars of one file, read through Polars
+///
+/// The file is scanned lazily and collected in batches of `BATCH_SIZE` rows,
+/// so only one batch is in memory, whatever the size of the file. Epoch
+/// timestamps are filtered to the range in the scan, formatted ones after
+/// they are parsed. Rows are expected in time order, except for CSV files with
+/// a symbol column: those are collected completely and sorted, because
+/// interleaving symbols needs a global order.
+#[derive(Debug, Clone)]
+pub struct HistoricalSource {
+    path: String,
+    symbol: String,
+    format: SourceFormat,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    resample: Option<Duration>,
+    adjustment: Adjustment,
+    validator: Option<SharedValidator>,
+}
+
+/// Validator shared by the sources of a run, to report their counts together
+pub type SharedValidator = Arc<Mutex<BarValidator>>;
+
+impl HistoricalSource {
+    /// CSV file in `layout`, `symbol` is used if the layout has no symbol column
+    pub fn csv(path: &str, symbol: &str, layout: CsvLayout) -> Self {
+        Self::new(path, symbol, SourceFormat::Csv(Box::new(layout)))
+    }
+
+    /// Parquet file with the normalized bar columns
+    pub fn parquet(path: &str, symbol: &str) -> Self {
+        Self::new(path, symbol, SourceFormat::Parquet)
+    }
+
+    fn new(path: &str, symbol: &str, format: SourceFormat) -> Self {
+        Self {
+            path: path.to_string(),
+            symbol: symbol.to_string(),
+            format,
+            start: None,
+            end: None,
+            resample: None,
+            adjustment: Adjustment::None,
+            validator: None,
+        }
+    }
+
+    /// Only replay bars within `start..=end`
+    pub fn between(mut self, start: Option<DateTime<Utc>>, end: Option<DateTime<Utc>>) -> Self {
+        self.start = start;
+        self.end = end;
+        self
+    }
+
+    /// Aggregate bars into coarser bars of `size`, aligned to the Unix epoch
+    pub fn resample(mut self, size: Option<Duration>) -> Self {
+        self.resample = size;
+        self
+    }
+
+    /// Adjust prices for splits and dividends
+    pub fn adjusted(mut self, adjustment: Adjustment) -> Self {
+        self.adjustment = adjustment;
+        self
+    }
+
+    /// Check bars with `validator` before they are resampled
+    pub fn validated(mut self, validator: SharedValidator) -> Self {
+        self.validator = Some(validator);
+        self
+    }
+
+    /// Dividends per share to credit on their ex-dates, for every symbol of the rows
+    pub fn dividends(&self) -> Result<Dividends, Box<dyn std::error::Error + Send + Sync>> {
+        let Adjustment::Events { file } = &self.adjustment else {
+            return Ok(Dividends::new());
+        };
+        let actions = adjust::read_actions(file)?;
+        let mut dividends = Dividends::new();
+        for symbol in self.symbols()? {
+            dividends.extend(adjust::dividends(&symbol, &actions));
+        }
+        Ok(dividends)
+    }
+
+    /// Symbols of the rows, `symbol` for files without a symbol column
+    fn symbols(&self) -> Result<Vec<String>, CLIError> {
+        let (frame, has_symbol) = self.frame()?;
+        if !has_symbol {
+            return Ok(vec![self.symbol.clone()]);
+        }
+        let unique = collect_frame(frame.select([col("symbol").unique()]))?;
+        let mut symbols: Vec<String> = unique
+            .column("symbol")?
+            .str()?
+            .into_iter()
+            .flatten()
+            .map(str::to_string)
+            .collect();
+        symbols.sort();
+        Ok(symbols)
+    }
+
+    /// Read all rows of the source at once, in stream order
+    pub fn rows(self) -> Result<Vec<CsvRow>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut reader = SourceReader::new(self)?;
+        let mut rows = vec![];
+        while !reader.done {
+            reader.next_batch()?;
+            rows.extend(reader.pending.drain(..));
+        }
+        Ok(rows)
+    }
+
+    /// Stream all bars of the source, without pacing
+    pub fn bars(self) -> BarStream {
+        let reader = match SourceReader::new(self) {
+            Ok(reader) => reader,
+            Err(e) => return stream::once(async move { Err(e) }).boxed(),
+        };
+
+        stream::unfold(reader, |mut reader| async move {
+            loop {
+                if let Some(row) = reader.pending.pop_front() {
+                    let bar = row.to_bar().map(Data::Bar).map_err(Into::into);
+                    return Some((bar, reader));
+                }
+                if reader.done {
+                    return None;
+                }
+                if let Err(e) = reader.next_batch() {
+                    reader.done = true;
+                    return Some((Err(e), reader));
+                }
+            }
+        })
+        .boxed()
+    }
+
+    /// Polars frame with the columns renamed to the Parquet names
+    fn frame(&self) -> Result<(LazyFrame, bool), CLIError> {
+        let (mut frame, layout) = match &self.format {
+            SourceFormat::Csv(layout) => {
+                // Every column is read as text, timestamps are parsed per row
+                let frame = LazyCsvReader::new(PlPath::new(&self.path))
+                    .with_has_header(true)
+                    .with_separator(layout.delimiter as u8)
+                    .with_infer_schema_length(Some(0))
+                    .finish()?;
+                (frame, layout.as_ref().clone())
+            }
+            SourceFormat::Parquet => {
+                let frame =
+                    LazyFrame::scan_parquet(PlPath::new(&self.path), ScanArgsParquet::default())?;
+                (frame, parquet_layout())
+            }
+        };
+
+        let schema = frame.collect_schema()?;
+        let mut columns = vec![];
+        for (name, alias) in [
+            (&layout.open, "open"),
+            (&layout.high, "high"),
+            (&layout.low, "low"),
+            (&layout.close, "close"),
+            (&layout.volume, "volume"),
+        ] {
+            if !schema.contains(name) {
+                return Err(CLIError::MissingColumn(name.clone()));
+            }
+            columns.push(col(name.as_str()).cast(DataType::Float64).alias(alias));
+        }
+        if !schema.contains(&layout.timestamp) {
+            return Err(CLIError::MissingColumn(layout.timestamp.clone()));
+        }
+        columns.push(
+            timestamp_text(&layout.timestamp, schema.get(&layout.timestamp)).alias("timestamp"),
+        );
+        if let Some(adj_close) = layout.adj_close.as_ref().filter(|c| schema.contains(c)) {
+            columns.push(
+                col(adj_close.as_str())
+                    .cast(DataType::Float64)
+                    .alias("adj_close"),
+            );
+        }
+        let has_symbol = match &layout.symbol {
+            Some(symbol) if schema.contains(symbol) => {
+                columns.push(col(symbol.as_str()).cast(DataType::String).alias("symbol"));
+                true
+            }
+            // Parquet files of a single symbol may leave the column out
+            Some(_) if matches!(self.format, SourceFormat::Parquet) => false,
+            Some(symbol) => return Err(CLIError::MissingColumn(symbol.clone())),
+            None => false,
+        };
+
+        Ok((frame.select(columns), has_symbol))
+    }
+}
+
+/// Collect `frame`, off the async runtime if called on one
+///
+/// Polars runs its scans on a Tokio runtime of its own, which can't be blocked
+/// on from a thread that drives another runtime.
+fn collect_frame(frame: LazyFrame) -> Result<DataFrame, PolarsError> {
+    if tokio::runtime::Handle::try_current().is_err() {
+        return frame.collect();
+    }
+    std::thread::scope(|scope| scope.spawn(|| frame.collect()).join())
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+/// Layout of the normalized Parquet columns
+fn parquet_layout() -> CsvLayout {
+    CsvLayout {
+        timestamp: "timestamp".to_string(),
+        open: "open".to_string(),
+        high: "high".to_string(),
+        low: "low".to_string(),
+        close: "close".to_string(),
+        adj_close: Some("adj_close".to_string()),
+        volume: "volume".to_string(),
+        symbol: Some("symbol".to_string()),
+        timestamp_format: TimestampFormat::EpochMillis,
+        timezone: None,
+        delimiter: ',',
+    }
+}
+
+/// Timestamp column as text, datetimes become epoch milliseconds
+fn timestamp_text(name: &str, dtype: Option<&DataType>) -> Expr {
+    match dtype {
+        Some(DataType::Datetime(_, _)) => col(name)
+            .cast(DataType::Datetime(TimeUnit::Milliseconds, None))
+            .cast(DataType::Int64)
+            .cast(DataType::String),
+        Some(DataType::String) => col(name),
+        _ => col(name).cast(DataType::String),
+    }
+}
+
+/// Rows within `start..=end` as a lazy filter, for timestamps Polars can compare
+///
+/// Epoch timestamps are compared as numbers, formatted ones are left to the
+/// filter after parsing. Timestamps that are no number are kept, so they fail
+/// when their row is converted.
+fn range_filter(
+    format: &TimestampFormat,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+) -> Option<Expr> {
+    let millis = match format {
+        TimestampFormat::EpochMillis => col("timestamp").cast(DataType::Int64),
+        TimestampFormat::EpochSeconds => col("timestamp").cast(DataType::Int64) * lit(1000i64),
+        _ => return None,
+    };
+    let after = start.map(|start| millis.clone().gt_eq(lit(start.timestamp_millis())));
+    let before = end.map(|end| millis.clone().lt_eq(lit(end.timestamp_millis())));
+    let within = match (after, before) {
+        (Some(after), Some(before)) => after.and(before),
+        (Some(bound), None) | (None, Some(bound)) => bound,
+        (None, None) => return None,
+    };
+    Some(within.or(millis.is_null()))
+}
+
+/// State of a source while it is streamed
+struct SourceReader {
+    /// Scanned for every batch, only the rows of one batch are collected
+    frame: LazyFrame,
+    symbol: String,
+    timestamp_format: TimestampFormat,
+    timezone: Option<Tz>,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    adjustment: Adjustment,
+    actions: Vec<CorporateAction>,
+    resampler: Option<Resampler>,
+    validator: Option<SharedValidator>,
+    /// Load everything at once and sort, for files with several symbols
+    sort_all: bool,
+    offset: usize,
+    pending: VecDeque<CsvRow>,
+    done: bool,
+}
+
+impl SourceReader {
+    fn new(source: HistoricalSource) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let (mut frame, has_symbol) = source.frame()?;
+        let layout = match &source.format {
+            SourceFormat::Csv(layout) => layout.as_ref().clone(),
+            SourceFormat::Parquet => parquet_layout(),
+        };
+        if let Some(within) = range_filter(&layout.timestamp_format, source.start, source.end) {
+            frame = frame.filter(within);
+        }
+        let timezone = layout.timezone()?;
+        let actions = match &source.adjustment {
+            Adjustment::Events { file } => adjust::read_actions(file)?,
+            _ => vec![],
+        };
+
+        Ok(Self {
+            frame,
+            symbol: source.symbol,
+            timestamp_format: layout.timestamp_format,
+            timezone,
+            start: source.start,
+            end: source.end,
+            adjustment: source.adjustment,
+            actions,
+            resampler: source.resample.map(Resampler::new),
+            validator: source.validator,
+            sort_all: has_symbol && matches!(source.format, SourceFormat::Csv(_)),
+            offset: 0,
+            pending: VecDeque::new(),
+            done: false,
+        })
+    }
+
+    /// Scan the next batch into `pending`
+    fn next_batch(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let batch = if self.sort_all {
+            collect_frame(self.frame.clone())?
+        } else {
+            collect_frame(
+                self.frame
+                    .clone()
+                    .slice(self.offset as i64, BATCH_SIZE as IdxSize),
+            )?
+        };
+        self.offset += batch.height();
+        let exhausted = self.sort_all || batch.height() < BATCH_SIZE;
+
+        let mut rows = self.rows(&batch)?;
+        if self.sort_all {
+            rows.sort_by_key(|row| row.timestamp);
+        }
+
+        // Time ordered files can stop at the end of the range
+        let past_end = |row: &CsvRow| self.end.is_some_and(|end| row.timestamp > end);
+        let finished = exhausted || rows.last().is_some_and(past_end);
+        rows.retain(|row| self.start.is_none_or(|start| row.timestamp >= start) && !past_end(row));
+
+        match &self.adjustment {
+            Adjustment::None => {}
+            Adjustment::AdjClose => adjust::adjust_by_adj_close(&mut rows),
+            Adjustment::Events { .. } => adjust::adjust_for_splits(&mut rows, &self.actions),
+        }
+
+        if let Some(validator) = &self.validator {
+            let mut validator = validator.lock().map_err(|_| CLIError::ConvertingError)?;
+            let mut checked = Vec::with_capacity(rows.len());
+            for row in rows {
+                checked.extend(validator.check(row)?);
+            }
+            rows = checked;
+        }
+
+        match &mut self.resampler {
+            Some(resampler) => {
+                for row in rows {
+                    self.pending.extend(resampler.push(row));
+                }
+                if finished {
+                    self.pending.extend(resampler.flush());
+                }
+            }
+            None => self.pending.extend(rows),
+        }
+
+        self.done = finished;
+        Ok(())
+    }
+
+    fn rows(
+        &self,
+        batch: &DataFrame,
+    ) -> Result<Vec<CsvRow>, Box<dyn std::error::Error + Send + Sync>> {
+        let timestamps = batch.column("timestamp")?.str()?;
+        let open = batch.column("open")?.f64()?;
+        let high = batch.column("high")?.f64()?;
+        let low = batch.column("low")?.f64()?;
+        let close = batch.column("close")?.f64()?;
+        let volume = batch.column("volume")?.f64()?;
+        let adj_close = batch
+            .column("adj_close")
+            .ok()
+            .map(|c| c.f64())
+            .transpose()?;
+        let symbols = batch.column("symbol").ok().map(|c| c.str()).transpose()?;
+
+        // Missing prices become NaN, so validation can drop or fill them
+        let mut rows = Vec::with_capacity(batch.height());
+        for i in 0..batch.height() {
+            let timestamp = timestamps.get(i).ok_or(CLIError::ConvertingError)?;
+            rows.push(CsvRow {
+                symbol: symbols
+                    .and_then(|s| s.get(i))
+                    .unwrap_or(self.symbol.as_str())
+                    .to_string(),
+                timestamp: parse_timestamp(timestamp, &self.timestamp_format, self.timezone)?,
+                open: open.get(i).unwrap_or(f64::NAN),
+                high: high.get(i).unwrap_or(f64::NAN),
+                low: low.get(i).unwrap_or(f64::NAN),
+                close: close.get(i).unwrap_or(f64::NAN),
+                adj_close: adj_close.and_then(|a| a.get(i)),
+                volume: volume.get(i).unwrap_or(0.0),
+            });
+        }
+        Ok(rows)
+    }
+}
+
+/// Aggregates bars per symbol into buckets of a fixed size
+///
+/// Buckets are aligned to the Unix epoch, or to the sessions of a calendar.
+#[derive(Debug)]
+pub struct Resampler {
+    size_ms: i64,
+    calendar: Option<TradingCalendar>,
+    open: HashMap<String, CsvRow>,
+}
+
+impl Resampler {
+    pub fn new(size: Duration) -> Self {
+        Self {
+            size_ms: size.num_milliseconds().max(1),
+            calendar: None,
+            open: HashMap::new(),
+        }
+    }
+
+    /// Start intraday buckets at the regular open of the session, e.g. 9:30,
+    /// 10:30 for hours. Buckets of a day or more start at exchange midnight,
+    /// weeks on Mondays.
+    pub fn with_calendar(mut self, calendar: TradingCalendar) -> Self {
+        self.calendar = Some(calendar);
+        self
+    }
+
+    fn bucket(&self, timestamp: DateTime<Utc>) -> DateTime<Utc> {
+        let millis = timestamp.timestamp_millis();
+        let origin = self
+            .calendar
+            .as_ref()
+            .and_then(|calendar| self.session_origin(calendar, timestamp))
+            .map_or(0, |origin| origin.timestamp_millis());
+        let start = millis - (millis - origin).rem_euclid(self.size_ms);
+        DateTime::from_timestamp_millis(start).unwrap_or(timestamp)
+    }
+
+    /// Start of a bucket in the session of `timestamp`
+    fn session_origin(
+        &self,
+        calendar: &TradingCalendar,
+        timestamp: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        let date = calendar.session_date(timestamp);
+        let days = self.size_ms / Duration::days(1).num_milliseconds();
+        if days == 0 {
+            return calendar.regular_open(date);
+        }
+        // Counted from a Monday, so weekly buckets start on Mondays
+        let monday = NaiveDate::from_ymd_opt(1970, 1, 5)?;
+        let skipped = (date - monday).num_days().rem_euclid(days);
+        calendar.midnight(date - Duration::days(skipped))
+    }
+
+    /// Add a bar, returns the previous bucket of its symbol once it is complete
+    pub fn push(&mut self, row: CsvRow) -> Option<CsvRow> {
+        let bucket = self.bucket(row.timestamp);
+        match self.open.get_mut(&row.symbol) {
+            Some(current) if current.timestamp == bucket => {
+                current.high = current.high.max(row.high);
+                current.low = current.low.min(row.low);
+                current.close = row.close;
+                current.adj_close = row.adj_close;
+                current.volume += row.volume;
+                None
+            }
+            _ => {
+                let symbol = row.symbol.clone();
+                let started = CsvRow {
+                    timestamp: bucket,
+                    ..row
+                };
+                self.open.insert(symbol, started)
+            }
+        }
+    }
+
+    /// Incomplete bucket of `symbol`
+    pub fn open_bucket(&self, symbol: &str) -> Option<&CsvRow> {
+        self.open.get(symbol)
+    }
+
+    /// Continue an incomplete bucket, e.g. one restored from a snapshot
+    pub fn resume(&mut self, row: CsvRow) {
+        self.open.insert(row.symbol.clone(), row);
+    }
+
+    /// Remaining incomplete buckets, oldest first
+    pub fn flush(&mut self) -> Vec<CsvRow> {
+        let mut rows: Vec<CsvRow> = self.open.drain().map(|(_, row)| row).collect();
+        rows.sort_by_key(|row| row.timestamp);
+        rows
+    }
+}
+
+/// Parse a bar size such as `30s`, `5m`, `1h`, `1d` or `1w`
+pub fn parse_bar_size(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let unit = value.chars().last()?;
+    let count = value[..value.len() - unit.len_utf8()].parse::<i64>().ok()?;
+    if count <= 0 {
+        return None;
+    }
+    match unit {
+        's' => Some(Duration::seconds(count)),
+        'm' => Some(Duration::minutes(count)),
+        'h' => Some(Duration::hours(count)),
+        'd' => Some(Duration::days(count)),
+        'w' => Some(Duration::weeks(count)),
+        _ => None,
+    }
+}
+
+/// Merge several sources into one time-ordered stream and pace it
+///
+/// Bars with the same timestamp are emitted in the order of `sources`.
+pub fn replay(sources: Vec<HistoricalSource>, speed: ReplaySpeed) -> BarStream {
+    let streams: Vec<Peekable<BarStream>> = sources
+        .into_iter()
+        .map(|source| source.bars().peekable())
+        .collect();
+
+    let merged = stream::unfold(streams, |mut streams| async move {
+        let mut next: Option<(usize, DateTime<Utc>)> = None;
+        for (i, stream) in streams.iter_mut().enumerate() {
+            let timestamp = match Pin::new(stream).peek().await {
+                Some(Ok(Data::Bar(bar))) => bar.timestamp,
+                // Errors and other data are passed on right away
+                Some(_) => DateTime::<Utc>::MIN_UTC,
+                None => continue,
+            };
+            if next.is_none_or(|(_, earliest)| timestamp < earliest) {
+                next = Some((i, timestamp));
+            }
+        }
+
+        let (i, _) = next?;
+        let item = streams[i].next().await?;
+        Some((item, streams))
+    })
+    .boxed();
+
+    pace(merged, speed, |item| match item {
+        Ok(Data::Bar(bar)) => Some(bar.timestamp),
+        _ => None,
+    })
+}
+
+/// Delay the items of `stream` according to `speed`
+///
+/// `timestamp` gives the time of an item for real-time pacing, items
+/// without one are passed on right away.
+pub fn pace<T: Send + 'static>(
+    stream: BoxStream<'static, T>,
+    speed: ReplaySpeed,
+    timestamp: fn(&T) -> Option<DateTime<Utc>>,
+) -> BoxStream<'static, T> {
+    match speed {
+        ReplaySpeed::AsFastAsPossible => stream,
+        ReplaySpeed::FixedDelay { millis } => stream
+            .then(move |item| async move {
+                tokio::time::sleep(tokio::time::Duration::from_millis(millis)).await;
+                item
+            })
+            .boxed(),
+        ReplaySpeed::RealTime { multiple } => {
+            let mut last: Option<DateTime<Utc>> = None;
+            stream
+                .then(move |item| {
+                    let timestamp = timestamp(&item);
+                    let wait = match (last, timestamp) {
+                        (Some(last), Some(timestamp)) if multiple > 0.0 => (timestamp - last)
+                            .to_std()
+                            .map(|d| d.div_f64(multiple))
+                            .unwrap_or_default(),
+                        _ => std::time::Duration::ZERO,
+                    };
+                    if timestamp.is_some() {
+                        last = timestamp;
+                    }
+                    async move {
+                        tokio::time::sleep(wait).await;
+                        item
+                    }
+                })
+                .boxed()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    async fn collect(stream: BarStream) -> Vec<Bar> {
+        stream
+            .map(|item| match item.unwrap() {
+                Data::Bar(bar) => bar,
+                _ => panic!("Expected Data::Bar"),
+            })
+            .collect()
+            .await
+    }
+
+    fn day(day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, 1, day, 0, 0, 0).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_csv_source() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "Date,Open,High,Low,Close,Adj Close,Volume")?;
+        writeln!(file, "2023-01-01,100.0,105.0,95.0,102.0,102.0,1000")?;
+
+        let path = file.path().to_str().unwrap();
+        let bars = collect(HistoricalSource::csv(path, "TEST", CsvLayout::default()).bars()).await;
+
+        assert_eq!(bars.len(), 1);
+        let bar = &bars[0];
+        assert_eq!(bar.symbol, "TEST");
+        assert_eq!(bar.open_price.to_string(), "100");
+        assert_eq!(bar.close_price.to_string(), "102");
+        assert_eq!(bar.high_price.to_string(), "105");
+        assert_eq!(bar.low_price.to_string(), "95");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_csv_source_custom_layout() -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+    {
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "ticker;t;o;h;l;c;v")?;
+        writeln!(file, "AAA;1704205800000;10;11;9;10.5;100")?;
+        writeln!(file, "BBB;1704205860000;20;21;19;20.5;200")?;
+
+        let layout = CsvLayout {
+            timestamp: "t".to_string(),
+            open: "o".to_string(),
+            high: "h".to_string(),
+            low: "l".to_string(),
+            close: "c".to_string(),
+            adj_close: None,
+            volume: "v".to_string(),
+            symbol: Some("ticker".to_string()),
+            timestamp_format: TimestampFormat::EpochMillis,
+            timezone: None,
+            delimiter: ';',
+        };
+        let source = HistoricalSource::csv(file.path().to_str().unwrap(), "IGNORED", layout);
+        let bars = collect(source.bars()).await;
+
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[0].symbol, "AAA");
+        assert_eq!(bars[1].symbol, "BBB");
+        assert_eq!(bars[1].close_price.to_string(), "20.5");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_csv_source_missing_column() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "Date,Open,High,Low,Close").unwrap();
+
+        let source =
+            HistoricalSource::csv(file.path().to_str().unwrap(), "TEST", CsvLayout::default());
+        let mut stream = source.bars();
+        assert!(stream.next().await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_orcl_first_bar() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let source = HistoricalSource::csv("files/orcl.csv", "ORCL", CsvLayout::default());
+        let bar = collect(source.bars()).await.remove(0);
+
+        assert_eq!(bar.symbol, "ORCL");
+        assert_eq!(bar.open_price.to_string(), "2.179012");
+        assert_eq!(bar.close_price.to_string(), "2.117284");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_orcl_date_range() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let start = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(2000, 12, 31, 0, 0, 0).unwrap();
+        let source = HistoricalSource::csv("files/orcl.csv", "ORCL", CsvLayout::default())
+            .between(Some(start), Some(end));
+
+        let bars = collect(source.bars()).await;
+        // One year of trading days
+        assert!(bars.len() > 240 && bars.len() < 260);
+        assert!(bars
+            .iter()
+            .all(|b| b.timestamp >= start && b.timestamp <= end));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_epoch_range_across_batches(
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "t,o,h,l,c,v")?;
+        let first = 1704205800;
+        for i in 0..BATCH_SIZE as i64 + 5 {
+            writeln!(file, "{},10,11,9,10.5,100", first + i * 60)?;
+        }
+
+        let layout = CsvLayout {
+            timestamp: "t".to_string(),
+            open: "o".to_string(),
+            high: "h".to_string(),
+            low: "l".to_string(),
+            close: "c".to_string(),
+            adj_close: None,
+            volume: "v".to_string(),
+            symbol: None,
+            timestamp_format: TimestampFormat::EpochSeconds,
+            timezone: None,
+            delimiter: ',',
+        };
+        let at = |i: i64| DateTime::from_timestamp(first + i * 60, 0).unwrap();
+        let (start, end) = (at(3), at(BATCH_SIZE as i64 + 2));
+        let source = HistoricalSource::csv(file.path().to_str().unwrap(), "TEST", layout)
+            .between(Some(start), Some(end));
+        let bars = collect(source.bars()).await;
+
+        assert_eq!(bars.len(), BATCH_SIZE);
+        assert_eq!(bars[0].timestamp, start);
+        assert_eq!(bars[BATCH_SIZE - 1].timestamp, end);
+        assert!(bars.windows(2).all(|w| w[0].timestamp < w[1].timestamp));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_replay_merges_sources() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut file_a = NamedTempFile::new()?;
+        writeln!(file_a, "Date,Open,High,Low,Close,Adj Close,Volume")?;
+        writeln!(file_a, "2023-01-01,100.0,105.0,95.0,102.0,102.0,1000")?;
+        writeln!(file_a, "2023-01-03,101.0,106.0,96.0,103.0,103.0,1000")?;
+
+        let mut file_b = NamedTempFile::new()?;
+        writeln!(file_b, "Date,Open,High,Low,Close,Adj Close,Volume")?;
+        writeln!(file_b, "2023-01-01,50.0,55.0,45.0,52.0,52.0,1000")?;
+        writeln!(file_b, "2023-01-02,51.0,56.0,46.0,53.0,53.0,1000")?;
+
+        let sources = vec![
+            HistoricalSource::csv(file_a.path().to_str().unwrap(), "AAA", CsvLayout::default()),
+            HistoricalSource::csv(file_b.path().to_str().unwrap(), "BBB", CsvLayout::default()),
+        ];
+        let bars = collect(replay(sources, ReplaySpeed::AsFastAsPossible)).await;
+
+        let symbols: Vec<&str> = bars.iter().map(|b| b.symbol.as_str()).collect();
+        assert_eq!(symbols, ["AAA", "BBB", "BBB", "AAA"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_csv_source_interleaves_symbols(
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "symbol,timestamp,open,high,low,close,volume")?;
+        writeln!(file, "AAA,2024-01-02T14:31:00Z,10,11,9,10,100")?;
+        writeln!(file, "BBB,2024-01-02T14:30:00Z,20,21,19,20,100")?;
+        writeln!(file, "AAA,2024-01-02T14:30:00Z,10,11,9,10,100")?;
+
+        let layout = CsvLayout {
+            timestamp: "timestamp".to_string(),
+            open: "open".to_string(),
+            high: "high".to_string(),
+            low: "low".to_string(),
+            close: "close".to_string(),
+            adj_close: None,
+            volume: "volume".to_string(),
+            symbol: Some("symbol".to_string()),
+            timestamp_format: TimestampFormat::Rfc3339,
+            ..CsvLayout::default()
+        };
+        let source = HistoricalSource::csv(file.path().to_str().unwrap(), "", layout);
+        let bars: Vec<(String, String)> = collect(source.bars())
+            .await
+            .into_iter()
+            .map(|b| (b.symbol, b.timestamp.format("%H:%M").to_string()))
+            .collect();
+
+        assert_eq!(
+            bars,
+            [
+                ("BBB".to_string(), "14:30".to_string()),
+                ("AAA".to_string(), "14:30".to_string()),
+                ("AAA".to_string(), "14:31".to_string()),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dividends_of_every_symbol() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "symbol,timestamp,open,high,low,close,volume")?;
+        writeln!(file, "AAA,2024-01-02T14:30:00Z,10,11,9,10,100")?;
+        writeln!(file, "BBB,2024-01-02T14:30:00Z,20,21,19,20,100")?;
+        let mut actions = NamedTempFile::new()?;
+        writeln!(actions, "Date,Action,Value")?;
+        writeln!(actions, "2024-01-02,dividend,0.25")?;
+
+        let layout = CsvLayout {
+            timestamp: "timestamp".to_string(),
+            open: "open".to_string(),
+            high: "high".to_string(),
+            low: "low".to_string(),
+            close: "close".to_string(),
+            adj_close: None,
+            volume: "volume".to_string(),
+            symbol: Some("symbol".to_string()),
+            timestamp_format: TimestampFormat::Rfc3339,
+            ..CsvLayout::default()
+        };
+        let source = HistoricalSource::csv(file.path().to_str().unwrap(), "IGNORED", layout)
+            .adjusted(Adjustment::Events {
+                file: actions.path().to_str().unwrap().to_string(),
+            });
+
+        let dividends = source.dividends()?;
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+        assert_eq!(dividends.len(), 2);
+        assert_eq!(dividends.get(&("AAA".to_string(), date)), Some(&0.25));
+        assert_eq!(dividends.get(&("BBB".to_string(), date)), Some(&0.25));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_adjusted_orcl() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let source = HistoricalSource::csv("files/orcl.csv", "ORCL", CsvLayout::default())
+            .adjusted(Adjustment::AdjClose);
+        assert!(source.dividends()?.is_empty());
+
+        // The first bar's close is replaced by its adjusted close
+        let bar = collect(source.bars()).await.remove(0);
+        assert_eq!(bar.close_price.to_string(), "1.883304");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_validated_source() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "Date,Open,High,Low,Close,Adj Close,Volume")?;
+        writeln!(file, "2023-01-02,100.0,105.0,95.0,102.0,102.0,1000")?;
+        writeln!(file, "2023-01-03,100.0,105.0,95.0,NaN,102.0,1000")?;
+        writeln!(file, "2022-12-30,100.0,105.0,95.0,102.0,102.0,1000")?;
+        writeln!(file, "2023-01-04,101.0,106.0,96.0,103.0,103.0,1000")?;
+
+        let validator = Arc::new(Mutex::new(BarValidator::new(
+            crate::wrangling::quality::QualityPolicy::Drop,
+        )));
+        let source =
+            HistoricalSource::csv(file.path().to_str().unwrap(), "TEST", CsvLayout::default())
+                .validated(validator.clone());
+        let bars = collect(source.bars()).await;
+
+        assert_eq!(bars.len(), 2);
+        let validator = validator.lock().unwrap();
+        assert_eq!(validator.report().invalid_prices, 1);
+        assert_eq!(validator.report().out_of_order, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resampler() {
+        let row = |timestamp: DateTime<Utc>, close: f64| CsvRow {
+            symbol: "TEST".to_string(),
+            timestamp,
+            open: close,
+            high: close + 1.0,
+            low: close - 1.0,
+            close,
+            adj_close: None,
+            volume: 10.0,
+        };
+        let mut resampler = Resampler::new(Duration::days(2));
+
+        // 2023-01-01 and 2023-01-02 share a bucket starting on 2023-01-01
+        assert_eq!(resampler.push(row(day(1), 10.0)), None);
+        assert_eq!(resampler.push(row(day(2), 12.0)), None);
+        let bar = resampler.push(row(day(3), 11.0)).unwrap();
+
+        assert_eq!(bar.timestamp, day(1));
+        assert_eq!(bar.open, 10.0);
+        assert_eq!(bar.high, 13.0);
+        assert_eq!(bar.low, 9.0);
+        assert_eq!(bar.close, 12.0);
+        assert_eq!(bar.volume, 20.0);
+
+        let rest = resampler.flush();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].timestamp, day(3));
+    }
+
+    #[test]
+    fn test_resampler_aligned_to_sessions() {
+        let row = |timestamp: DateTime<Utc>| CsvRow {
+            symbol: "TEST".to_string(),
+            timestamp,
+            open: 10.0,
+            high: 10.0,
+            low: 10.0,
+            close: 10.0,
+            adj_close: None,
+            volume: 10.0,
+        };
+        let calendar = TradingCalendar::nyse();
+        let hourly = Resampler::new(Duration::hours(1)).with_calendar(calendar.clone());
+
+        // 9:30 to 10:30 in New York, in winter and in summer time
+        let winter = Utc.with_ymd_and_hms(2024, 1, 2, 15, 29, 0).unwrap();
+        let summer = Utc.with_ymd_and_hms(2024, 7, 2, 14, 29, 0).unwrap();
+        assert_eq!(
+            hourly.bucket(winter),
+            Utc.with_ymd_and_hms(2024, 1, 2, 14, 30, 0).unwrap()
+        );
+        assert_eq!(
+            hourly.bucket(summer),
+            Utc.with_ymd_and_hms(2024, 7, 2, 13, 30, 0).unwrap()
+        );
+
+        // Weeks start at midnight of Monday in New York
+        let mut weekly = Resampler::new(Duration::weeks(1)).with_calendar(calendar);
+        let thursday = Utc.with_ymd_and_hms(2024, 1, 4, 15, 0, 0).unwrap();
+        let monday = Utc.with_ymd_and_hms(2024, 1, 1, 5, 0, 0).unwrap();
+        assert_eq!(weekly.bucket(thursday), monday);
+        assert_eq!(weekly.push(row(thursday)), None);
+        assert_eq!(weekly.flush()[0].timestamp, monday);
+    }
+
+    #[test]
+    fn test_parse_bar_size() {
+        assert_eq!(parse_bar_size("5m"), Some(Duration::minutes(5)));
+        assert_eq!(parse_bar_size("1d"), Some(Duration::days(1)));
+        assert_eq!(parse_bar_size("0h"), None);
+        assert_eq!(parse_bar_size("5x"), None);
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use polars::prelude::{Column, DataFrame, DataType, ParquetWriter, TimeUnit};
+
+use crate::mocking::layout::{CsvLayout, CsvRow};
+use crate::mocking::source::HistoricalSource;
+use crate::wrangling::quality::price_issue;
+
+/// Local Parquet store of OHLCV bars
+///
+/// Bars are partitioned by symbol and timeframe, hive style:
+/// `<root>/symbol=ORCL/timeframe=1d/bars.parquet`. Every partition is
+/// sorted by timestamp and holds at most one bar per timestamp.
+#[derive(Debug, Clone)]
+pub struct BarStore {
+    root: PathBuf,
+}
+
+/// What happened to the bars of one symbol during an import
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportReport {
+    pub symbol: String,
+    /// Bars in the partition after the import
+    pub rows: usize,
+    /// Bars replaced by a later bar with the same timestamp
+    pub duplicates: usize,
+    /// Bars dropped because their OHLC values are inconsistent
+    pub invalid: usize,
+}
+
+impl BarStore {
+    pub fn new(root: &str) -> Self {
+        Self {
+            root: PathBuf::from(root),
+        }
+    }
+
+    /// File of the partition of `symbol` and `timeframe`
+    pub fn path(&self, symbol: &str, timeframe: &str) -> PathBuf {
+        self.root
+            .join(format!("symbol={}", symbol))
+            .join(format!("timeframe={}", timeframe))
+            .join("bars.parquet")
+    }
+
+    pub fn contains(&self, symbol: &str, timeframe: &str) -> bool {
+        self.path(symbol, timeframe).exists()
+    }
+
+    /// Source replaying a partition
+    pub fn source(&self, symbol: &str, timeframe: &str) -> HistoricalSource {
+        HistoricalSource::parquet(&self.path(symbol, timeframe).to_string_lossy(), symbol)
+    }
+
+    /// All bars of a partition, empty if it does not exist yet
+    pub fn read(
+        &self,
+        symbol: &str,
+        timeframe: &str,
+    ) -> Result<Vec<CsvRow>, Box<dyn std::error::Error + Send + Sync>> {
+        if !self.contains(symbol, timeframe) {
+            return Ok(vec![]);
+        }
+        self.source(symbol, timeframe).rows()
+    }
+
+    /// Merge `rows` of `symbol` into its partition
+    ///
+    /// Inconsistent bars are dropped. For duplicate timestamps the bar
+    /// written last wins, so a new import replaces stored bars.
+    pub fn write(
+        &self,
+        symbol: &str,
+        timeframe: &str,
+        rows: Vec<CsvRow>,
+    ) -> Result<ImportReport, Box<dyn std::error::Error + Send + Sync>> {
+        let received = rows.len();
+        let mut valid: Vec<CsvRow> = rows
+            .into_iter()
+            .filter(|row| price_issue(row).is_none())
+            .collect();
+        let invalid = received - valid.len();
+
+        let mut merged = self.read(symbol, timeframe)?;
+        let stored = merged.len();
+        merged.append(&mut valid);
+        let merged = dedup_by_timestamp(merged);
+
+        let report = ImportReport {
+            symbol: symbol.to_string(),
+            rows: merged.len(),
+            duplicates: stored + received - invalid - merged.len(),
+            invalid,
+        };
+        if !merged.is_empty() {
+            write_parquet(&self.path(symbol, timeframe), &merged)?;
+        }
+        Ok(report)
+    }
+
+    /// Import a CSV export into the store
+    ///
+    /// Files with a symbol column are split into one partition per symbol.
+    pub fn import_csv(
+        &self,
+        path: &str,
+        symbol: &str,
+        timeframe: &str,
+        layout: CsvLayout,
+    ) -> Result<Vec<ImportReport>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut by_symbol: BTreeMap<String, Vec<CsvRow>> = BTreeMap::new();
+        for row in HistoricalSource::csv(path, symbol, layout).rows()? {
+            by_symbol.entry(row.symbol.clone()).or_default().push(row);
+        }
+
+        by_symbol
+            .into_iter()
+            .map(|(symbol, rows)| self.write(&symbol, timeframe, rows))
+            .collect()
+    }
+}
+
+/// Sort by timestamp, keeping the last of several bars with equal timestamps
+fn dedup_by_timestamp(rows: Vec<CsvRow>) -> Vec<CsvRow> {
+    let mut by_timestamp = BTreeMap::new();
+    for row in rows {
+        by_timestamp.insert(row.timestamp, row);
+    }
+    by_timestamp.into_values().collect()
+}
+
+fn write_parquet(
+    path: &Path,
+    rows: &[CsvRow],
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let timestamps: Vec<i64> = rows
+        .iter()
+        .map(|r| r.timestamp.timestamp_millis())
+        .collect();
+    let column = |name: &str, value: fn(&CsvRow) -> f64| {
+        Column::new(name.into(), rows.iter().map(value).collect::<Vec<f64>>())
+    };
+
+    let mut frame = DataFrame::new(vec![
+        Column::new(
+            "symbol".into(),
+            rows.iter()
+                .map(|r| r.symbol.as_str())
+                .collect::<Vec<&str>>(),
+        ),
+        Column::new("timestamp".into(), timestamps)
+            .cast(&DataType::Datetime(TimeUnit::Milliseconds, None))?,
+        column("open", |r| r.open),
+        column("high", |r| r.high),
+        column("low", |r| r.low),
+        column("close", |r| r.close),
+        Column::new(
+            "adj_close".into(),
+            rows.iter()
+                .map(|r| r.adj_close)
+                .collect::<Vec<Option<f64>>>(),
+        ),
+        column("volume", |r| r.volume),
+    ])?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Write next to the partition first, so readers never see half a file
+    let partial = path.with_extension("parquet.partial");
+    ParquetWriter::new(File::create(&partial)?).finish(&mut frame)?;
+    fs::rename(partial, path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use std::io::Write;
+    use tempfile::{tempdir, NamedTempFile};
+
+    fn row(day: u32, close: f64) -> CsvRow {
+        CsvRow {
+            symbol: "TEST".to_string(),
+            timestamp: Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap(),
+            open: close,
+            high: close + 1.0,
+            low: close - 1.0,
+            close,
+            adj_close: None,
+            volume: 100.0,
+        }
+    }
+
+    #[test]
+    fn test_write_merges_and_dedups() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let dir = tempdir()?;
+        let store = BarStore::new(dir.path().to_str().unwrap());
+
+        let report = store.write("TEST", "1d", vec![row(3, 10.0), row(2, 11.0), row(2, 12.0)])?;
+        assert_eq!(report.rows, 2);
+        assert_eq!(report.duplicates, 1);
+
+        let report = store.write("TEST", "1d", vec![row(4, 13.0), row(5, 0.5), row(3, 14.0)])?;
+        assert_eq!(report.rows, 3);
+        assert_eq!(report.duplicates, 1);
+        assert_eq!(report.invalid, 1);
+
+        let closes: Vec<f64> = store.read("TEST", "1d")?.iter().map(|r| r.close).collect();
+        assert_eq!(closes, [12.0, 14.0, 13.0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_csv_per_symbol() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "symbol,timestamp,open,high,low,close,volume")?;
+        writeln!(file, "AAA,2024-01-02T14:31:00Z,10,11,9,10,100")?;
+        writeln!(file, "BBB,2024-01-02T14:30:00Z,20,21,19,20,100")?;
+        writeln!(file, "AAA,2024-01-02T14:30:00Z,10,11,9,10,100")?;
+
+        let layout = CsvLayout {
+            timestamp: "timestamp".to_string(),
+            open: "open".to_string(),
+            high: "high".to_string(),
+            low: "low".to_string(),
+            close: "close".to_string(),
+            adj_close: None,
+            volume: "volume".to_string(),
+            symbol: Some("symbol".to_string()),
+            timestamp_format: crate::mocking::layout::TimestampFormat::Rfc3339,
+            ..CsvLayout::default()
+        };
+        let dir = tempdir()?;
+        let store = BarStore::new(dir.path().to_str().unwrap());
+        let reports = store.import_csv(file.path().to_str().unwrap(), "", "1m", layout)?;
+
+        assert_eq!(reports.len(), 2);
+        assert_eq!((reports[0].symbol.as_str(), reports[0].rows), ("AAA", 2));
+        assert_eq!((reports[1].symbol.as_str(), reports[1].rows), ("BBB", 1));
+        assert!(dir
+            .path()
+            .join("symbol=AAA/timeframe=1m/bars.parquet")
+            .exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_replay_orcl_from_store() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        use futures::StreamExt;
+
+        let dir = tempdir()?;
+        let store = BarStore::new(dir.path().to_str().unwrap());
+        let reports = store.import_csv("files/orcl.csv", "ORCL", "1d", CsvLayout::default())?;
+        assert_eq!(reports[0].symbol, "ORCL");
+
+        let mut stream = store.source("ORCL", "1d").bars();
+        match stream.next().await.unwrap()? {
+            apca::data::v2::stream::Data::Bar(bar) => {
+                assert_eq!(bar.symbol, "ORCL");
+                assert_eq!(bar.close_price.to_string(), "2.117284");
+            }
+            _ => panic!("Expected Data::Bar"),
+        }
+
+        Ok(())
+    }
+}
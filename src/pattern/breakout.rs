@@ -0,0 +1,126 @@
+use crate::pattern::signal::Signal;
+
+/// Donchian channel of the bars before the current one
+#[derive(Debug, Clone, Copy)]
+pub struct Channel {
+    /// Highest high of the last N bars
+    pub high: f64,
+    /// Lowest low of the last M bars
+    pub low: f64,
+}
+
+// flat: buy when the close breaks the N-bar high and the filters pass
+// long: exit when the close breaks the M-bar low
+pub fn signal(close: f64, channel: Channel, filters_pass: bool, position: i32) -> Signal {
+    if position > 0 {
+        if close < channel.low {
+            return Signal::Exit;
+        }
+        return Signal::Hold;
+    }
+
+    if position == 0 && close > channel.high && filters_pass {
+        Signal::Buy
+    } else {
+        Signal::Hold
+    }
+}
+
+// momentum filter: rate of change has to reach the minimum
+pub fn momentum(rate_of_change: f64, min_rate_of_change: f64) -> bool {
+    rate_of_change >= min_rate_of_change
+}
+
+// volatility regime: standard deviation relative to price within the band
+pub fn regime(deviation: f64, close: f64, min: f64, max: f64) -> bool {
+    if close <= 0.0 {
+        return false;
+    }
+    let volatility = deviation / close;
+    volatility >= min && volatility <= max
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mocking::layout::CsvLayout;
+    use crate::mocking::source::HistoricalSource;
+    use apca::data::v2::stream::Data;
+    use tokio_stream::StreamExt;
+
+    const CHANNEL: Channel = Channel {
+        high: 110.0,
+        low: 90.0,
+    };
+
+    #[test]
+    fn test_entry() {
+        assert_eq!(signal(111.0, CHANNEL, true, 0), Signal::Buy);
+        assert_eq!(signal(111.0, CHANNEL, false, 0), Signal::Hold);
+        assert_eq!(signal(105.0, CHANNEL, true, 0), Signal::Hold);
+        // Already long, no pyramiding
+        assert_eq!(signal(111.0, CHANNEL, true, 10), Signal::Hold);
+    }
+
+    #[test]
+    fn test_exit() {
+        assert_eq!(signal(89.0, CHANNEL, true, 10), Signal::Exit);
+        assert_eq!(signal(95.0, CHANNEL, true, 10), Signal::Hold);
+    }
+
+    #[test]
+    fn test_filters() {
+        assert!(momentum(2.5, 2.0));
+        assert!(!momentum(1.0, 2.0));
+        assert!(regime(2.0, 100.0, 0.01, 0.05));
+        assert!(!regime(10.0, 100.0, 0.01, 0.05));
+    }
+
+    #[tokio::test]
+    async fn test_backtest_orcl() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut stream =
+            HistoricalSource::csv("files/orcl.csv", "ORCL", CsvLayout::default()).bars();
+
+        let (entry, exit) = (20, 10);
+        let mut bars = vec![];
+        let mut position = 0;
+        let mut trades = 0;
+
+        while let Some(data) = stream.next().await {
+            let Data::Bar(bar) = data? else {
+                continue;
+            };
+            bars.push((
+                bar.high_price.to_f64().unwrap(),
+                bar.low_price.to_f64().unwrap(),
+                bar.close_price.to_f64().unwrap(),
+            ));
+            if bars.len() <= entry {
+                continue;
+            }
+
+            let previous = &bars[..bars.len() - 1];
+            let channel = Channel {
+                high: previous[previous.len() - entry..]
+                    .iter()
+                    .map(|b| b.0)
+                    .fold(f64::MIN, f64::max),
+                low: previous[previous.len() - exit..]
+                    .iter()
+                    .map(|b| b.1)
+                    .fold(f64::MAX, f64::min),
+            };
+            match signal(bars.last().unwrap().2, channel, true, position) {
+                Signal::Buy => position = 1,
+                Signal::Exit => {
+                    position = 0;
+                    trades += 1;
+                }
+                _ => {}
+            }
+        }
+
+        assert!(trades > 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+use crate::pattern::signal::Signal;
+
+/// Latest Bollinger band and RSI values for one bar
+#[derive(Debug, Clone, Copy)]
+pub struct Bands {
+    pub lower: f64,
+    pub middle: f64,
+    pub upper: f64,
+    pub rsi: f64,
+}
+
+/// Thresholds of the mean-reversion rule
+#[derive(Debug, Clone, Copy)]
+pub struct Thresholds {
+    pub oversold: f64,
+    pub overbought: f64,
+    /// Longs exit once RSI recovers above this, shorts once it drops below `100 - exit_rsi`
+    pub exit_rsi: f64,
+}
+
+// flat: buy below the lower band when oversold, short above the upper band when overbought
+// long: exit at the middle band or on an rsi recovery, short mirrored
+pub fn signal(close: f64, bands: Bands, thresholds: Thresholds, position: i32) -> Signal {
+    if position > 0 {
+        if close >= bands.middle || bands.rsi >= thresholds.exit_rsi {
+            return Signal::Exit;
+        }
+        return Signal::Hold;
+    }
+
+    if position < 0 {
+        if close <= bands.middle || bands.rsi <= 100.0 - thresholds.exit_rsi {
+            return Signal::Exit;
+        }
+        return Signal::Hold;
+    }
+
+    if close < bands.lower && bands.rsi < thresholds.oversold {
+        Signal::Buy
+    } else if close > bands.upper && bands.rsi > thresholds.overbought {
+        Signal::Sell
+    } else {
+        Signal::Hold
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THRESHOLDS: Thresholds = Thresholds {
+        oversold: 30.0,
+        overbought: 70.0,
+        exit_rsi: 50.0,
+    };
+
+    fn bands(rsi: f64) -> Bands {
+        Bands {
+            lower: 90.0,
+            middle: 100.0,
+            upper: 110.0,
+            rsi,
+        }
+    }
+
+    #[test]
+    fn test_entry_when_flat() {
+        assert_eq!(signal(85.0, bands(25.0), THRESHOLDS, 0), Signal::Buy);
+        assert_eq!(signal(115.0, bands(75.0), THRESHOLDS, 0), Signal::Sell);
+        // Below the band but RSI not oversold
+        assert_eq!(signal(85.0, bands(40.0), THRESHOLDS, 0), Signal::Hold);
+    }
+
+    #[test]
+    fn test_exit_long() {
+        assert_eq!(signal(101.0, bands(40.0), THRESHOLDS, 10), Signal::Exit);
+        assert_eq!(signal(95.0, bands(55.0), THRESHOLDS, 10), Signal::Exit);
+        assert_eq!(signal(95.0, bands(40.0), THRESHOLDS, 10), Signal::Hold);
+    }
+
+    #[test]
+    fn test_exit_short() {
+        assert_eq!(signal(99.0, bands(60.0), THRESHOLDS, -10), Signal::Exit);
+        assert_eq!(signal(105.0, bands(45.0), THRESHOLDS, -10), Signal::Exit);
+        assert_eq!(signal(105.0, bands(60.0), THRESHOLDS, -10), Signal::Hold);
+    }
+}
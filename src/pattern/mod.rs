@@ -1 +1,7 @@
+pub mod breakout;
 pub mod cross_gc_dc;
+pub mod mean_reversion;
+pub mod oscillators;
+pub mod pairs;
+pub mod signal;
+pub mod trend;
@@ -0,0 +1,64 @@
+use crate::pattern::cross_gc_dc;
+use crate::pattern::signal::Signal;
+
+// moving average crossover: short crossing above long buys, below sells
+pub fn crossover(long: (f64, f64), short: (f64, f64)) -> Signal {
+    match cross_gc_dc::gc(long, short) {
+        Some(true) => Signal::Buy,
+        Some(false) => Signal::Sell,
+        None => Signal::Hold,
+    }
+}
+
+// rsi below the oversold level buys, above the overbought level sells
+pub fn rsi(value: f64, oversold: f64, overbought: f64) -> Signal {
+    if value < oversold {
+        Signal::Buy
+    } else if value > overbought {
+        Signal::Sell
+    } else {
+        Signal::Hold
+    }
+}
+
+// close below the lower band buys, above the upper band sells
+pub fn bollinger(close: f64, lower: f64, upper: f64) -> Signal {
+    if close < lower {
+        Signal::Buy
+    } else if close > upper {
+        Signal::Sell
+    } else {
+        Signal::Hold
+    }
+}
+
+// macd line crossing its signal line
+pub fn macd(signal_line: (f64, f64), macd_line: (f64, f64)) -> Signal {
+    crossover(signal_line, macd_line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crossover() {
+        assert_eq!(crossover((2.0, 2.0), (1.0, 3.0)), Signal::Buy);
+        assert_eq!(crossover((1.0, 2.0), (2.0, 1.0)), Signal::Sell);
+        assert_eq!(crossover((1.0, 1.0), (2.0, 2.0)), Signal::Hold);
+    }
+
+    #[test]
+    fn test_rsi() {
+        assert_eq!(rsi(25.0, 30.0, 70.0), Signal::Buy);
+        assert_eq!(rsi(75.0, 30.0, 70.0), Signal::Sell);
+        assert_eq!(rsi(50.0, 30.0, 70.0), Signal::Hold);
+    }
+
+    #[test]
+    fn test_bollinger() {
+        assert_eq!(bollinger(9.0, 10.0, 20.0), Signal::Buy);
+        assert_eq!(bollinger(21.0, 10.0, 20.0), Signal::Sell);
+        assert_eq!(bollinger(15.0, 10.0, 20.0), Signal::Hold);
+    }
+}
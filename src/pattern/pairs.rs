@@ -0,0 +1,114 @@
+/// Trade on the spread `a - hedge_ratio * b`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PairSignal {
+    /// Spread is too low: buy `a`, short `b`
+    LongSpread,
+    /// Spread is too high: short `a`, buy `b`
+    ShortSpread,
+    /// Spread has reverted: close both legs
+    Exit,
+    Hold,
+}
+
+/// Hedge ratio and z-score of the latest spread value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spread {
+    pub hedge_ratio: f64,
+    pub zscore: f64,
+}
+
+// ordinary least squares slope of a on b
+pub fn hedge_ratio(a: &[f64], b: &[f64]) -> Option<f64> {
+    let n = a.len().min(b.len());
+    if n < 2 {
+        return None;
+    }
+    let (a, b) = (&a[a.len() - n..], &b[b.len() - n..]);
+    let mean_a = a.iter().sum::<f64>() / n as f64;
+    let mean_b = b.iter().sum::<f64>() / n as f64;
+
+    let covariance: f64 = a
+        .iter()
+        .zip(b)
+        .map(|(x, y)| (x - mean_a) * (y - mean_b))
+        .sum();
+    let variance: f64 = b.iter().map(|y| (y - mean_b).powi(2)).sum();
+
+    if variance <= f64::EPSILON {
+        return None;
+    }
+    Some(covariance / variance)
+}
+
+// z-score of the last spread value over the window
+pub fn spread(a: &[f64], b: &[f64]) -> Option<Spread> {
+    let hedge_ratio = hedge_ratio(a, b)?;
+    let n = a.len().min(b.len());
+    let values: Vec<f64> = a[a.len() - n..]
+        .iter()
+        .zip(&b[b.len() - n..])
+        .map(|(x, y)| x - hedge_ratio * y)
+        .collect();
+
+    let mean = values.iter().sum::<f64>() / n as f64;
+    let deviation = (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n as f64).sqrt();
+    if deviation <= f64::EPSILON {
+        return None;
+    }
+
+    Some(Spread {
+        hedge_ratio,
+        zscore: (values[n - 1] - mean) / deviation,
+    })
+}
+
+// flat: enter when |z| reaches entry_z, in a trade: exit when |z| falls below exit_z
+pub fn signal(zscore: f64, entry_z: f64, exit_z: f64, position_a: i32) -> PairSignal {
+    if position_a != 0 {
+        if zscore.abs() <= exit_z {
+            return PairSignal::Exit;
+        }
+        return PairSignal::Hold;
+    }
+
+    if zscore >= entry_z {
+        PairSignal::ShortSpread
+    } else if zscore <= -entry_z {
+        PairSignal::LongSpread
+    } else {
+        PairSignal::Hold
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hedge_ratio() {
+        let b = [1.0, 2.0, 3.0, 4.0];
+        let a: Vec<f64> = b.iter().map(|x| 2.0 * x + 1.0).collect();
+        assert!((hedge_ratio(&a, &b).unwrap() - 2.0).abs() < 1e-9);
+
+        // Constant b has no defined slope
+        assert_eq!(hedge_ratio(&[1.0, 2.0], &[1.0, 1.0]), None);
+    }
+
+    #[test]
+    fn test_spread_zscore() {
+        let b = [10.0, 11.0, 12.0, 13.0, 14.0, 15.0];
+        let a = [20.0, 22.0, 24.0, 26.0, 28.0, 34.0];
+        let spread = spread(&a, &b).unwrap();
+        // The last value is far above the others
+        assert!(spread.zscore > 1.5);
+    }
+
+    #[test]
+    fn test_signal() {
+        assert_eq!(signal(2.5, 2.0, 0.5, 0), PairSignal::ShortSpread);
+        assert_eq!(signal(-2.5, 2.0, 0.5, 0), PairSignal::LongSpread);
+        assert_eq!(signal(1.0, 2.0, 0.5, 0), PairSignal::Hold);
+        assert_eq!(signal(0.3, 2.0, 0.5, 10), PairSignal::Exit);
+        assert_eq!(signal(1.0, 2.0, 0.5, -10), PairSignal::Hold);
+    }
+}
@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+
+/// Trading decision produced by a single indicator or strategy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Signal {
+    /// Open or add to a long position
+    Buy,
+    /// Close a long position, or open a short when flat
+    Sell,
+    /// Close whatever position is open without reversing
+    Exit,
+    /// Do nothing
+    Hold,
+}
+
+impl Signal {
+    /// Direction of the signal as a vote: +1 for buy, -1 for sell, 0 otherwise
+    pub fn vote(&self) -> f64 {
+        match self {
+            Signal::Buy => 1.0,
+            Signal::Sell => -1.0,
+            Signal::Exit | Signal::Hold => 0.0,
+        }
+    }
+}
+
+/// How the signals of several indicators are combined into one decision
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum CombineRule {
+    /// Every indicator has to emit the same non-hold signal
+    All,
+    /// At least one indicator emits a signal and none contradicts it
+    Any,
+    /// More than half of the vote weight goes to the same non-hold signal
+    Majority,
+    /// Weighted sum of votes must exceed the threshold (0.0..=1.0)
+    Weighted { threshold: f64 },
+}
+
+/// Combine `(signal, weight)` pairs according to `rule`
+///
+/// Weights are only used by [`CombineRule::Weighted`]. An empty input
+/// always yields [`Signal::Hold`].
+pub fn combine(signals: &[(Signal, f64)], rule: &CombineRule) -> Signal {
+    if signals.is_empty() {
+        return Signal::Hold;
+    }
+
+    match rule {
+        CombineRule::All => {
+            let first = signals[0].0;
+            if first != Signal::Hold && signals.iter().all(|(s, _)| *s == first) {
+                first
+            } else {
+                Signal::Hold
+            }
+        }
+        CombineRule::Any => {
+            let mut active = signals
+                .iter()
+                .map(|(s, _)| *s)
+                .filter(|s| *s != Signal::Hold);
+            match active.next() {
+                Some(first) if active.all(|s| s == first) => first,
+                _ => Signal::Hold,
+            }
+        }
+        CombineRule::Majority => {
+            let total: f64 = signals.iter().map(|(_, w)| w.abs()).sum();
+            [Signal::Buy, Signal::Sell, Signal::Exit]
+                .into_iter()
+                .find(|candidate| {
+                    let votes: f64 = signals
+                        .iter()
+                        .filter(|(s, _)| s == candidate)
+                        .map(|(_, w)| w.abs())
+                        .sum();
+                    votes > total / 2.0
+                })
+                .unwrap_or(Signal::Hold)
+        }
+        CombineRule::Weighted { threshold } => {
+            let total: f64 = signals.iter().map(|(_, w)| w.abs()).sum();
+            if total <= 0.0 {
+                return Signal::Hold;
+            }
+            let score: f64 = signals.iter().map(|(s, w)| s.vote() * w.abs()).sum::<f64>() / total;
+
+            if score > *threshold {
+                Signal::Buy
+            } else if score < -*threshold {
+                Signal::Sell
+            } else if signals.iter().any(|(s, _)| *s == Signal::Exit) {
+                Signal::Exit
+            } else {
+                Signal::Hold
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_combine_all() {
+        let signals = [(Signal::Buy, 1.0), (Signal::Buy, 1.0)];
+        assert_eq!(combine(&signals, &CombineRule::All), Signal::Buy);
+
+        let signals = [(Signal::Buy, 1.0), (Signal::Hold, 1.0)];
+        assert_eq!(combine(&signals, &CombineRule::All), Signal::Hold);
+    }
+
+    #[test]
+    fn test_combine_any() {
+        let signals = [(Signal::Hold, 1.0), (Signal::Sell, 1.0)];
+        assert_eq!(combine(&signals, &CombineRule::Any), Signal::Sell);
+
+        // Contradicting signals cancel out
+        let signals = [(Signal::Buy, 1.0), (Signal::Sell, 1.0)];
+        assert_eq!(combine(&signals, &CombineRule::Any), Signal::Hold);
+    }
+
+    #[test]
+    fn test_combine_majority() {
+        let signals = [(Signal::Buy, 1.0), (Signal::Buy, 1.0), (Signal::Sell, 1.0)];
+        assert_eq!(combine(&signals, &CombineRule::Majority), Signal::Buy);
+
+        // Two of four is not a majority
+        let signals = [
+            (Signal::Exit, 1.0),
+            (Signal::Exit, 1.0),
+            (Signal::Hold, 1.0),
+            (Signal::Hold, 1.0),
+        ];
+        assert_eq!(combine(&signals, &CombineRule::Majority), Signal::Hold);
+    }
+
+    #[test]
+    fn test_combine_weighted() {
+        let rule = CombineRule::Weighted { threshold: 0.5 };
+
+        // 3 of 4 weight points vote buy: score 0.75
+        let signals = [(Signal::Buy, 3.0), (Signal::Hold, 1.0)];
+        assert_eq!(combine(&signals, &rule), Signal::Buy);
+
+        // Score 0.5 is not above the threshold
+        let signals = [(Signal::Buy, 3.0), (Signal::Sell, 1.0)];
+        assert_eq!(combine(&signals, &rule), Signal::Hold);
+    }
+
+    #[test]
+    fn test_combine_empty() {
+        assert_eq!(combine(&[], &CombineRule::Any), Signal::Hold);
+    }
+}
@@ -0,0 +1,54 @@
+use crate::pattern::signal::Signal;
+
+/// Direction of the higher-timeframe trend
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trend {
+    Up,
+    Down,
+    Flat,
+}
+
+// close above its moving average is an uptrend, below a downtrend
+pub fn direction(close: f64, sma: f64) -> Trend {
+    if close > sma {
+        Trend::Up
+    } else if close < sma {
+        Trend::Down
+    } else {
+        Trend::Flat
+    }
+}
+
+// entries only with the trend, orders reducing the open position always pass
+pub fn filter(signal: Signal, trend: Trend, position: i32) -> Signal {
+    match signal {
+        Signal::Buy if trend == Trend::Up || position < 0 => Signal::Buy,
+        Signal::Sell if trend == Trend::Down || position > 0 => Signal::Sell,
+        Signal::Buy | Signal::Sell => Signal::Hold,
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_direction() {
+        assert_eq!(direction(11.0, 10.0), Trend::Up);
+        assert_eq!(direction(9.0, 10.0), Trend::Down);
+        assert_eq!(direction(10.0, 10.0), Trend::Flat);
+    }
+
+    #[test]
+    fn test_filter() {
+        assert_eq!(filter(Signal::Buy, Trend::Up, 0), Signal::Buy);
+        assert_eq!(filter(Signal::Buy, Trend::Down, 0), Signal::Hold);
+        assert_eq!(filter(Signal::Sell, Trend::Up, 0), Signal::Hold);
+        assert_eq!(filter(Signal::Sell, Trend::Down, 0), Signal::Sell);
+        // Closing a long against the trend is still allowed
+        assert_eq!(filter(Signal::Sell, Trend::Up, 10), Signal::Sell);
+        assert_eq!(filter(Signal::Buy, Trend::Flat, -10), Signal::Buy);
+        assert_eq!(filter(Signal::Exit, Trend::Flat, 10), Signal::Exit);
+    }
+}
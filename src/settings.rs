@@ -1,32 +1,468 @@
-use config::{Config, Environment, File};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Utc};
+use config::builder::DefaultState;
+use config::{Config, ConfigBuilder, Environment, File};
 use serde::Deserialize;
 
+use crate::broker::execution::ExecutionSettings;
+use crate::broker::position_sizing::PositionSizer;
+use crate::broker::reload::ReloadSettings;
+use crate::broker::snapshot::SnapshotSettings;
+use crate::broker::strategy::{StrategyConfig, StrategyKind};
+use crate::control::ControlSettings;
+use crate::db::Metric;
+use crate::error::CLIError;
+use crate::mocking::adjust::Adjustment;
+use crate::mocking::layout::CsvLayout;
+use crate::mocking::source::{parse_bar_size, ReplaySpeed};
+use crate::wrangling::buffers::BUFFER_SIZE;
+use crate::wrangling::calendar::TradingCalendar;
+use crate::wrangling::quality::QualityPolicy;
+
 #[derive(Debug, Deserialize)]
 pub struct Settings {
     pub depot_url: String,
     pub indicator_url: String,
-    pub api_key_id: String,
-    pub api_secret_key: String,
+    /// From `APP__API_KEY_ID` or the file named by `api_key_id_file`
+    pub api_key_id: Option<Secret>,
+    /// From `APP__API_SECRET_KEY` or the file named by `api_secret_key_file`
+    pub api_secret_key: Option<Secret>,
     pub api_base_url: String,
     pub use_mock_data: bool,
     pub mock_file_path: String,
+    /// Symbol the bars of `mock_file_path` are traded as
+    pub mock_symbol: String,
+    /// Symbols subscribed to in live mode
+    pub live_symbols: Vec<String>,
+    /// Root directory of the Parquet bar store
+    pub bar_store: String,
+    /// Timeframe partition replayed from the bar store, e.g. `1d`
+    pub timeframe: String,
+    /// Replay these symbols straight from the bar store instead of importing
+    /// `mock_file_path`, e.g. after `trader-bot download`
+    #[serde(default)]
+    pub replay_symbols: Vec<String>,
     pub top_n_configs: usize,
     pub eval_iterations: usize,
     pub surreal_db_url: String,
     pub surreal_db_user: String,
-    pub surreal_db_pass: String,
+    /// From `APP__SURREAL_DB_PASS` or the file named by `surreal_db_pass_file`
+    pub surreal_db_pass: Option<Secret>,
     pub max_trade_percent: f64,
     pub max_position_percent: f64,
+    pub strategy: StrategyKind,
+    pub pair: Option<PairSettings>,
+    /// Columns of `mock_file_path`, Yahoo Finance exports if not set
+    #[serde(default)]
+    pub csv_layout: CsvLayout,
+    /// Split and dividend handling of `mock_file_path`
+    #[serde(default)]
+    pub adjustment: Adjustment,
+    /// Pacing of the mock stream
+    #[serde(default)]
+    pub replay_speed: ReplaySpeed,
+    /// Only replay bars from this time on
+    pub replay_start: Option<DateTime<Utc>>,
+    /// Only replay bars up to this time
+    pub replay_end: Option<DateTime<Utc>>,
+    /// Aggregate the mock bars to this size, e.g. `1w`
+    pub resample: Option<String>,
+    /// Append every live market data message to this file
+    pub record_path: Option<String>,
+    /// Replay a recorded live session instead of historical bars
+    pub replay_recording: Option<String>,
+    /// What happens to bars that fail validation
+    #[serde(default)]
+    pub quality_policy: QualityPolicy,
+    /// Exchange sessions and how they are traded
+    #[serde(default)]
+    pub calendar: CalendarSettings,
+    /// Higher timeframes kept per symbol next to the base bars, e.g. `1d`
+    #[serde(default)]
+    pub timeframes: Vec<String>,
+    /// Timeframe strategies act on, built from the base bars, e.g. `5m` on 1-minute live bars;
+    /// the base bars if not set
+    pub entry_timeframe: Option<String>,
+    /// Only enter in the direction of a higher-timeframe trend
+    pub trend_filter: Option<TrendFilterSettings>,
+    /// Pricing of orders against the bid and ask
+    #[serde(default)]
+    pub execution: ExecutionSettings,
+    /// RNG seed of the first evaluation run, later runs add their index
+    pub seed: Option<u64>,
+    /// Study the evaluation runs are stored under
+    pub study: Option<StudySettings>,
+    /// Periodic snapshots of the live state, restored at startup
+    pub snapshot: Option<SnapshotSettings>,
+    /// Strategy traded live, if not set a 5 bar SMA crossed with the SMA of every buffered bar
+    pub live_strategy: Option<StrategyConfig>,
+    /// Reload `live_strategy` and the position limits when the config files change
+    pub reload: Option<ReloadSettings>,
+    /// gRPC API to inspect and steer the live bot
+    pub control: Option<ControlSettings>,
+    /// From `APP__CONTROL_TOKEN` or the file named by `control_token_file`, required by the
+    /// control API unless it listens on localhost
+    pub control_token: Option<Secret>,
+}
+
+/// Name and goal of the study a batch of runs belongs to
+#[derive(Debug, Deserialize, Clone)]
+pub struct StudySettings {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Metric the runs are ranked by
+    #[serde(default)]
+    pub objective: Metric,
+    /// Searched parameters, the random search of `strategy` if not set
+    pub parameter_space: Option<serde_json::Value>,
+}
+
+/// Moving average trend of a higher timeframe that entries have to follow
+#[derive(Debug, Deserialize, Clone)]
+pub struct TrendFilterSettings {
+    /// Timeframe of the trend, kept even if missing in `timeframes`
+    pub timeframe: String,
+    pub sma_period: i32,
+}
+
+/// Trading calendar and session rules
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct CalendarSettings {
+    /// Holidays and half days, NYSE weekdays only if not set
+    pub holidays_file: Option<String>,
+    /// Trade pre-market and after-hours bars instead of skipping them
+    pub extended_hours: bool,
+    /// Close all positions this many minutes before the regular close
+    pub flatten_before_close: Option<i64>,
+}
+
+/// The two legs traded by the pairs strategy, with their backtest files
+#[derive(Debug, Deserialize, Clone)]
+pub struct PairSettings {
+    pub symbol_a: String,
+    pub file_a: String,
+    pub symbol_b: String,
+    pub file_b: String,
+}
+
+/// A credential, printed as `[redacted]`
+#[derive(Clone, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+/// Settings that are only read from the environment or from files
+const SECRETS: [&str; 4] = [
+    "api_key_id",
+    "api_secret_key",
+    "surreal_db_pass",
+    "control_token",
+];
+
+/// Values set on the command line, they win over the files and environment
+static OVERRIDES: OnceLock<Vec<(String, config::Value)>> = OnceLock::new();
+
+/// Overlay on `config/default.toml`, e.g. `dev`, `paper`, `live` or `backtest`
+static PROFILE: OnceLock<Option<String>> = OnceLock::new();
+
+/// Keys whose values are never stored or logged
+fn is_secret(key: &str) -> bool {
+    let key = key.to_lowercase();
+    // Paths of secret files are not secret themselves
+    if key.ends_with("_file") {
+        return false;
+    }
+    ["secret", "pass", "token", "key_id"]
+        .iter()
+        .any(|part| key.contains(part))
+}
+
+/// Remove every secret from a settings tree
+fn redact(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            map.retain(|key, _| !is_secret(key));
+            map.values_mut().for_each(redact);
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
 }
 
 impl Settings {
     pub fn new() -> Result<Self, config::ConfigError> {
-        let builder = Config::builder()
-            .add_source(File::with_name("config/default"))
-            .add_source(Environment::with_prefix("APP").separator("__"));
+        Self::config()?.try_deserialize()
+    }
+
+    /// Apply `overrides` to all settings loaded afterwards, only the first call counts
+    pub fn set_overrides(overrides: Vec<(String, config::Value)>) {
+        let _ = OVERRIDES.set(overrides);
+    }
+
+    /// Overlay `config/<profile>.toml` on all settings loaded afterwards,
+    /// `APP_PROFILE` is used if not set
+    pub fn set_profile(profile: Option<String>) {
+        let _ = PROFILE.set(profile);
+    }
+
+    /// Profile in use, if any
+    pub fn profile() -> Option<String> {
+        PROFILE
+            .get()
+            .cloned()
+            .flatten()
+            .or_else(|| std::env::var("APP_PROFILE").ok())
+            .filter(|profile| !profile.is_empty())
+    }
+
+    /// Files the settings are read from, later ones overlay earlier ones
+    pub fn config_files() -> Vec<PathBuf> {
+        let mut files = vec![PathBuf::from("config/default.toml")];
+        if let Some(profile) = Self::profile() {
+            files.push(PathBuf::from(format!("config/{}.toml", profile)));
+        }
+        files
+    }
+
+    fn config() -> Result<Config, config::ConfigError> {
+        let mut files = Config::builder();
+        for path in Self::config_files() {
+            files = files.add_source(File::from(path.as_path()));
+        }
+        // Files are committed, secrets in them would end up in the repository
+        reject_secrets(&files.build_cloned()?)?;
+
+        let mut builder = files.add_source(Environment::with_prefix("APP").separator("__"));
+        for (key, value) in OVERRIDES.get().into_iter().flatten() {
+            builder = builder.set_override(key.as_str(), value.clone())?;
+        }
+        read_secret_files(builder)?.build()
+    }
+
+    /// Problems with the values, all of them at once
+    pub fn validate(&self) -> Result<(), CLIError> {
+        let mut problems =
+            PositionSizer::new(self.max_trade_percent, self.max_position_percent).problems();
+        if let Some(strategy) = &self.live_strategy {
+            for problem in strategy.problems() {
+                problems.push(format!("live_strategy: {}", problem));
+            }
+            if strategy.lookback() > BUFFER_SIZE {
+                problems.push(format!(
+                    "live_strategy: needs {} bars, only {} are buffered",
+                    strategy.lookback(),
+                    BUFFER_SIZE
+                ));
+            }
+        }
+        let mut check = |ok: bool, problem: String| {
+            if !ok {
+                problems.push(problem);
+            }
+        };
+
+        check(
+            self.eval_iterations > 0,
+            "eval_iterations must be at least 1".to_string(),
+        );
+        check(
+            self.top_n_configs > 0,
+            "top_n_configs must be at least 1".to_string(),
+        );
+        for (key, url) in [
+            ("depot_url", &self.depot_url),
+            ("indicator_url", &self.indicator_url),
+            ("api_base_url", &self.api_base_url),
+            ("surreal_db_url", &self.surreal_db_url),
+        ] {
+            check(
+                url.contains("://"),
+                format!("{} must be a URL, is {:?}", key, url),
+            );
+        }
+
+        let bar_sizes = std::iter::once(("timeframe", Some(&self.timeframe)))
+            .chain([("resample", self.resample.as_ref())])
+            .chain([("entry_timeframe", self.entry_timeframe.as_ref())])
+            .chain(self.timeframes.iter().map(|tf| ("timeframes", Some(tf))))
+            .chain([(
+                "trend_filter.timeframe",
+                self.trend_filter.as_ref().map(|f| &f.timeframe),
+            )]);
+        for (key, size) in bar_sizes {
+            if let Some(size) = size {
+                check(
+                    parse_bar_size(size).is_some(),
+                    format!("{} is not a bar size like 5m or 1d: {:?}", key, size),
+                );
+            }
+        }
+        if let Some(filter) = &self.trend_filter {
+            check(
+                filter.sma_period > 0,
+                "trend_filter.sma_period must be at least 1".to_string(),
+            );
+        }
+        if let (Some(start), Some(end)) = (self.replay_start, self.replay_end) {
+            check(
+                start < end,
+                "replay_start must be before replay_end".to_string(),
+            );
+        }
+        check(
+            (0.0..=0.5).contains(&self.execution.price_offset),
+            format!(
+                "execution.price_offset must be in [0, 0.5], is {}",
+                self.execution.price_offset
+            ),
+        );
+        if let Some(max) = self.execution.max_spread_percent {
+            check(
+                max > 0.0,
+                "execution.max_spread_percent must be positive".to_string(),
+            );
+        }
+        if let Some(secs) = self.execution.max_quote_age_secs {
+            check(
+                secs > 0,
+                "execution.max_quote_age_secs must be positive".to_string(),
+            );
+        }
+        if let Some(minutes) = self.calendar.flatten_before_close {
+            check(
+                minutes >= 0,
+                "calendar.flatten_before_close must not be negative".to_string(),
+            );
+        }
+        if let Some(reload) = &self.reload {
+            check(
+                reload.interval_secs > 0,
+                "reload.interval_secs must be at least 1".to_string(),
+            );
+        }
+        if let Some(control) = &self.control {
+            let addr = control.addr.parse::<std::net::SocketAddr>();
+            check(
+                addr.is_ok(),
+                format!(
+                    "control.addr must be an address like 127.0.0.1:50052, is {:?}",
+                    control.addr
+                ),
+            );
+            if let Ok(addr) = addr {
+                check(
+                    addr.ip().is_loopback() || self.control_token.is_some(),
+                    format!(
+                        "control.addr {} is reachable from other hosts, set APP__CONTROL_TOKEN \
+                         or control_token_file",
+                        addr
+                    ),
+                );
+            }
+        }
+        if let Some(snapshot) = &self.snapshot {
+            check(
+                snapshot.interval_secs > 0,
+                "snapshot.interval_secs must be at least 1".to_string(),
+            );
+            check(
+                snapshot.max_age_minutes >= 0,
+                "snapshot.max_age_minutes must not be negative".to_string(),
+            );
+        }
+        check(
+            self.strategy != StrategyKind::Pairs || self.pair.is_some(),
+            "strategy = \"pairs\" needs the [pair] table".to_string(),
+        );
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(CLIError::InvalidSettings(problems))
+        }
+    }
+
+    /// Alpaca key id and secret, needed to stream or download market data
+    pub fn alpaca_credentials(&self) -> Result<(&str, &str), CLIError> {
+        match (&self.api_key_id, &self.api_secret_key) {
+            (Some(key_id), Some(secret)) => Ok((key_id.expose(), secret.expose())),
+            _ => Err(CLIError::InvalidSettings(vec![
+                "api_key_id and api_secret_key are required, set APP__API_KEY_ID and \
+                 APP__API_SECRET_KEY or api_key_id_file and api_secret_key_file"
+                    .to_string(),
+            ])),
+        }
+    }
+
+    /// SurrealDB root password, embedded databases need none
+    pub fn db_pass(&self) -> &str {
+        self.surreal_db_pass.as_ref().map_or("", Secret::expose)
+    }
+
+    /// Loaded settings as stored with run results, without secrets
+    pub fn snapshot() -> Result<serde_json::Value, config::ConfigError> {
+        let mut value: serde_json::Value = Self::config()?.try_deserialize()?;
+        redact(&mut value);
+        Ok(value)
+    }
+
+    /// NYSE calendar with the configured holidays
+    pub fn trading_calendar(
+        &self,
+    ) -> Result<TradingCalendar, Box<dyn std::error::Error + Send + Sync>> {
+        match &self.calendar.holidays_file {
+            Some(file) => TradingCalendar::load(file),
+            None => Ok(TradingCalendar::nyse()),
+        }
+    }
+}
+
+/// Fail if a config file sets a secret
+fn reject_secrets(files: &Config) -> Result<(), config::ConfigError> {
+    for key in SECRETS {
+        if files.get_string(key).is_ok() {
+            return Err(config::ConfigError::Message(format!(
+                "{} is set in a config file, set APP__{} or {}_file instead",
+                key,
+                key.to_uppercase(),
+                key
+            )));
+        }
+    }
+    Ok(())
+}
 
-        builder.build()?.try_deserialize()
+/// Set every secret whose `<key>_file` is configured to the file's content
+fn read_secret_files(
+    mut builder: ConfigBuilder<DefaultState>,
+) -> Result<ConfigBuilder<DefaultState>, config::ConfigError> {
+    let loaded = builder.build_cloned()?;
+    for key in SECRETS {
+        let Ok(path) = loaded.get_string(&format!("{}_file", key)) else {
+            continue;
+        };
+        let value = std::fs::read_to_string(&path).map_err(|e| {
+            config::ConfigError::Message(format!("Failed to read {}_file {}: {}", key, path, e))
+        })?;
+        builder = builder.set_override(key, value.trim().to_string())?;
     }
+    Ok(builder)
 }
 
 #[cfg(test)]
@@ -44,4 +480,133 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_redact_secrets() {
+        let mut value = serde_json::json!({
+            "api_key_id": "PK123",
+            "api_secret_key": "secret",
+            "surreal_db_pass": "hunter2",
+            "surreal_db_user": "admin",
+            "pair": { "symbol_a": "ORCL", "token": "t" },
+        });
+        redact(&mut value);
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "surreal_db_user": "admin",
+                "pair": { "symbol_a": "ORCL" },
+            })
+        );
+    }
+
+    const DEFAULT: &str = include_str!("../config/default.toml");
+
+    fn load(files: &[&str], overrides: &[(&str, &str)]) -> Settings {
+        let mut builder = Config::builder();
+        for file in files {
+            builder = builder.add_source(File::from_str(file, config::FileFormat::Toml));
+        }
+        for (key, value) in overrides {
+            builder = builder.set_override(*key, *value).unwrap();
+        }
+        builder.build().unwrap().try_deserialize().unwrap()
+    }
+
+    #[test]
+    fn test_profiles_are_valid() {
+        let profiles = [
+            include_str!("../config/dev.toml"),
+            include_str!("../config/paper.toml"),
+            include_str!("../config/live.toml"),
+            include_str!("../config/backtest.toml"),
+        ];
+        load(&[DEFAULT], &[]).validate().unwrap();
+        for profile in profiles {
+            load(&[DEFAULT, profile], &[]).validate().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_validate_lists_every_problem() {
+        let settings = load(
+            &[DEFAULT],
+            &[
+                ("max_trade_percent", "150"),
+                ("timeframe", "1y"),
+                ("strategy", "pairs"),
+            ],
+        );
+        let Err(CLIError::InvalidSettings(problems)) = settings.validate() else {
+            panic!("Settings should be invalid");
+        };
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert!(problems[0].starts_with("max_trade_percent"));
+        assert!(problems.iter().any(|p| p.contains("[pair]")));
+    }
+
+    #[test]
+    fn test_live_strategy_from_toml() {
+        let strategy = r#"
+            [live_strategy]
+            strategy = "trend"
+            sma = { long_range = 5, short_range = 20 }
+        "#;
+        let settings = load(&[DEFAULT, strategy], &[]);
+        assert!(matches!(
+            settings.live_strategy,
+            Some(StrategyConfig::Trend(_))
+        ));
+        let Err(CLIError::InvalidSettings(problems)) = settings.validate() else {
+            panic!("Settings should be invalid");
+        };
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(problems[0].starts_with("live_strategy: sma"));
+    }
+
+    #[test]
+    fn test_remote_control_needs_token() {
+        let remote = [("control.addr", "0.0.0.0:50052")];
+        let Err(CLIError::InvalidSettings(problems)) = load(&[DEFAULT], &remote).validate() else {
+            panic!("Settings should be invalid");
+        };
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(problems[0].contains("APP__CONTROL_TOKEN"));
+
+        let with_token = [remote[0], ("control_token", "s3cret")];
+        load(&[DEFAULT], &with_token).validate().unwrap();
+        load(&[DEFAULT], &[("control.addr", "127.0.0.1:50052")])
+            .validate()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_secrets_only_from_env_or_files() {
+        let files = Config::builder()
+            .add_source(File::from_str(
+                "api_secret_key = \"abc\"",
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        assert!(reject_secrets(&files).is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret");
+        std::fs::write(&path, "s3cr3t\n").unwrap();
+        let builder = Config::builder()
+            .add_source(File::from_str(DEFAULT, config::FileFormat::Toml))
+            .set_override("api_secret_key_file", path.to_str().unwrap())
+            .unwrap();
+        let settings: Settings = read_secret_files(builder)
+            .unwrap()
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+        let secret = settings.api_secret_key.as_ref().unwrap();
+        assert_eq!(secret.expose(), "s3cr3t");
+        assert!(!format!("{:?}", settings).contains("s3cr3t"));
+        assert!(settings.alpaca_credentials().is_err());
+    }
 }
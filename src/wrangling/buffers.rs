@@ -1,4 +1,16 @@
+use std::collections::HashMap;
+
 use apca::data::v2::stream::{Bar, Trade};
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+
+use crate::error::CLIError;
+use crate::mocking::layout::CsvRow;
+use crate::mocking::source::Resampler;
+use crate::wrangling::calendar::TradingCalendar;
+
+/// Bars kept per symbol and timeframe, strategies can't look back further
+pub const BUFFER_SIZE: usize = 100;
 
 #[derive(Debug)]
 pub struct Buffer {
@@ -65,11 +77,143 @@ impl Buffer {
     }
 }
 
+/// Bars of one symbol at higher timeframes, built from its base bars
+///
+/// A higher-timeframe bar is only added once the first base bar of the next
+/// period arrives, so strategies never see a bar before it has closed.
+/// Intraday periods start at the regular open of the calendar's sessions,
+/// longer ones at exchange midnight.
+#[derive(Debug)]
+pub struct Timeframes {
+    frames: Vec<(String, Resampler, Buffer)>,
+}
+
+impl Timeframes {
+    /// Keep the last `size` bars of each `(name, bar size)` timeframe
+    pub fn new(
+        symbol: &str,
+        timeframes: &[(String, Duration)],
+        size: usize,
+        calendar: &TradingCalendar,
+    ) -> Self {
+        Self {
+            frames: timeframes
+                .iter()
+                .map(|(name, bar_size)| {
+                    (
+                        name.clone(),
+                        Resampler::new(*bar_size).with_calendar(calendar.clone()),
+                        Buffer::new(symbol.to_string(), size),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Aggregate a base bar into every timeframe
+    pub fn add_bar(&mut self, bar: &Bar) -> Result<(), CLIError> {
+        let row = CsvRow::from_bar(bar)?;
+        for (_, resampler, buffer) in &mut self.frames {
+            if let Some(closed) = resampler.push(row.clone()) {
+                buffer.add_bar(closed.to_bar()?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Closed bars of `timeframe`, like `1d`
+    pub fn get(&self, timeframe: &str) -> Option<&Buffer> {
+        self.frames
+            .iter()
+            .find(|(name, _, _)| name == timeframe)
+            .map(|(_, _, buffer)| buffer)
+    }
+
+    /// Closed and open bars of every timeframe
+    pub fn state(&self) -> Result<Vec<FrameState>, CLIError> {
+        self.frames
+            .iter()
+            .map(|(name, resampler, buffer)| {
+                Ok(FrameState {
+                    name: name.clone(),
+                    bars: buffer.get_bars().cloned().unwrap_or_default(),
+                    open: resampler
+                        .open_bucket(buffer.symbol())
+                        .map(CsvRow::to_bar)
+                        .transpose()?,
+                })
+            })
+            .collect()
+    }
+
+    /// Continue from saved frames, frames no longer configured are skipped
+    pub fn restore(&mut self, states: Vec<FrameState>) -> Result<(), CLIError> {
+        for state in states {
+            let Some((_, resampler, buffer)) = self
+                .frames
+                .iter_mut()
+                .find(|(name, _, _)| *name == state.name)
+            else {
+                continue;
+            };
+            for bar in state.bars {
+                buffer.add_bar(bar);
+            }
+            if let Some(open) = state.open {
+                resampler.resume(CsvRow::from_bar(&open)?);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Bars of one timeframe as kept in a snapshot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrameState {
+    pub name: String,
+    /// Closed bars, oldest first
+    pub bars: Vec<Bar>,
+    /// Bar of the period still in progress
+    pub open: Option<Bar>,
+}
+
+/// Close prices of a buffer, oldest first
+pub fn closes(buffer: &Buffer) -> Vec<f64> {
+    buffer
+        .get_bars()
+        .map(|bars| {
+            bars.iter()
+                .map(|bar| bar.close_price.to_f64().unwrap_or(0.0))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Close prices of two buffers at the timestamps both of them have, oldest first
+pub fn aligned_closes(a: &Buffer, b: &Buffer) -> (Vec<f64>, Vec<f64>) {
+    let empty = Vec::new();
+    let bars_b = b.get_bars().unwrap_or(&empty);
+    let closes_b: HashMap<_, _> = bars_b
+        .iter()
+        .map(|bar| (bar.timestamp, bar.close_price.to_f64().unwrap_or(0.0)))
+        .collect();
+
+    a.get_bars()
+        .unwrap_or(&empty)
+        .iter()
+        .filter_map(|bar| {
+            closes_b
+                .get(&bar.timestamp)
+                .map(|close_b| (bar.close_price.to_f64().unwrap_or(0.0), *close_b))
+        })
+        .unzip()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::mocking::mock::values_to_bar;
-    use chrono::Utc;
+    use chrono::{TimeZone, Utc};
 
     #[tokio::test]
     async fn test_buffer_initialization() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -85,7 +229,7 @@ mod tests {
     #[tokio::test]
     async fn test_add_single_bar() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let mut buffer = Buffer::new("AAPL".to_string(), 10);
-        let bar = values_to_bar("AAPL", Utc::now(), 100.0, 105.0, 110.0, 95.0, 1000.0);
+        let bar = values_to_bar("AAPL", Utc::now(), 100.0, 105.0, 110.0, 95.0, 1000.0)?;
 
         buffer.add_bar(bar);
 
@@ -108,7 +252,7 @@ mod tests {
                 110.0 + i as f64,
                 95.0 + i as f64,
                 1000.0,
-            );
+            )?;
             buffer.add_bar(bar);
         }
 
@@ -132,7 +276,7 @@ mod tests {
                 210.0 + i as f64,
                 195.0 + i as f64,
                 1000.0,
-            );
+            )?;
             buffer.add_bar(bar);
         }
 
@@ -168,4 +312,112 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_aligned_closes() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let start = Utc::now();
+        let mut a = Buffer::new("AAA".to_string(), 10);
+        let mut b = Buffer::new("BBB".to_string(), 10);
+
+        for i in 0..4 {
+            let timestamp = start + chrono::Duration::days(i);
+            a.add_bar(values_to_bar(
+                "AAA",
+                timestamp,
+                1.0,
+                10.0 + i as f64,
+                1.0,
+                1.0,
+                1.0,
+            )?);
+            // b is missing the second day
+            if i != 1 {
+                b.add_bar(values_to_bar(
+                    "BBB",
+                    timestamp,
+                    1.0,
+                    20.0 + i as f64,
+                    1.0,
+                    1.0,
+                    1.0,
+                )?);
+            }
+        }
+
+        let (closes_a, closes_b) = aligned_closes(&a, &b);
+        assert_eq!(closes_a, vec![10.0, 12.0, 13.0]);
+        assert_eq!(closes_b, vec![20.0, 22.0, 23.0]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_higher_timeframe_waits_for_close(
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // 9:00 in New York, half an hour before the open
+        let start = Utc.with_ymd_and_hms(2024, 1, 2, 14, 0, 0).unwrap();
+        let mut timeframes = Timeframes::new(
+            "AAA",
+            &[("1h".to_string(), Duration::hours(1))],
+            10,
+            &TradingCalendar::nyse(),
+        );
+
+        // Twelve 5-minute bars fill the 8:30 and 9:30 hours
+        for i in 0..12 {
+            let timestamp = start + Duration::minutes(5 * i);
+            let close = 10.0 + i as f64;
+            timeframes.add_bar(&values_to_bar(
+                "AAA", timestamp, close, close, close, close, 1.0,
+            )?)?;
+        }
+        // Only the 8:30 hour has closed, the 9:30 hour is still open
+        let hourly = timeframes.get("1h").unwrap();
+        assert_eq!(closes(hourly), vec![15.0]);
+
+        let timestamp = start + Duration::minutes(90);
+        timeframes.add_bar(&values_to_bar("AAA", timestamp, 1.0, 1.0, 1.0, 1.0, 1.0)?)?;
+        assert_eq!(closes(timeframes.get("1h").unwrap()), vec![15.0, 21.0]);
+        assert!(timeframes.get("1d").is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_timeframes_restored_mid_period(
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // The regular open in New York
+        let start = Utc.with_ymd_and_hms(2024, 1, 2, 14, 30, 0).unwrap();
+        let hourly = [("1h".to_string(), Duration::hours(1))];
+        let calendar = TradingCalendar::nyse();
+        let bar = |minutes: i64, close: f64| {
+            values_to_bar(
+                "AAA",
+                start + Duration::minutes(minutes),
+                close,
+                close,
+                close,
+                close,
+                1.0,
+            )
+        };
+
+        let mut before = Timeframes::new("AAA", &hourly, 10, &calendar);
+        for (minutes, close) in [(0, 10.0), (30, 11.0), (60, 12.0)] {
+            before.add_bar(&bar(minutes, close)?)?;
+        }
+        let state = before.state()?;
+        assert!(state[0].open.is_some());
+
+        // The 10:30 hour continues after the restart instead of starting over
+        let mut after = Timeframes::new("AAA", &hourly, 10, &calendar);
+        after.restore(state)?;
+        after.add_bar(&bar(90, 13.0)?)?;
+        after.add_bar(&bar(120, 14.0)?)?;
+        let bars = after.get("1h").unwrap().get_bars().unwrap();
+        assert_eq!(closes(after.get("1h").unwrap()), vec![11.0, 13.0]);
+        assert_eq!(bars[1].open_price.to_f64(), Some(12.0));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,284 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use csv::ReaderBuilder;
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::error::CLIError;
+
+/// Part of the trading day a timestamp falls into
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Session {
+    PreMarket,
+    Regular,
+    AfterHours,
+    Closed,
+}
+
+/// Sessions of an exchange in its local time
+///
+/// Weekends and the loaded holidays are closed, half days close early.
+/// Dates outside the years of the holidays file are taken as regular days,
+/// which is warned about once.
+#[derive(Debug, Clone)]
+pub struct TradingCalendar {
+    timezone: Tz,
+    pre_market_open: NaiveTime,
+    open: NaiveTime,
+    close: NaiveTime,
+    early_close: NaiveTime,
+    after_hours_close: NaiveTime,
+    holidays: HashSet<NaiveDate>,
+    half_days: HashSet<NaiveDate>,
+    /// First and last day of the years in the holidays file
+    covered: Option<(NaiveDate, NaiveDate)>,
+    /// Shared by clones, so a calendar warns about uncovered dates only once
+    warned: Arc<AtomicBool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HolidayRecord {
+    #[serde(rename = "Date")]
+    date: String,
+    #[serde(rename = "Kind")]
+    kind: String,
+}
+
+fn time(hour: u32, minute: u32) -> NaiveTime {
+    NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+}
+
+impl Default for TradingCalendar {
+    fn default() -> Self {
+        Self::nyse()
+    }
+}
+
+impl TradingCalendar {
+    /// NYSE hours without holidays, extended sessions as offered by Alpaca
+    pub fn nyse() -> Self {
+        Self {
+            timezone: chrono_tz::America::New_York,
+            pre_market_open: time(4, 0),
+            open: time(9, 30),
+            close: time(16, 0),
+            early_close: time(13, 0),
+            after_hours_close: time(20, 0),
+            holidays: HashSet::new(),
+            half_days: HashSet::new(),
+            covered: None,
+            warned: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// NYSE hours with the holidays and half days of a `Date,Kind` CSV file
+    ///
+    /// `Kind` is `holiday` or `half_day`, dates are `%Y-%m-%d`. The file is
+    /// taken to list every holiday of the years it has dates in.
+    pub fn load(filename: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut calendar = Self::nyse();
+        let mut reader = ReaderBuilder::new().has_headers(true).from_path(filename)?;
+
+        for result in reader.deserialize() {
+            let record: HolidayRecord = result?;
+            let date = NaiveDate::parse_from_str(record.date.trim(), "%Y-%m-%d")
+                .map_err(|_| CLIError::InvalidTimestamp(record.date.clone()))?;
+            match record.kind.trim().to_lowercase().as_str() {
+                "holiday" => calendar.holidays.insert(date),
+                "half_day" => calendar.half_days.insert(date),
+                _ => return Err(CLIError::ConvertingError.into()),
+            };
+        }
+
+        let dates = calendar.holidays.iter().chain(&calendar.half_days);
+        calendar.covered = dates
+            .clone()
+            .min()
+            .zip(dates.max())
+            .and_then(|(first, last)| {
+                Some((
+                    NaiveDate::from_ymd_opt(first.year(), 1, 1)?,
+                    NaiveDate::from_ymd_opt(last.year(), 12, 31)?,
+                ))
+            });
+        Ok(calendar)
+    }
+
+    /// Whether the holidays of `date` are known, always for a calendar without a file
+    pub fn covers(&self, date: NaiveDate) -> bool {
+        self.covered
+            .is_none_or(|(first, last)| first <= date && date <= last)
+    }
+
+    pub fn timezone(&self) -> Tz {
+        self.timezone
+    }
+
+    /// Exchange date of `timestamp`
+    pub fn session_date(&self, timestamp: DateTime<Utc>) -> NaiveDate {
+        timestamp.with_timezone(&self.timezone).date_naive()
+    }
+
+    pub fn is_trading_day(&self, date: NaiveDate) -> bool {
+        if !self.covers(date) && !self.warned.swap(true, Ordering::Relaxed) {
+            if let Some((first, last)) = self.covered {
+                warn!(
+                    "{} is outside the holidays file ({} to {}), holidays are missed",
+                    date, first, last
+                );
+            }
+        }
+        !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) && !self.holidays.contains(&date)
+    }
+
+    /// Bars stamped at exchange midnight cover a whole session, like daily bars
+    pub fn is_daily(&self, timestamp: DateTime<Utc>) -> bool {
+        timestamp.with_timezone(&self.timezone).time() == NaiveTime::MIN
+    }
+
+    pub fn session(&self, timestamp: DateTime<Utc>) -> Session {
+        let local = timestamp.with_timezone(&self.timezone);
+        let date = local.date_naive();
+        if !self.is_trading_day(date) {
+            return Session::Closed;
+        }
+
+        let now = local.time();
+        let close = self.close_time(date);
+        if now < self.pre_market_open {
+            Session::Closed
+        } else if now < self.open {
+            Session::PreMarket
+        } else if now < close {
+            Session::Regular
+        } else if now < self.after_hours_close && !self.half_days.contains(&date) {
+            // Half days have no after-hours session
+            Session::AfterHours
+        } else {
+            Session::Closed
+        }
+    }
+
+    /// Regular close of the session of `date`, early on half days
+    pub fn close(&self, date: NaiveDate) -> Option<DateTime<Utc>> {
+        if !self.is_trading_day(date) {
+            return None;
+        }
+        self.at(date, self.close_time(date))
+    }
+
+    /// Time of the regular open on `date`, whether or not it is a trading day
+    pub fn regular_open(&self, date: NaiveDate) -> Option<DateTime<Utc>> {
+        self.at(date, self.open)
+    }
+
+    /// Exchange midnight starting `date`, the timestamp of its daily bar
+    pub fn midnight(&self, date: NaiveDate) -> Option<DateTime<Utc>> {
+        self.at(date, NaiveTime::MIN)
+    }
+
+    /// Minutes until the regular close, `None` outside the regular session
+    pub fn minutes_to_close(&self, timestamp: DateTime<Utc>) -> Option<i64> {
+        if self.session(timestamp) != Session::Regular {
+            return None;
+        }
+        let close = self.close(self.session_date(timestamp))?;
+        Some((close - timestamp).num_minutes())
+    }
+
+    /// Trading days strictly between two session dates
+    pub fn sessions_between(&self, from: NaiveDate, to: NaiveDate) -> usize {
+        from.iter_days()
+            .skip(1)
+            .take_while(|day| *day < to)
+            .filter(|day| self.is_trading_day(*day))
+            .count()
+    }
+
+    fn at(&self, date: NaiveDate, time: NaiveTime) -> Option<DateTime<Utc>> {
+        self.timezone
+            .from_local_datetime(&date.and_time(time))
+            .earliest()
+            .map(|t| t.with_timezone(&Utc))
+    }
+
+    fn close_time(&self, date: NaiveDate) -> NaiveTime {
+        if self.half_days.contains(&date) {
+            self.early_close
+        } else {
+            self.close
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_york(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        // November 2024 is in EST, UTC-5
+        Utc.with_ymd_and_hms(2024, 11, day, hour + 5, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_sessions() {
+        let calendar = TradingCalendar::nyse();
+        assert_eq!(calendar.session(new_york(26, 3, 59)), Session::Closed);
+        assert_eq!(calendar.session(new_york(26, 9, 0)), Session::PreMarket);
+        assert_eq!(calendar.session(new_york(26, 9, 30)), Session::Regular);
+        assert_eq!(calendar.session(new_york(26, 16, 0)), Session::AfterHours);
+        // Saturday
+        assert_eq!(calendar.session(new_york(23, 12, 0)), Session::Closed);
+    }
+
+    #[test]
+    fn test_holidays_file() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let calendar = TradingCalendar::load("files/nyse_holidays.csv")?;
+
+        // Thanksgiving and the half day after it
+        assert_eq!(calendar.session(new_york(28, 12, 0)), Session::Closed);
+        assert_eq!(calendar.session(new_york(29, 12, 0)), Session::Regular);
+        assert_eq!(calendar.session(new_york(29, 13, 30)), Session::Closed);
+        assert_eq!(calendar.minutes_to_close(new_york(29, 12, 45)), Some(15));
+
+        // Wednesday to Monday over Thanksgiving, only the half day is expected
+        let (from, to) = (
+            NaiveDate::from_ymd_opt(2024, 11, 27).unwrap(),
+            NaiveDate::from_ymd_opt(2024, 12, 2).unwrap(),
+        );
+        assert_eq!(calendar.sessions_between(from, to), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_holidays_file_covers_its_years() -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+    {
+        let calendar = TradingCalendar::load("files/nyse_holidays.csv")?;
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+
+        // As far back as the bundled ORCL bars
+        assert!(calendar.covers(date(1995, 1, 3)));
+        assert!(!calendar.is_trading_day(date(2001, 9, 12)));
+        assert!(!calendar.covers(date(1994, 12, 30)));
+        assert!(TradingCalendar::nyse().covers(date(1994, 12, 30)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_daily_bars() {
+        let calendar = TradingCalendar::nyse();
+        assert!(calendar.is_daily(new_york(26, 0, 0)));
+        assert!(!calendar.is_daily(new_york(26, 9, 30)));
+        assert_eq!(
+            calendar.session_date(new_york(26, 0, 0)),
+            NaiveDate::from_ymd_opt(2024, 11, 26).unwrap()
+        );
+    }
+}
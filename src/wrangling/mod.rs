@@ -1 +1,4 @@
 pub mod buffers;
+pub mod calendar;
+pub mod performance;
+pub mod quality;
@@ -0,0 +1,82 @@
+use chrono::Duration;
+
+/// Change of the last equity value against the first, as a fraction
+pub fn total_return(equity: &[f64]) -> f64 {
+    match (equity.first(), equity.last()) {
+        (Some(first), Some(last)) if *first > 0.0 => last / first - 1.0,
+        _ => 0.0,
+    }
+}
+
+/// Largest fall from a previous peak, as a positive fraction of the peak
+pub fn max_drawdown(equity: &[f64]) -> f64 {
+    let mut peak = f64::MIN;
+    let mut drawdown: f64 = 0.0;
+    for value in equity {
+        peak = peak.max(*value);
+        if peak > 0.0 {
+            drawdown = drawdown.max((peak - value) / peak);
+        }
+    }
+    drawdown
+}
+
+/// Annualized Sharpe ratio of the per-bar returns, without a risk-free rate
+///
+/// Returns 0 if there are fewer than two returns or they don't vary.
+pub fn sharpe(equity: &[f64], periods_per_year: f64) -> f64 {
+    let returns: Vec<f64> = equity
+        .windows(2)
+        .filter(|w| w[0] > 0.0)
+        .map(|w| w[1] / w[0] - 1.0)
+        .collect();
+    if returns.len() < 2 {
+        return 0.0;
+    }
+
+    let n = returns.len() as f64;
+    let mean = returns.iter().sum::<f64>() / n;
+    let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    if variance <= 0.0 {
+        return 0.0;
+    }
+    mean / variance.sqrt() * periods_per_year.sqrt()
+}
+
+/// Bars of `bar_size` in a trading year of 252 sessions of 6.5 hours
+pub fn periods_per_year(bar_size: Duration) -> f64 {
+    if bar_size >= Duration::days(1) {
+        252.0 / bar_size.num_days() as f64
+    } else {
+        252.0 * 390.0 / bar_size.num_minutes().max(1) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_total_return_and_drawdown() {
+        let equity = [100.0, 120.0, 90.0, 110.0];
+        assert!((total_return(&equity) - 0.1).abs() < 1e-9);
+        assert!((max_drawdown(&equity) - 0.25).abs() < 1e-9);
+        assert_eq!(max_drawdown(&[100.0, 110.0]), 0.0);
+        assert_eq!(total_return(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_sharpe() {
+        assert_eq!(sharpe(&[100.0, 101.0], 252.0), 0.0);
+        // Flat equity has no volatility
+        assert_eq!(sharpe(&[100.0, 100.0, 100.0], 252.0), 0.0);
+        assert!(sharpe(&[100.0, 102.0, 101.0, 104.0], 252.0) > 0.0);
+        assert!(sharpe(&[100.0, 98.0, 99.0, 96.0], 252.0) < 0.0);
+    }
+
+    #[test]
+    fn test_periods_per_year() {
+        assert_eq!(periods_per_year(Duration::days(1)), 252.0);
+        assert_eq!(periods_per_year(Duration::minutes(5)), 252.0 * 78.0);
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::error::CLIError;
+use crate::mocking::layout::CsvRow;
+use crate::wrangling::calendar::TradingCalendar;
+
+/// What to do with a bar that fails validation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QualityPolicy {
+    /// Leave the bar out
+    #[default]
+    Drop,
+    /// Replace bad prices with a flat bar at the previous close
+    ///
+    /// Duplicate and out-of-order bars cannot be repaired and are dropped.
+    ForwardFill,
+    /// Stop with an error
+    Halt,
+}
+
+/// Problem found in a bar
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Issue {
+    /// Zero, negative or non-finite price, or negative or non-finite volume
+    InvalidPrice,
+    /// High below low, or open or close outside the high-low range
+    InvalidRange,
+    /// Same timestamp as the previous bar of the symbol
+    Duplicate,
+    /// Older than the previous bar of the symbol
+    OutOfOrder,
+}
+
+/// Counts of the issues found in one run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QualityReport {
+    pub bars: usize,
+    pub invalid_prices: usize,
+    pub invalid_ranges: usize,
+    pub duplicates: usize,
+    pub out_of_order: usize,
+    /// Places where at least one session is missing between two bars
+    pub gaps: usize,
+    pub missing_sessions: usize,
+    pub dropped: usize,
+    pub filled: usize,
+}
+
+/// Checks bars per symbol before they reach the indicators
+#[derive(Debug)]
+pub struct BarValidator {
+    policy: QualityPolicy,
+    /// Last bar passed on per symbol
+    last: HashMap<String, CsvRow>,
+    report: QualityReport,
+    /// Sessions expected between two bars
+    calendar: TradingCalendar,
+}
+
+impl BarValidator {
+    pub fn new(policy: QualityPolicy) -> Self {
+        Self {
+            policy,
+            last: HashMap::new(),
+            report: QualityReport::default(),
+            calendar: TradingCalendar::nyse(),
+        }
+    }
+
+    /// Detect gaps against `calendar` instead of NYSE without holidays
+    pub fn with_calendar(mut self, calendar: TradingCalendar) -> Self {
+        self.calendar = calendar;
+        self
+    }
+
+    pub fn report(&self) -> &QualityReport {
+        &self.report
+    }
+
+    /// Validate `row`, returns the bar to pass on or `None` if it is dropped
+    ///
+    /// Fails only with `QualityPolicy::Halt`.
+    pub fn check(&mut self, row: CsvRow) -> Result<Option<CsvRow>, CLIError> {
+        self.report.bars += 1;
+        let last = self.last.get(&row.symbol);
+        let issue = match last {
+            Some(last) if row.timestamp == last.timestamp => Some(Issue::Duplicate),
+            Some(last) if row.timestamp < last.timestamp => Some(Issue::OutOfOrder),
+            _ => price_issue(&row),
+        };
+
+        let Some(issue) = issue else {
+            self.advance(&row);
+            return Ok(Some(row));
+        };
+        match issue {
+            Issue::InvalidPrice => self.report.invalid_prices += 1,
+            Issue::InvalidRange => self.report.invalid_ranges += 1,
+            Issue::Duplicate => self.report.duplicates += 1,
+            Issue::OutOfOrder => self.report.out_of_order += 1,
+        }
+
+        let repairable = matches!(issue, Issue::InvalidPrice | Issue::InvalidRange);
+        match (self.policy, last) {
+            (QualityPolicy::Halt, _) => Err(CLIError::BadBar(format!(
+                "{:?} in {} bar at {}",
+                issue, row.symbol, row.timestamp
+            ))),
+            (QualityPolicy::ForwardFill, Some(last)) if repairable => {
+                let filled = CsvRow {
+                    symbol: row.symbol,
+                    timestamp: row.timestamp,
+                    open: last.close,
+                    high: last.close,
+                    low: last.close,
+                    close: last.close,
+                    adj_close: last.adj_close,
+                    volume: 0.0,
+                };
+                self.report.filled += 1;
+                self.advance(&filled);
+                Ok(Some(filled))
+            }
+            _ => {
+                warn!(
+                    "Dropping {} bar at {}: {:?}",
+                    row.symbol, row.timestamp, issue
+                );
+                self.report.dropped += 1;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Count missing sessions since the last bar and remember `row`
+    fn advance(&mut self, row: &CsvRow) {
+        if let Some(last) = self.last.get(&row.symbol) {
+            let missing = self.calendar.sessions_between(
+                self.calendar.session_date(last.timestamp),
+                self.calendar.session_date(row.timestamp),
+            );
+            if missing > 0 {
+                warn!(
+                    "{} sessions of {} missing before {}",
+                    missing, row.symbol, row.timestamp
+                );
+                self.report.gaps += 1;
+                self.report.missing_sessions += missing;
+            }
+        }
+        self.last.insert(row.symbol.clone(), row.clone());
+    }
+}
+
+/// Price problems of a single bar
+pub fn price_issue(row: &CsvRow) -> Option<Issue> {
+    let prices = [row.open, row.high, row.low, row.close];
+    if prices.iter().any(|p| !p.is_finite() || *p <= 0.0)
+        || !row.volume.is_finite()
+        || row.volume < 0.0
+    {
+        return Some(Issue::InvalidPrice);
+    }
+    if row.high < row.low || row.high < row.open.max(row.close) || row.low > row.open.min(row.close)
+    {
+        return Some(Issue::InvalidRange);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn row(day: u32, close: f64) -> CsvRow {
+        CsvRow {
+            symbol: "TEST".to_string(),
+            // Mid-session in New York
+            timestamp: Utc.with_ymd_and_hms(2024, 1, day, 15, 0, 0).unwrap(),
+            open: close,
+            high: close + 1.0,
+            low: close - 1.0,
+            close,
+            adj_close: None,
+            volume: 100.0,
+        }
+    }
+
+    #[test]
+    fn test_price_issues() {
+        assert_eq!(price_issue(&row(2, 10.0)), None);
+        assert_eq!(price_issue(&row(2, 0.0)), Some(Issue::InvalidPrice));
+        assert_eq!(
+            price_issue(&CsvRow {
+                close: f64::NAN,
+                ..row(2, 10.0)
+            }),
+            Some(Issue::InvalidPrice)
+        );
+        assert_eq!(
+            price_issue(&CsvRow {
+                volume: -1.0,
+                ..row(2, 10.0)
+            }),
+            Some(Issue::InvalidPrice)
+        );
+        assert_eq!(
+            price_issue(&CsvRow {
+                high: 8.0,
+                ..row(2, 10.0)
+            }),
+            Some(Issue::InvalidRange)
+        );
+    }
+
+    #[test]
+    fn test_drop_policy() {
+        let mut validator = BarValidator::new(QualityPolicy::Drop);
+        assert!(validator.check(row(3, 10.0)).unwrap().is_some());
+        assert!(validator.check(row(3, 11.0)).unwrap().is_none());
+        assert!(validator.check(row(2, 11.0)).unwrap().is_none());
+        assert!(validator.check(row(4, -1.0)).unwrap().is_none());
+
+        let report = validator.report();
+        assert_eq!(report.bars, 4);
+        assert_eq!(report.duplicates, 1);
+        assert_eq!(report.out_of_order, 1);
+        assert_eq!(report.invalid_prices, 1);
+        assert_eq!(report.dropped, 3);
+    }
+
+    #[test]
+    fn test_forward_fill_policy() {
+        let mut validator = BarValidator::new(QualityPolicy::ForwardFill);
+        validator.check(row(2, 10.0)).unwrap();
+        let filled = validator
+            .check(CsvRow {
+                close: f64::NAN,
+                ..row(3, 10.0)
+            })
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(filled.close, 10.0);
+        assert_eq!(filled.high, 10.0);
+        assert_eq!(filled.volume, 0.0);
+        assert_eq!(validator.report().filled, 1);
+    }
+
+    #[test]
+    fn test_halt_policy() {
+        let mut validator = BarValidator::new(QualityPolicy::Halt);
+        validator.check(row(2, 10.0)).unwrap();
+        assert!(validator.check(row(2, 10.0)).is_err());
+    }
+
+    #[test]
+    fn test_gaps() {
+        let mut validator = BarValidator::new(QualityPolicy::Drop);
+        // 2024-01-05 is a Friday, the weekend is no gap
+        validator.check(row(5, 10.0)).unwrap();
+        validator.check(row(8, 10.0)).unwrap();
+        assert_eq!(validator.report().gaps, 0);
+
+        // Tuesday and Wednesday are missing
+        validator.check(row(11, 10.0)).unwrap();
+        assert_eq!(validator.report().gaps, 1);
+        assert_eq!(validator.report().missing_sessions, 2);
+    }
+
+    #[test]
+    fn test_gaps_skip_holidays() {
+        let calendar = TradingCalendar::load("files/nyse_holidays.csv").unwrap();
+        let mut validator = BarValidator::new(QualityPolicy::Drop).with_calendar(calendar);
+        // Martin Luther King Jr. Day on Monday 2024-01-15
+        validator.check(row(12, 10.0)).unwrap();
+        validator.check(row(16, 10.0)).unwrap();
+        assert_eq!(validator.report().gaps, 0);
+    }
+}